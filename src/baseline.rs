@@ -0,0 +1,96 @@
+//! Baseline of known messages, which are not reported again.
+//!
+//! Messages are identified by the file, lint, text and the line of code
+//! they point to (but not its number), so the baseline survives
+//! unrelated edits of the file.
+
+use failure::{Error, ResultExt};
+use serde_json;
+
+use std::collections::BTreeSet as Set;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lint::Lint;
+use Location;
+
+/// Baseline file name, in the root of the analyzed directory
+pub const FILE: &str = "shelly-baseline.json";
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// Path relative to the analyzed directory, with forward slashes
+    pub file: String,
+
+    /// Lint slug, eg. `unknown-functions`
+    pub lint: String,
+
+    pub message: String,
+
+    /// Trimmed first line of code of the message's span, if it has one
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub line: String,
+}
+
+impl Entry {
+    pub fn new(root: &Path, lint: Lint, message: &str, location: &Location) -> Entry {
+        let file = location.file.strip_prefix(root).unwrap_or(&location.file);
+        let file = file.components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let line = location.span
+            .map(|span| span.start.find_line(&location.source).trim().to_owned())
+            .unwrap_or_default();
+
+        Entry {
+            file,
+            lint: lint.slug().to_owned(),
+            message: message.to_owned(),
+            line,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Baseline {
+    /// Analyzed directory, which entries' paths are relative to
+    root: PathBuf,
+
+    entries: Set<Entry>,
+}
+
+impl Baseline {
+    /// Loads the baseline of a directory. It's empty if there's no baseline file.
+    pub fn load(root: &Path) -> Result<Baseline, Error> {
+        let path = root.join(FILE);
+        let entries = if path.exists() {
+            let contents = fs::read_to_string(&path).with_context(|_| format!("Reading {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|_| format!("Parsing {}", path.display()))?
+        } else {
+            Set::new()
+        };
+
+        Ok(Baseline { root: root.to_owned(), entries })
+    }
+
+    pub fn contains(&self, lint: Lint, message: &str, location: &Location) -> bool {
+        !self.entries.is_empty() && self.entries.contains(&Entry::new(&self.root, lint, message, location))
+    }
+
+    pub fn insert(&mut self, lint: Lint, message: &str, location: &Location) {
+        let entry = Entry::new(&self.root, lint, message, location);
+        self.entries.insert(entry);
+    }
+
+    /// Writes the baseline file, sorted, so it diffs nicely
+    pub fn save(&self) -> Result<(), Error> {
+        let path = self.root.join(FILE);
+        let entries: Vec<&Entry> = self.entries.iter().collect();
+        fs::write(&path, serde_json::to_string_pretty(&entries)? + "\n")
+            .with_context(|_| format!("Writing {}", path.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+//! Builtin commandlets and bundled command lists of popular modules
+
+use failure::{Error, ResultExt};
+use unicase::{self, UniCase};
+
+use std::collections::BTreeMap as Map;
+use std::collections::BTreeSet as Set;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use lint::Emitter;
+use lint::Lint;
+use syntax::{Item, Span};
+use ConfigFile;
+
+lazy_static! {
+    /// Commandlets shipped with shelly
+    pub static ref SHIPPED: Set<Item<UniCase<&'static str>>> =
+        include_str!("builtins.txt")
+        .split_whitespace()
+        .chain(include_str!("extras.txt").split_whitespace())
+        .map(UniCase::new)
+        .map(Item::function)
+        .collect();
+}
+
+/// Version of PowerShell the scripts are run with, eg. `5.1` or `7.2`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct PsVersion {
+    major: u32,
+    minor: u32,
+}
+
+impl PsVersion {
+    /// Whether it's PowerShell (Core) 6 or later, rather than Windows PowerShell
+    pub fn is_core(self) -> bool {
+        self.major >= 6
+    }
+}
+
+impl FromStr for PsVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<PsVersion, Error> {
+        // Only the major and minor versions matter, eg. `5.1.19041` is `5.1`
+        let mut parts = s.split('.');
+        let major = parts.next().unwrap_or("").parse();
+        let minor = parts.next().map_or(Ok(0), |minor| minor.parse());
+        let rest_valid = parts.all(|part| part.parse::<u32>().is_ok());
+
+        match (major, minor) {
+            (Ok(major), Ok(minor)) if rest_valid => Ok(PsVersion { major, minor }),
+            _ => bail!("Invalid PowerShell version `{}`, expected eg. `5.1` or `7.2`", s),
+        }
+    }
+}
+
+impl fmt::Display for PsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+lazy_static! {
+    /// Shipped commandlets missing in PowerShell 6 and later, with their replacements
+    static ref CORE_REMOVED: Map<UniCase<&'static str>, Option<&'static str>> = entries(include_str!("core_removed.txt"))
+        .map(|(name, replacement)| (UniCase::new(name), replacement))
+        .collect();
+
+    /// Commandlets added in PowerShell 6 and later, with the version introducing them
+    static ref CORE_ADDED: Vec<(&'static str, PsVersion)> = entries(include_str!("core_added.txt"))
+        .map(|(name, version)| (name, version.unwrap().parse().unwrap()))
+        .collect();
+}
+
+/// Lines of a data file as a first word and an optional second one, skipping comments
+fn entries(data: &'static str) -> impl Iterator<Item=(&'static str, Option<&'static str>)> {
+    data.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            Some((words.next()?, words.next()))
+        })
+}
+
+/// If a shipped commandlet is missing in a given version, returns its replacement, if any
+pub fn removed_in(version: PsVersion, command: &str) -> Option<Option<&'static str>> {
+    if !version.is_core() {
+        return None;
+    }
+    CORE_REMOVED.get(&UniCase::new(command)).cloned()
+}
+
+/// Commandlets not shipped with Windows PowerShell, available in a given version
+pub fn added_in(version: PsVersion) -> impl Iterator<Item=&'static str> {
+    CORE_ADDED.iter().filter(move |&&(_, added)| added <= version).map(|&(name, _)| name)
+}
+
+lazy_static! {
+    /// Built-in aliases with the commandlets they resolve to
+    static ref ALIASES: Map<UniCase<&'static str>, &'static str> = entries(include_str!("aliases.txt"))
+        .map(|(alias, command)| (UniCase::new(alias), command.unwrap()))
+        .collect();
+}
+
+/// Commandlet a built-in alias resolves to, eg. `Get-ChildItem` for `gci`
+pub fn alias_target(name: &str) -> Option<&'static str> {
+    ALIASES.get(&UniCase::new(name)).cloned()
+}
+
+/// Name of project's file with additional builtins
+pub const LOCKFILE: &str = "builtins.lock";
+
+/// Loads commands from project's `builtins.lock`, if it exists.
+///
+/// The file contains one command per line, `#` starts a comment.
+/// Conflicting entries – differing from shipped builtins
+/// or from each other only in letter casing – are reported.
+pub fn load_lockfile(root: &Path, emitter: &mut Emitter) -> Result<Vec<String>, Error> {
+    let path = root.join(LOCKFILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let source: Rc<str> = Rc::from(fs::read_to_string(&path)?);
+
+    let shipped: Map<UniCase<&str>, &str> = SHIPPED
+        .iter()
+        .map(|item| (item.name, *item.name))
+        .collect();
+
+    let mut commands = Vec::new();
+    let mut seen: Map<UniCase<&str>, &str> = Map::new();
+
+    for (line_no, command) in listed_commands(&source) {
+        let conflict = seen.get(&UniCase::new(command))
+            .map(|&previous| (previous, "an earlier entry"))
+            .or_else(|| shipped.get(&UniCase::new(command)).map(|&builtin| (builtin, "a shipped builtin")));
+
+        match conflict {
+            Some((other, _)) if other == command => continue,
+            Some((other, what)) => {
+                Span::from_fragment(line_no, command, &source)
+                    .in_file_source(&path, Rc::clone(&source))
+                    .lint(Lint::BuiltinsConflicts, "conflicting builtin")
+                    .what(command)
+                    .note(format!("Differs in letter casing from {} `{}`", what, other))
+                    .emit(emitter);
+                continue;
+            }
+            None => (),
+        }
+
+        seen.insert(UniCase::new(command), command);
+        commands.push(command.to_owned());
+    }
+
+    Ok(commands)
+}
+
+/// Loads commands from files listed in `extras.builtins_files`,
+/// relative to the directory of the config.
+///
+/// The files have the same format as `builtins.lock`.
+pub fn load_builtins_files(config_file: &ConfigFile, dir: &Path) -> Result<Vec<String>, Error> {
+    let mut commands = Vec::new();
+
+    for file in config_file.extras.iter().flat_map(|extras| &extras.builtins_files).flatten() {
+        let path = dir.join(file);
+        let source = fs::read_to_string(&path)
+            .with_context(|_| format!("Reading builtins file {}", path.display()))?;
+
+        commands.extend(listed_commands(&source).map(|(_, command)| command.to_owned()));
+    }
+
+    Ok(commands)
+}
+
+/// Commands listed in a file in the `builtins.lock` format, with their line numbers
+fn listed_commands(source: &str) -> impl Iterator<Item=(u32, &str)> {
+    source.lines()
+        .zip(1..)
+        .map(|(line, line_no)| (line_no, line.split('#').next().unwrap().trim()))
+        .filter(|(_, command)| !command.is_empty())
+}
+
+/// A bundled command list of a module
+struct KnownModule {
+    name: &'static str,
+    version: Option<&'static str>,
+
+    /// One command per line. Lines like `# Az.Accounts` start
+    /// a list of a submodule, which can be requested on its own.
+    commands: &'static str,
+}
+
+impl KnownModule {
+    fn commands(&self) -> impl Iterator<Item=&'static str> {
+        self.commands.lines().filter(|line| !line.starts_with('#')).flat_map(str::split_whitespace)
+    }
+
+    /// Commands of a submodule, if the module has one with a given name
+    fn submodule_commands(&self, name: &str) -> Option<impl Iterator<Item=&'static str>> {
+        let header = self.commands.lines()
+            .position(|line| line.strip_prefix("# ").is_some_and(|submodule| unicase::eq(submodule, name)))?;
+        let commands = self.commands.lines()
+            .skip(header + 1)
+            .take_while(|line| !line.starts_with('#'))
+            .flat_map(str::split_whitespace);
+        Some(commands)
+    }
+
+    fn submodules(&self) -> impl Iterator<Item=&'static str> {
+        self.commands.lines().filter_map(|line| line.strip_prefix("# "))
+    }
+}
+
+/// Versions of the same module should be sorted,
+/// the last one is used when no version is requested.
+const KNOWN_MODULES: &[KnownModule] = &[
+    KnownModule {
+        name: "Pester",
+        version: Some("4"),
+        commands: include_str!("known_modules/Pester-4.txt"),
+    },
+    KnownModule {
+        name: "Pester",
+        version: Some("5"),
+        commands: include_str!("known_modules/Pester-5.txt"),
+    },
+    KnownModule {
+        name: "Az",
+        version: None,
+        commands: include_str!("known_modules/Az.txt"),
+    },
+    KnownModule {
+        name: "PSReadLine",
+        version: None,
+        commands: include_str!("known_modules/PSReadLine.txt"),
+    },
+    KnownModule {
+        name: "ActiveDirectory",
+        version: None,
+        commands: include_str!("known_modules/ActiveDirectory.txt"),
+    },
+];
+
+/// Returns commands of a known module, given as `Name`, `Name@Version`
+/// or a name of a submodule, eg. `Az.Accounts`.
+pub fn known_module_commands(spec: &str) -> Result<Box<dyn Iterator<Item=&'static str>>, Error> {
+    let (name, version) = match spec.find('@') {
+        Some(at) => (&spec[..at], Some(&spec[at + 1..])),
+        None     => (spec, None),
+    };
+
+    let module = KNOWN_MODULES
+        .iter()
+        .rev()
+        .filter(|module| unicase::eq(module.name, name))
+        .find(|module| version.is_none() || module.version == version);
+
+    if let Some(module) = module {
+        return Ok(Box::new(module.commands()));
+    }
+
+    if version.is_none() {
+        for module in KNOWN_MODULES.iter().rev() {
+            if let Some(commands) = module.submodule_commands(name) {
+                return Ok(Box::new(commands));
+            }
+        }
+    }
+
+    let available: Vec<_> = KNOWN_MODULES
+        .iter()
+        .map(|module| match module.version {
+            Some(version) => format!("{}@{}", module.name, version),
+            None          => module.name.to_owned(),
+        })
+        .collect();
+    let submodules: Vec<_> = KNOWN_MODULES.iter().flat_map(KnownModule::submodules).collect();
+
+    bail!(
+        "Unknown module `{}`. Available modules: {} (or their parts: {})",
+        spec,
+        available.join(", "),
+        submodules.join(", "),
+    )
+}
+
+/// Names of the known modules (in any version) providing a command
+pub fn modules_providing(command: &str) -> Vec<&'static str> {
+    let mut names: Vec<_> = KNOWN_MODULES
+        .iter()
+        .filter(|module| module.commands().any(|known| unicase::eq(known, command)))
+        .map(|module| module.name)
+        .collect();
+    names.dedup();
+    names
+}
+
+#[test]
+fn test_versions() {
+    let version = |s: &str| s.parse::<PsVersion>().unwrap();
+
+    assert_eq!(version("7").to_string(), "7.0");
+    assert!(version("7.2") > version("7.0"));
+    assert!("7.x".parse::<PsVersion>().is_err());
+    assert_eq!(version("5.1.19041"), version("5.1"));
+
+    for (name, _) in CORE_REMOVED.iter() {
+        assert!(SHIPPED.contains(&Item::function(*name)), "{} is not shipped", name);
+    }
+    for &(name, _) in CORE_ADDED.iter() {
+        assert!(!SHIPPED.contains(&Item::function(UniCase::new(name))), "{} is already shipped", name);
+    }
+
+    assert_eq!(removed_in(version("7.2"), "get-wmiobject"), Some(Some("Get-CimInstance")));
+    assert_eq!(removed_in(version("7.2"), "Add-Computer"), Some(None));
+    assert_eq!(removed_in(version("5.1"), "Get-WmiObject"), None);
+    assert!(added_in(version("7.2")).any(|name| name == "Get-Error"));
+    assert!(!added_in(version("6.2")).any(|name| name == "Get-Error"));
+    assert_eq!(added_in(version("5.1")).count(), 0);
+}
+
+#[test]
+fn test_aliases() {
+    for (alias, command) in ALIASES.iter() {
+        assert!(!SHIPPED.contains(&Item::function(*alias)), "{} is shipped", alias);
+        assert!(SHIPPED.contains(&Item::function(UniCase::new(*command))), "{} is not shipped", command);
+    }
+
+    assert_eq!(alias_target("GCI"), Some("Get-ChildItem"));
+    assert_eq!(alias_target("%"), Some("ForEach-Object"));
+    assert_eq!(alias_target("Get-ChildItem"), None);
+}
+
+#[test]
+fn test_known_modules() {
+    let has = |spec, command| known_module_commands(spec).unwrap().any(|c| c == command);
+
+    assert!(has("Pester@5", "BeforeDiscovery"));
+    assert!(!has("Pester@4", "BeforeDiscovery"));
+    assert!(has("Pester", "BeforeDiscovery"));
+    assert!(has("psreadline", "Set-PSReadLineOption"));
+    assert!(has("Az", "Connect-AzAccount"));
+    assert!(has("Az.Accounts", "Connect-AzAccount"));
+    assert!(!has("Az.Accounts", "Get-AzVM"));
+    assert!(has("az.compute", "Get-AzVM"));
+
+    assert!(known_module_commands("Pester@3").is_err());
+    assert!(known_module_commands("Az@1").is_err());
+    assert!(known_module_commands("Frobnicator").is_err());
+    assert!(known_module_commands("Az.Accounts@2").is_err());
+    assert!(known_module_commands("Get-AzVM").is_err());
+    assert!(known_module_commands("Describe").is_err());
+
+    assert_eq!(modules_providing("describe"), ["Pester"]);
+    assert_eq!(modules_providing("Get-AzVM"), ["Az"]);
+    assert!(modules_providing("Get-Frobnicator").is_empty());
+}
@@ -1,8 +1,22 @@
 use std::collections::BTreeMap as Map;
+use std::fmt;
 use std::str::FromStr;
 
+use failure::{Error, ResultExt};
+use serde::de::DeserializeOwned;
 use toml;
 
+use imports;
+use lint::{self, Lint};
+use preprocess;
+use scope;
+use testnames;
+use todos;
+use RunOpt;
+
+/// Substrings of skipped paths, when `[traversal] skip` is not set
+pub(crate) const DEFAULT_SKIP: &[&str] = &["_Old_Tests"];
+
 /// ConfigFile describes a TOML-structure of a shelly.toml config.
 ///
 /// Each module should use a separate config with proper types.
@@ -13,15 +27,467 @@ pub struct ConfigFile {
     /// Lint levels overrides
     pub(crate) levels: Option<Map<String, String>>,
 
+    /// Lint levels for files matching globs
+    pub(crate) overrides: Option<Vec<ConfigFileOverride>>,
+
+    /// Promote all warnings to errors
+    pub(crate) deny_warnings: Option<bool>,
+
+    /// Maximal number of messages of a single lint in a single file
+    pub(crate) max_per_lint: Option<usize>,
+
+    /// Environment variables assumed to be always set
+    pub(crate) required_env: Option<Vec<String>>,
+
+    /// Maximal line length checked by `long-lines`.
+    /// Deprecated, `[lint-options.long-lines] max` takes precedence.
+    pub(crate) max_line_length: Option<usize>,
+
+    /// Version of PowerShell the scripts are run with, eg. `"5.1"` or `"7.2"`
+    pub(crate) target: Option<String>,
+
+    /// Options of individual lints, by slug, eg. `[lint-options.invalid-testname-characters]`
+    #[serde(rename = "lint-options")]
+    pub(crate) lint_options: Option<Map<String, toml::Value>>,
+
+    /// Which files are analyzed
+    pub(crate) traversal: Option<ConfigFileTraversal>,
+
+    /// Directories outside of the analyzed tree
+    pub(crate) paths: Option<ConfigFilePaths>,
+
+    /// Which files are reported on
+    pub(crate) files: Option<ConfigFileFiles>,
+
     /// Custom commandlets that are assumed to exist
     /// (in addition to the ones defined in builtins.txt)
     pub(crate) extras: Option<ConfigFileExtras>,
+
+    /// File naming conventions
+    pub(crate) filenames: Option<ConfigFileFilenames>,
+
+    /// Testname conventions
+    pub(crate) testnames: Option<ConfigFileTestnames>,
+
+    /// Custom regexes producing definitions or usages
+    pub(crate) patterns: Option<Vec<ConfigFilePattern>>,
+
+    /// Deprecated functions with replacement hints
+    pub(crate) deprecated: Option<Map<String, String>>,
+
+    /// Banned functions with optional replacement hints
+    pub(crate) banned: Option<Map<String, String>>,
+
+    /// Lints about imports
+    pub(crate) imports: Option<ConfigFileImports>,
+
+    /// Scripts run directly rather than imported
+    pub(crate) entrypoints: Option<ConfigFileEntrypoints>,
+
+    /// Strict mode checks
+    pub(crate) strictness: Option<ConfigFileStrictness>,
+
+    /// Custom requirements, checked similarly to strict mode
+    pub(crate) requirements: Option<Vec<ConfigFileRequirement>>,
+
+    /// Markers of comments reported by `todo-comments`
+    pub(crate) todos: Option<ConfigFileTodos>,
 }
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct ConfigFileExtras {
     pub(crate) cmdlets: Option<Vec<String>>,
+
+    /// Modules with bundled command lists, eg. `Pester@5` or `Az.Accounts`
+    #[serde(alias = "modules")]
+    pub(crate) known_modules: Option<Vec<String>>,
+
+    /// Files whose definitions are in scope of every file
+    pub(crate) preludes: Option<Vec<String>>,
+
+    /// Files with more commandlets, one per line, as in `builtins.lock`
+    pub(crate) builtins_files: Option<Vec<String>>,
+
+    /// Classes defined outside of the analyzed files, eg. in compiled modules
+    pub(crate) classes: Option<Vec<String>>,
+
+    /// Global variables assumed to be set
+    pub(crate) variables: Option<Vec<String>>,
+
+    /// External tools which can be invoked, eg. `git` (with or without `.exe`)
+    pub(crate) executables: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileTraversal {
+    /// Skip directories starting with a dot
+    pub(crate) skip_hidden: Option<bool>,
+
+    /// Maximal depth of analyzed files, 1 meaning only the files in the root
+    pub(crate) max_depth: Option<usize>,
+
+    /// Skip paths containing any of these substrings, `["_Old_Tests"]` by default
+    pub(crate) skip: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFilePaths {
+    /// Directories with scripts imported by the analyzed files,
+    /// relative to the config's directory. Their files are parsed
+    /// when imported, but never reported on.
+    pub(crate) external_roots: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileOverride {
+    /// Glob of paths, relative to the directory of the config
+    pub(crate) path: String,
+
+    /// Lint levels overriding `[levels]` in matching files
+    pub(crate) levels: Map<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileFiles {
+    /// Glob patterns of paths, relative to the analyzed directory.
+    /// Matching files are still parsed when imported, but not reported on.
+    pub(crate) exclude: Option<Vec<String>>,
+
+    /// Extensions of analyzed files, all of `ps1`, `psm1` and `psd1` by default.
+    /// Other files are still parsed when imported, but not reported on.
+    pub(crate) extensions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileFilenames {
+    pub(crate) pattern: Option<String>,
+    pub(crate) tests_pattern: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileTestnames {
+    pub(crate) pattern: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileImports {
+    /// Regexes matching names of test files
+    pub(crate) test_patterns: Option<Vec<String>>,
+
+    /// Directories of environment variables used in imports,
+    /// eg. `$env:BUILD_ROOT`, relative to the config's directory
+    pub(crate) env_roots: Option<Map<String, String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileEntrypoints {
+    /// Glob patterns of paths, relative to the analyzed directory
+    pub(crate) paths: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileTodos {
+    pub(crate) markers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileStrictness {
+    pub(crate) scope: Option<StrictnessScope>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFileRequirement {
+    pub(crate) name: String,
+
+    /// Commandlets meeting the requirement
+    pub(crate) injected_by: Vec<String>,
+
+    pub(crate) scope: Option<StrictnessScope>,
+    pub(crate) message: Option<String>,
+}
+
+/// Files that are required to meet a requirement, eg. run in strict mode
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StrictnessScope {
+    /// Files not imported by any other file
+    Roots,
+
+    /// All the files, including imported ones
+    All,
+}
+
+impl fmt::Display for StrictnessScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StrictnessScope::Roots => "roots",
+            StrictnessScope::All   => "all",
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFilePattern {
+    pub(crate) regex: String,
+    pub(crate) kind: PatternKind,
+    pub(crate) item: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PatternKind {
+    Definition,
+    Usage,
+}
+
+/// What set a configuration value
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Source {
+    Default,
+    ConfigFile,
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Source::Default     => "default",
+            Source::ConfigFile  => "config file",
+            Source::CommandLine => "command line",
+        })
+    }
+}
+
+/// A single value of the effective configuration, as printed by `shelly config show`
+#[derive(Debug)]
+pub struct ConfigValue {
+    /// Dotted key, eg. `levels.unknown-functions`
+    pub key: String,
+    pub value: String,
+    pub source: Source,
+}
+
+/// Resolves the configuration after applying the command line options
+pub(crate) fn effective_values(config_file: &ConfigFile, lint_config: &lint::Config, run_opt: &RunOpt)
+    -> Vec<ConfigValue>
+{
+    // Destructured, so that new options can't be forgotten here
+    let ConfigFile {
+        levels: _,
+        deny_warnings: _,
+        max_line_length: _,
+        lint_options: _,
+        overrides,
+        max_per_lint,
+        required_env,
+        target,
+        traversal,
+        paths,
+        files,
+        extras,
+        filenames,
+        testnames,
+        patterns,
+        deprecated,
+        banned,
+        imports,
+        entrypoints,
+        strictness,
+        requirements,
+        todos,
+    } = config_file;
+
+    let mut values = Vec::new();
+    let mut push = |key: &str, value: String, source| {
+        values.push(ConfigValue { key: key.to_owned(), value, source });
+    };
+
+    let from_file = |is_set: bool| if is_set { Source::ConfigFile } else { Source::Default };
+    let list = |items: Option<&Vec<String>>| format!("[{}]", items.map_or(String::new(), |items| items.join(", ")));
+    let defaults = |items: &[&str]| format!("[{}]", items.join(", "));
+    let optional = |value: Option<&String>, default: &str| value.map_or(default.to_owned(), String::clone);
+
+    for lint in Lint::lints() {
+        let level = format!("{:?}", lint.level(lint_config)).to_lowercase();
+        push(&format!("levels.{}", lint.slug()), level, lint.level_source(lint_config));
+    }
+
+    for (i, path_override) in overrides.iter().flatten().enumerate() {
+        push(&format!("overrides[{}].path", i), path_override.path.clone(), Source::ConfigFile);
+        for (lint, level) in &path_override.levels {
+            push(&format!("overrides[{}].levels.{}", i, lint), level.clone(), Source::ConfigFile);
+        }
+    }
+
+    let (deny_warnings, source) = lint_config.deny_warnings();
+    push("deny_warnings", deny_warnings.to_string(), source);
+
+    let (max_per_lint, source) = match (run_opt.max_per_lint, max_per_lint) {
+        (Some(max), _) => (max.to_string(), Source::CommandLine),
+        (None, Some(max)) => (max.to_string(), Source::ConfigFile),
+        (None, None) => ("unlimited".to_owned(), Source::Default),
+    };
+    push("max_per_lint", max_per_lint, source);
+
+    let (target, source) = match (&run_opt.ps_version, target) {
+        (Some(version), _) => (version.clone(), Source::CommandLine),
+        (None, Some(version)) => (version.clone(), Source::ConfigFile),
+        (None, None) => ("not set (any version)".to_owned(), Source::Default),
+    };
+    push("target", target, source);
+
+    let characters = config_file.lint_options::<testnames::InvalidCharactersOptions>(Lint::InvalidTestnameCharacters)
+        .unwrap_or_default()
+        .and_then(|options| options.characters);
+    let value = format!("{:?}", characters.as_deref().unwrap_or(testnames::DEFAULT_INVALID_CHARACTERS));
+    push("lint-options.invalid-testname-characters.characters", value, from_file(characters.is_some()));
+
+    let allowed = config_file.lint_options::<scope::AliasesOptions>(Lint::AvoidAliases)
+        .unwrap_or_default()
+        .and_then(|options| options.allowed);
+    push("lint-options.avoid-aliases.allowed", list(allowed.as_ref()), from_file(allowed.is_some()));
+
+    let max_line_length = preprocess::max_line_length(config_file).unwrap_or_default();
+    let value = max_line_length.unwrap_or(preprocess::DEFAULT_MAX_LINE_LENGTH).to_string();
+    push("lint-options.long-lines.max", value, from_file(max_line_length.is_some()));
+
+    let value = match required_env {
+        Some(required) => list(Some(required)),
+        None => "not set (check disabled)".to_owned(),
+    };
+    push("required_env", value, from_file(required_env.is_some()));
+
+    let traversal = traversal.as_ref();
+    let skip_hidden = traversal.and_then(|traversal| traversal.skip_hidden);
+    push("traversal.skip_hidden", skip_hidden.unwrap_or(false).to_string(), from_file(skip_hidden.is_some()));
+    let max_depth = traversal.and_then(|traversal| traversal.max_depth);
+    let depth = max_depth.map_or("unlimited".to_owned(), |depth| depth.to_string());
+    push("traversal.max_depth", depth, from_file(max_depth.is_some()));
+    let skip = traversal.and_then(|traversal| traversal.skip.as_ref());
+    push("traversal.skip", skip.map_or(defaults(DEFAULT_SKIP), |skip| list(Some(skip))), from_file(skip.is_some()));
+
+    let external_roots = paths.as_ref().and_then(|paths| paths.external_roots.as_ref());
+    push("paths.external_roots", list(external_roots), from_file(external_roots.is_some()));
+
+    let config_excludes = files.as_ref().and_then(|files| files.exclude.as_ref());
+    let excludes: Vec<String> = config_excludes.into_iter().flatten().chain(&run_opt.exclude).cloned().collect();
+    let source = if !run_opt.exclude.is_empty() { Source::CommandLine } else { from_file(config_excludes.is_some()) };
+    push("files.exclude", list(Some(&excludes)), source);
+
+    let extensions = files.as_ref().and_then(|files| files.extensions.as_ref());
+    let value = extensions.map_or("[ps1, psm1, psd1]".to_owned(), |extensions| list(Some(extensions)));
+    push("files.extensions", value, from_file(extensions.is_some()));
+
+    let extras = extras.as_ref();
+    for &(key, items) in &[
+        ("extras.cmdlets", extras.and_then(|extras| extras.cmdlets.as_ref())),
+        ("extras.known_modules", extras.and_then(|extras| extras.known_modules.as_ref())),
+        ("extras.preludes", extras.and_then(|extras| extras.preludes.as_ref())),
+        ("extras.builtins_files", extras.and_then(|extras| extras.builtins_files.as_ref())),
+        ("extras.classes", extras.and_then(|extras| extras.classes.as_ref())),
+        ("extras.variables", extras.and_then(|extras| extras.variables.as_ref())),
+        ("extras.executables", extras.and_then(|extras| extras.executables.as_ref())),
+    ] {
+        push(key, list(items), from_file(items.is_some()));
+    }
+
+    for &(key, pattern) in &[
+        ("filenames.pattern", filenames.as_ref().and_then(|filenames| filenames.pattern.as_ref())),
+        ("filenames.tests_pattern", filenames.as_ref().and_then(|filenames| filenames.tests_pattern.as_ref())),
+        ("testnames.pattern", testnames.as_ref().and_then(|testnames| testnames.pattern.as_ref())),
+    ] {
+        push(key, optional(pattern, "not set (check disabled)"), from_file(pattern.is_some()));
+    }
+
+    for (i, pattern) in patterns.iter().flatten().enumerate() {
+        push(&format!("patterns[{}].regex", i), pattern.regex.clone(), Source::ConfigFile);
+        push(&format!("patterns[{}].kind", i), format!("{:?}", pattern.kind).to_lowercase(), Source::ConfigFile);
+        push(&format!("patterns[{}].item", i), optional(pattern.item.as_ref(), "function"), from_file(pattern.item.is_some()));
+    }
+
+    for &(key, functions) in &[("deprecated", deprecated), ("banned", banned)] {
+        match functions {
+            Some(functions) => for (function, hint) in functions {
+                push(&format!("{}.{}", key, function), format!("{:?}", hint), Source::ConfigFile);
+            },
+            None => push(key, "{}".to_owned(), Source::Default),
+        }
+    }
+
+    let imports = imports.as_ref();
+    let test_patterns = imports.and_then(|imports| imports.test_patterns.as_ref());
+    let value = test_patterns.map_or(defaults(imports::DEFAULT_TEST_PATTERNS), |patterns| list(Some(patterns)));
+    push("imports.test_patterns", value, from_file(test_patterns.is_some()));
+    match imports.and_then(|imports| imports.env_roots.as_ref()) {
+        Some(env_roots) => for (variable, dir) in env_roots {
+            push(&format!("imports.env_roots.{}", variable), dir.clone(), Source::ConfigFile);
+        },
+        None => push("imports.env_roots", "{}".to_owned(), Source::Default),
+    }
+
+    let entrypoints = entrypoints.as_ref().and_then(|entrypoints| entrypoints.paths.as_ref());
+    let value = entrypoints.map_or("not set (files not imported by any other file)".to_owned(), |paths| list(Some(paths)));
+    push("entrypoints.paths", value, from_file(entrypoints.is_some()));
+
+    let scope = strictness.as_ref().and_then(|strictness| strictness.scope);
+    push("strictness.scope", scope.unwrap_or(StrictnessScope::Roots).to_string(), from_file(scope.is_some()));
+
+    for (i, requirement) in requirements.iter().flatten().enumerate() {
+        push(&format!("requirements[{}].name", i), requirement.name.clone(), Source::ConfigFile);
+        push(&format!("requirements[{}].injected_by", i), list(Some(&requirement.injected_by)), Source::ConfigFile);
+        let scope = requirement.scope.unwrap_or(StrictnessScope::Roots).to_string();
+        push(&format!("requirements[{}].scope", i), scope, from_file(requirement.scope.is_some()));
+        let message = requirement.message.clone().unwrap_or_else(|| format!("requirement `{}` not met for this file", requirement.name));
+        push(&format!("requirements[{}].message", i), message, from_file(requirement.message.is_some()));
+    }
+
+    let markers = todos.as_ref().and_then(|todos| todos.markers.as_ref());
+    push("todos.markers", markers.map_or(defaults(todos::DEFAULT_MARKERS), |markers| list(Some(markers))), from_file(markers.is_some()));
+
+    values
+}
+
+/// Lints which can be configured in `[lint-options]`
+const CONFIGURABLE_LINTS: &[Lint] = &[Lint::InvalidTestnameCharacters, Lint::AvoidAliases, Lint::LongLines];
+
+impl ConfigFile {
+    /// Options of a lint from `[lint-options.<slug>]`, if there are any.
+    /// `T` should deny unknown fields, so typos are reported.
+    pub(crate) fn lint_options<T: DeserializeOwned>(&self, lint: Lint) -> Result<Option<T>, Error> {
+        let options = match self.lint_options.as_ref().and_then(|options| options.get(lint.slug())) {
+            Some(options) => options.clone(),
+            None => return Ok(None),
+        };
+
+        let options = options.try_into()
+            .with_context(|_| format!("Invalid options of {}", lint.slug()))?;
+        Ok(Some(options))
+    }
+
+    /// Checks that `[lint-options]` has only tables of lints having options
+    pub(crate) fn check_lint_options(&self) -> Result<(), Error> {
+        for name in self.lint_options.iter().flat_map(|options| options.keys()) {
+            match name.parse::<Lint>() {
+                Ok(lint) if CONFIGURABLE_LINTS.contains(&lint) => (),
+                Ok(_) => bail!("Lint {} has no options", name),
+                Err(e) => bail!("{} in lint-options", e),
+            }
+        }
+        Ok(())
+    }
 }
 
 impl FromStr for ConfigFile {
@@ -32,3 +498,30 @@ impl FromStr for ConfigFile {
     }
 }
 
+
+#[test]
+fn test_effective_values_cover_all_options() {
+    // The example config in README sets every option
+    let readme = include_str!("../README.md");
+    let example = readme.split("All the options:\n\n```toml\n").nth(1).unwrap().split("```").next().unwrap();
+
+    let config_file: ConfigFile = example.parse().unwrap();
+    let lint_config = lint::Config::from_config_file(&config_file).unwrap();
+    let values = effective_values(&config_file, &lint_config, &RunOpt::default());
+
+    let table: toml::value::Table = toml::from_str(example).unwrap();
+    for (key, value) in &table {
+        // Levels of categories are shown as levels of their lints
+        let keys: Vec<String> = match value {
+            toml::Value::Table(table) if key != "levels" => table.keys().map(|sub| format!("{}.{}", key, sub)).collect(),
+            _ => vec![key.clone()],
+        };
+
+        for key in keys {
+            assert!(
+                values.iter().any(|value| value.key.starts_with(&key) && value.source == Source::ConfigFile),
+                "{} is not in the effective config", key,
+            );
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap as Map;
+use std::path::PathBuf;
+
+use failure::Error;
+use regex::Regex;
+use unicase::UniCase;
+
+use lint::Emitter;
+use lint::Lint;
+use preprocess::Parsed;
+use ConfigFile;
+use {Edit, Fix};
+
+/// Functions marked as deprecated or banned
+#[derive(Default)]
+pub struct Config {
+    functions: Map<UniCase<String>, Replacement>,
+    banned: Map<UniCase<String>, Replacement>,
+}
+
+struct Replacement {
+    /// Hint shown to the user, if any
+    hint: Option<String>,
+
+    /// Name of a function that can be used instead,
+    /// without any change to the arguments
+    drop_in: Option<String>,
+}
+
+impl Config {
+    /// Value of each `[deprecated]` and `[banned]` entry is either a hint, eg. "Use Get-CimInstance",
+    /// or just a name of the function, which is then treated as a drop-in replacement.
+    /// For banned functions, the value may be empty.
+    pub fn from_config_file(config_file: &ConfigFile) -> Result<Config, Error> {
+        Ok(Config {
+            functions: replacements(config_file.deprecated.as_ref()),
+            banned: replacements(config_file.banned.as_ref()),
+        })
+    }
+}
+
+fn replacements(entries: Option<&Map<String, String>>) -> Map<UniCase<String>, Replacement> {
+    lazy_static! {
+        static ref FUNCTION_NAME: Regex = Regex::new(r"^[[:alpha:]_][\w-]*$").unwrap();
+    }
+
+    let mut functions = Map::new();
+
+    for (function, replacement) in entries.into_iter().flatten() {
+        let replacement = if FUNCTION_NAME.is_match(replacement) {
+            Replacement {
+                hint: Some(format!("Use `{}` instead", replacement)),
+                drop_in: Some(replacement.clone()),
+            }
+        } else {
+            Replacement {
+                hint: if replacement.is_empty() { None } else { Some(replacement.clone()) },
+                drop_in: None,
+            }
+        };
+
+        functions.insert(UniCase::new(function.clone()), replacement);
+    }
+
+    functions
+}
+
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[Lint::DeprecatedFunctions, Lint::BannedFunctions];
+
+pub fn analyze(files: &Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter) {
+    if config.functions.is_empty() && config.banned.is_empty() {
+        return;
+    }
+
+    for file in files.values() {
+        for usage in &file.usages {
+            if !usage.item.is_function() {
+                continue;
+            }
+
+            let name = UniCase::new(usage.name().to_owned());
+
+            // Banned takes precedence if a function is in both lists
+            let (lint, text, replacement) = match (config.banned.get(&name), config.functions.get(&name)) {
+                (Some(replacement), _) => (Lint::BannedFunctions, "usage of banned function", replacement),
+                (None, Some(replacement)) => (Lint::DeprecatedFunctions, "usage of deprecated function", replacement),
+                (None, None) => continue,
+            };
+
+            let mut message = usage.span.in_file(file)
+                .lint(lint, text)
+                .what(usage.name());
+
+            if let Some(hint) = &replacement.hint {
+                message = message.note(hint.clone());
+            }
+
+            if let Some(drop_in) = &replacement.drop_in {
+                message = message.fix(Fix {
+                    description: format!("Replace with `{}`", drop_in),
+                    edits: vec![Edit { span: usage.span, replacement: drop_in.clone() }],
+                });
+            }
+
+            message.emit(emitter);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Checks reads of environment variables, active when `required_env` is set in config
+
+use std::collections::BTreeMap as Map;
+use std::collections::BTreeSet as Set;
+use std::path::PathBuf;
+
+use unicase::UniCase;
+
+use lint::Emitter;
+use lint::Lint;
+use preprocess::Parsed;
+use syntax::EnvAccess;
+use ConfigFile;
+
+/// Variables set by the system, assumed to be always there
+const WELL_KNOWN: &[&str] = &[
+    "APPDATA",
+    "COMPUTERNAME",
+    "ComSpec",
+    "HOME",
+    "HOMEDRIVE",
+    "HOMEPATH",
+    "LOCALAPPDATA",
+    "OS",
+    "PATH",
+    "PATHEXT",
+    "PROCESSOR_ARCHITECTURE",
+    "ProgramData",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "PSModulePath",
+    "PUBLIC",
+    "SystemDrive",
+    "SystemRoot",
+    "TEMP",
+    "TMP",
+    "USERDOMAIN",
+    "USERNAME",
+    "USERPROFILE",
+    "windir",
+];
+
+#[derive(Default)]
+pub struct Config {
+    /// Variables assumed to be set, `None` disables the checks
+    required: Option<Set<UniCase<String>>>,
+}
+
+impl Config {
+    pub fn from_config_file(config_file: &ConfigFile) -> Config {
+        // Variables mapped to import roots are assumed to be set too
+        let env_roots = config_file.imports.iter().flat_map(|imports| &imports.env_roots).flatten();
+
+        let required = config_file.required_env.as_ref().map(|required| {
+            required
+                .iter()
+                .cloned()
+                .chain(env_roots.map(|(name, _)| name.clone()))
+                .chain(WELL_KNOWN.iter().map(|&name| name.to_owned()))
+                .map(UniCase::new)
+                .collect()
+        });
+
+        Config { required }
+    }
+}
+
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[Lint::UncheckedEnvVariables];
+
+pub fn analyze(files: &Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter) {
+    let required = match &config.required {
+        Some(required) => required,
+        None => return,
+    };
+
+    for file in files.values() {
+        // Variables set or checked so far
+        let mut known = Set::new();
+
+        for variable in &file.env_variables {
+            let name = UniCase::new(variable.name.clone());
+
+            if variable.access == EnvAccess::Read && !required.contains(&name) && !known.contains(&name) {
+                variable.span.in_file(file)
+                    .lint(Lint::UncheckedEnvVariables, "environment variable may not be set")
+                    .what(variable.name.as_str())
+                    .note("Check whether it's set before reading it or add it to `required_env` in config")
+                    .emit(emitter);
+            }
+
+            // Report each variable only once
+            known.insert(name);
+        }
+    }
+}
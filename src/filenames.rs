@@ -0,0 +1,84 @@
+use std::collections::BTreeMap as Map;
+use std::path::PathBuf;
+
+use failure::{Error, ResultExt};
+use regex::Regex;
+use unicase;
+
+use lint::Emitter;
+use lint::Lint;
+use preprocess::{FileKind, Parsed};
+use ConfigFile;
+use Location;
+
+/// File naming conventions
+#[derive(Default)]
+pub struct Config {
+    /// Pattern that every file name should match
+    pattern: Option<Regex>,
+
+    /// Pattern that names of files with Pester tests should match
+    tests_pattern: Option<Regex>,
+}
+
+impl Config {
+    pub fn from_config_file(config_file: &ConfigFile) -> Result<Config, Error> {
+        let filenames = match &config_file.filenames {
+            Some(filenames) => filenames,
+            None => return Ok(Config::default()),
+        };
+
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>, Error> {
+            match pattern {
+                Some(pattern) => {
+                    let regex = Regex::new(pattern)
+                        .with_context(|_| format!("Invalid file name pattern: {}", pattern))?;
+                    Ok(Some(regex))
+                }
+                None => Ok(None),
+            }
+        };
+
+        Ok(Config {
+            pattern: compile(&filenames.pattern)?,
+            tests_pattern: compile(&filenames.tests_pattern)?,
+        })
+    }
+}
+
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[Lint::InvalidFileNames];
+
+pub fn analyze(files: &Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter) {
+    for file in files.values() {
+        // Naming conventions apply to scripts, module
+        // names are dictated by their manifests
+        if file.kind != FileKind::Script {
+            continue;
+        }
+
+        let file_name = match file.original_path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let is_test_file = file.usages.iter()
+            .any(|usage| usage.item.is_function() && unicase::eq(usage.name(), "Describe"));
+
+        let patterns = [
+            (&config.pattern, true),
+            (&config.tests_pattern, is_test_file),
+        ];
+
+        for &(pattern, applies) in &patterns {
+            if let (Some(pattern), true) = (pattern, applies) {
+                if !pattern.is_match(file_name) {
+                    Location::whole_file(file)
+                        .lint(Lint::InvalidFileNames, "file name doesn't follow the naming convention")
+                        .note(format!("File names should match `{}`", pattern.as_str()))
+                        .emit(emitter);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Output formats of `shelly analyze`, selectable by name
+//!
+//! To add a format, implement `Report` and add it to `output_formats`.
+
+use failure::Error;
+use serde_json;
+
+use std::io::{self, Write};
+
+use grouped;
+use html;
+use json::JsonEmitter;
+use junit::JunitEmitter;
+use {EmittedItem, Emitter, VecEmitter};
+
+/// Emitter writing messages in some format
+pub trait Report: Emitter {
+    /// Writes the rest of the report after all the messages are emitted,
+    /// eg. the whole document for formats that can't be streamed.
+    fn finish(self: Box<Self>) -> Result<(), Error>;
+}
+
+/// Options affecting some of the formats
+#[derive(Debug, Default)]
+pub struct FormatOptions {
+    /// Include suggested fixes in the JSON output
+    pub with_fixes: bool,
+}
+
+pub struct OutputFormat {
+    pub name: &'static str,
+    pub description: &'static str,
+    create: fn(Box<dyn Write>, &FormatOptions) -> Box<dyn Report>,
+}
+
+impl OutputFormat {
+    /// Creates an emitter of this format writing to `out`
+    pub fn create(&self, out: Box<dyn Write>, options: &FormatOptions) -> Box<dyn Report> {
+        (self.create)(out, options)
+    }
+}
+
+/// All the available formats
+pub fn output_formats() -> Vec<OutputFormat> {
+    let mut formats = Vec::new();
+
+    #[cfg(feature = "pretty")]
+    formats.push(OutputFormat {
+        name: "human",
+        description: "colored messages with code snippets",
+        create: |out, _| Box::new(::pretty::PrettyEmitter::new(out)),
+    });
+
+    formats.extend(vec![
+        OutputFormat {
+            name: "json",
+            description: "JSON array of messages",
+            create: |out, options| Box::new(Buffered { out, emitter: JsonEmitter::new(options.with_fixes) }),
+        },
+        OutputFormat {
+            name: "short",
+            description: "one line per message, `path:line:col: level[lint]: message`",
+            create: |out, _| Box::new(Streamed::new(out, write_short)),
+        },
+        OutputFormat {
+            name: "by-lint",
+            description: "messages grouped by lint, with counts",
+            create: |out, _| Box::new(Buffered { out, emitter: ByLint(VecEmitter::new()) }),
+        },
+        OutputFormat {
+            name: "html",
+            description: "standalone HTML report",
+            create: |out, _| Box::new(Buffered { out, emitter: VecEmitter::new() }),
+        },
+        OutputFormat {
+            name: "github",
+            description: "GitHub Actions workflow commands, shown as annotations in pull requests",
+            create: |out, _| Box::new(Streamed::new(out, write_github)),
+        },
+        OutputFormat {
+            name: "junit",
+            description: "JUnit XML report with a test case per file",
+            create: |out, _| Box::new(Buffered { out, emitter: JunitEmitter::new() }),
+        },
+    ]);
+
+    formats
+}
+
+/// Finds a format by name. `text` is accepted as an alias of `human`.
+pub fn output_format(name: &str) -> Option<OutputFormat> {
+    let name = if name == "text" { "human" } else { name };
+    output_formats().into_iter().find(|format| format.name == name)
+}
+
+/// Emitter collecting the messages and writing the whole report at the end
+struct Buffered<E> {
+    out: Box<dyn Write>,
+    emitter: E,
+}
+
+/// Emitters which can render collected messages
+trait Render: Emitter {
+    fn render(&self) -> Result<String, Error>;
+}
+
+impl Render for JsonEmitter {
+    fn render(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(&self.messages)?)
+    }
+}
+
+impl Render for VecEmitter {
+    fn render(&self) -> Result<String, Error> {
+        Ok(html::report(&self.emitted_items))
+    }
+}
+
+/// Collects messages for the `by-lint` format
+struct ByLint(VecEmitter);
+
+impl Emitter for ByLint {
+    fn emit(&mut self, item: EmittedItem) {
+        self.0.emit(item);
+    }
+}
+
+impl Render for ByLint {
+    fn render(&self) -> Result<String, Error> {
+        Ok(grouped::report(&self.0.emitted_items))
+    }
+}
+
+impl Render for JunitEmitter {
+    fn render(&self) -> Result<String, Error> {
+        Ok(self.report())
+    }
+}
+
+impl<E: Render> Emitter for Buffered<E> {
+    fn emit(&mut self, item: EmittedItem) {
+        self.emitter.emit(item);
+    }
+
+    fn analyzed_file(&mut self, path: &::std::path::Path) {
+        self.emitter.analyzed_file(path);
+    }
+}
+
+impl<E: Render> Report for Buffered<E> {
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        writeln!(self.out, "{}", self.emitter.render()?)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Emitter writing each message as soon as it's emitted
+struct Streamed {
+    out: Box<dyn Write>,
+    write: fn(&mut dyn Write, &EmittedItem) -> io::Result<()>,
+
+    /// The first error of writing the output
+    error: Option<io::Error>,
+}
+
+impl Streamed {
+    fn new(out: Box<dyn Write>, write: fn(&mut dyn Write, &EmittedItem) -> io::Result<()>) -> Streamed {
+        Streamed { out, write, error: None }
+    }
+}
+
+impl Emitter for Streamed {
+    fn emit(&mut self, item: EmittedItem) {
+        if self.error.is_none() {
+            self.error = (self.write)(&mut *self.out, &item).err();
+        }
+    }
+}
+
+impl Report for Streamed {
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        match self.error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(self.out.flush()?),
+        }
+    }
+}
+
+/// Writes a message in the errorformat-friendly `path:line:col: level[lint]: message` form.
+///
+/// Newlines are replaced, so every message is exactly one line.
+fn write_short(out: &mut dyn Write, item: &EmittedItem) -> io::Result<()> {
+    let position = item.location.span
+        .map(|span| format!(":{}:{}", span.start.line, span.start.col))
+        .unwrap_or_default();
+
+    let message = item.message.replace("\r\n", " ").replace('\n', " ");
+    writeln!(out, "{}{}: {}: {}", item.location.file.display(), position, item.heading(), message)
+}
+
+/// Writes a message as an `::error` or `::warning` workflow command of GitHub Actions
+fn write_github(out: &mut dyn Write, item: &EmittedItem) -> io::Result<()> {
+    // Paths should be relative to the repository root, without `./`
+    let file = item.location.file.strip_prefix(".").unwrap_or(&item.location.file);
+
+    let mut properties = vec![format!("file={}", escape_github_property(&file.display().to_string()))];
+    if let Some(span) = item.location.span {
+        properties.push(format!("line={},col={}", span.start.line, span.start.col));
+        properties.push(format!("endLine={},endColumn={}", span.end.line, span.end.col));
+    }
+    properties.push(format!("title={}", escape_github_property(&item.heading())));
+
+    let mut message = item.message.clone();
+    for note in &item.notes {
+        message.push('\n');
+        message.push_str(&note.message);
+    }
+
+    writeln!(out, "::{} {}::{}", item.kind.name(), properties.join(","), escape_github_data(&message))
+}
+
+/// Escapes a message of a workflow command
+fn escape_github_data(data: &str) -> String {
+    data.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escapes a property value of a workflow command
+fn escape_github_property(value: &str) -> String {
+    escape_github_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[test]
+fn test_escape_github() {
+    assert_eq!(escape_github_data("100% done\nreally"), "100%25 done%0Areally");
+    assert_eq!(escape_github_property("a:b,c"), "a%3Ab%2Cc");
+}
+
+#[test]
+fn test_write_short() {
+    use lint::Lint;
+    use std::rc::Rc;
+    use {MessageKind, Span};
+
+    let source = "Import-Module Foo\nGet-Foo\n";
+    let span = Span::from_fragment(2, &source[18..25], source);
+    let item = EmittedItem {
+        lint: Lint::UnknownFunctions,
+        category: Lint::UnknownFunctions.category(),
+        doc_url: Lint::UnknownFunctions.doc_url(),
+        kind: MessageKind::Error,
+        message: "function not\nin scope".to_owned(),
+        location: span.in_file_source("dir/Build.ps1", Rc::from(source)),
+        notes: Vec::new(),
+        fix: None,
+    };
+
+    let mut out = Vec::new();
+    write_short(&mut out, &item).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "dir/Build.ps1:2:1: error[unknown-functions]: function not in scope\n",
+    );
+}
+
+#[test]
+fn test_format_names() {
+    let formats = output_formats();
+    for format in &formats {
+        assert_eq!(output_format(format.name).map(|format| format.name), Some(format.name));
+    }
+    assert!(output_format("xml").is_none());
+}
@@ -0,0 +1,218 @@
+//! Graph of dot-imports printed by `shelly graph`
+
+use failure::{Error, ResultExt};
+
+use std::collections::BTreeMap as Map;
+use std::collections::BTreeSet as Set;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use lint::{Level, Lint};
+use {EmittedItem, Emitter, Location, RunOpt};
+
+/// Files and imports between them, with paths relative
+/// to the analyzed directory (if inside of it)
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+    pub files: Set<PathBuf>,
+    pub imports: Vec<Import>,
+}
+
+/// Dot-import of one file by another
+#[derive(Debug, Serialize)]
+pub struct Import {
+    pub from: PathBuf,
+    pub to: PathBuf,
+
+    /// 1-indexed line of the import statement
+    pub line: u32,
+
+    /// Nothing from the imported file is used, see `unused-imports`
+    pub unused: bool,
+
+    /// Functions used through this import, but defined
+    /// in files it imports, see `indirect-imports`
+    pub indirect: Vec<IndirectUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndirectUsage {
+    pub function: String,
+    pub defined_in: PathBuf,
+}
+
+/// Collects imports and matches messages about them by the location
+struct GraphEmitter {
+    root: PathBuf,
+    graph: Graph,
+
+    /// Indices of imports by the original path of the importing file
+    /// and the byte offset of the import statement
+    by_location: Map<(PathBuf, u32), usize>,
+}
+
+impl GraphEmitter {
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.root).unwrap_or(path).to_owned()
+    }
+
+    fn import_at(&mut self, location: &Location) -> Option<&mut Import> {
+        let span = location.span?;
+        let index = *self.by_location.get(&(location.file.clone(), span.start.byte))?;
+        Some(&mut self.graph.imports[index])
+    }
+}
+
+impl Emitter for GraphEmitter {
+    fn emit(&mut self, item: EmittedItem) {
+        match item.lint {
+            Lint::UnusedImports => {
+                if let Some(import) = self.import_at(&item.location) {
+                    import.unused = true;
+                }
+            }
+            Lint::IndirectImports => {
+                let function = match item.location.span {
+                    Some(span) => item.location.source
+                        .get(span.start.byte as usize..span.end.byte as usize)
+                        .unwrap_or_default()
+                        .to_owned(),
+                    None => return,
+                };
+                let (through, definition) = match (item.notes.first(), item.notes.get(1)) {
+                    (Some(through), Some(definition)) => (through, definition),
+                    _ => return,
+                };
+                let defined_in = match &definition.location {
+                    Some(location) => {
+                        let file = location.file.canonicalize().unwrap_or_else(|_| location.file.clone());
+                        self.relative(&file)
+                    }
+                    None => return,
+                };
+                if let Some(import) = through.location.as_ref().and_then(|location| self.import_at(location)) {
+                    let usage = IndirectUsage { function, defined_in };
+                    if !import.indirect.iter().any(|known| known.function.eq_ignore_ascii_case(&usage.function)) {
+                        import.indirect.push(usage);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn parsed_imports(&mut self, path: &Path, _imports: &[&Path]) {
+        let path = self.relative(path);
+        self.graph.files.insert(path);
+    }
+
+    fn parsed_import(&mut self, path: &Path, import: &Path, location: &Location) {
+        let span = match location.span {
+            Some(span) => span,
+            None => return,
+        };
+        let edge = Import {
+            from: self.relative(path),
+            to: self.relative(import),
+            line: span.start.line,
+            unused: false,
+            indirect: Vec::new(),
+        };
+        self.by_location.insert((location.file.clone(), span.start.byte), self.graph.imports.len());
+        self.graph.imports.push(edge);
+    }
+}
+
+/// Analyzes the directory, collecting the imports between files
+/// and the `unused-imports` and `indirect-imports` reported for them
+pub fn import_graph(root: &Path, run_opt: RunOpt) -> Result<Graph, Error> {
+    let lints = vec![Lint::UnusedImports, Lint::IndirectImports];
+    let mut lint_overrides = run_opt.lint_overrides;
+    for &lint in &lints {
+        lint_overrides.insert(lint, Level::Warn);
+    }
+    let run_opt = RunOpt { only: lints, lint_overrides, ..run_opt };
+
+    let mut emitter = GraphEmitter {
+        root: root.canonicalize().with_context(|_| format!("Opening {}", root.display()))?,
+        graph: Graph::default(),
+        by_location: Map::new(),
+    };
+    ::run(root, run_opt, &mut emitter)?;
+
+    let mut graph = emitter.graph;
+    graph.imports.sort_by(|a, b| (&a.from, a.line, &a.to).cmp(&(&b.from, b.line, &b.to)));
+    Ok(graph)
+}
+
+impl Graph {
+    /// Renders the graph in the Graphviz DOT language. Unused imports
+    /// are dashed and imports used indirectly are labeled with the functions.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph imports {\n    node [shape=box];\n");
+
+        for file in &self.files {
+            let _ = writeln!(dot, "    {};", quote(&file.display().to_string()));
+        }
+
+        for import in &self.imports {
+            let mut attributes = Vec::new();
+            if import.unused {
+                attributes.push("style=dashed".to_owned());
+                attributes.push("color=gray".to_owned());
+                attributes.push("label=\"unused\"".to_owned());
+            } else if !import.indirect.is_empty() {
+                let functions: Vec<_> = import.indirect.iter().map(|usage| &*usage.function).collect();
+                attributes.push("color=orange".to_owned());
+                attributes.push(format!("label={}", quote(&format!("indirect: {}", functions.join(", ")))));
+            }
+
+            let _ = write!(
+                dot,
+                "    {} -> {}",
+                quote(&import.from.display().to_string()),
+                quote(&import.to.display().to_string()),
+            );
+            if !attributes.is_empty() {
+                let _ = write!(dot, " [{}]", attributes.join(", "));
+            }
+            dot.push_str(";\n");
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Quotes a DOT identifier
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[test]
+fn test_import_graph() {
+    let dir = ::testing::setup_dir(&[
+        ("A.ps1", ". $PSScriptRoot/B.ps1\n. $PSScriptRoot/D.ps1\nGet-C\n"),
+        ("B.ps1", ". $PSScriptRoot/C.ps1\nfunction Get-B { Get-C }\n"),
+        ("C.ps1", "function Get-C { }\n"),
+        ("D.ps1", "function Get-D { }\n"),
+    ]).unwrap();
+
+    let graph = import_graph(dir.path(), RunOpt::default()).unwrap();
+    assert_eq!(graph.files.len(), 4);
+
+    let edges: Vec<_> = graph.imports.iter()
+        .map(|import| (import.from.to_str().unwrap(), import.to.to_str().unwrap(), import.unused))
+        .collect();
+    assert_eq!(edges, [("A.ps1", "B.ps1", false), ("A.ps1", "D.ps1", true), ("B.ps1", "C.ps1", false)]);
+
+    assert_eq!(graph.imports[0].line, 1);
+    assert_eq!(graph.imports[0].indirect.len(), 1);
+    assert_eq!(graph.imports[0].indirect[0].function, "Get-C");
+    assert_eq!(graph.imports[0].indirect[0].defined_in, Path::new("C.ps1"));
+
+    let dot = graph.to_dot();
+    assert!(dot.contains("    \"A.ps1\" -> \"B.ps1\" [color=orange, label=\"indirect: Get-C\"];\n"));
+    assert!(dot.contains("    \"A.ps1\" -> \"D.ps1\" [style=dashed, color=gray, label=\"unused\"];\n"));
+    assert!(dot.contains("    \"B.ps1\" -> \"C.ps1\";\n"));
+}
@@ -0,0 +1,70 @@
+//! Report grouping messages by lint, written by `--output-format by-lint`
+//!
+//! Useful for triaging a large codebase one lint at a time.
+
+use std::collections::BTreeMap as Map;
+use std::fmt::Write;
+
+use lint::Lint;
+use {plural, EmittedItem};
+
+/// Renders a section per lint, with the number of messages
+/// and a single line for each of them.
+///
+/// Lints with the most messages come first.
+pub fn report(items: &[EmittedItem]) -> String {
+    let mut by_lint: Map<Lint, Vec<&EmittedItem>> = Map::new();
+    for item in items {
+        by_lint.entry(item.lint).or_default().push(item);
+    }
+
+    let mut groups: Vec<_> = by_lint.into_iter().collect();
+    groups.sort_by_key(|(_, items)| ::std::cmp::Reverse(items.len()));
+
+    let mut report = String::new();
+    for (lint, items) in &groups {
+        let _ = writeln!(report, "{} ({})", items[0].heading(), plural(items.len(), "message"));
+
+        for item in items {
+            let position = item.location.span
+                .map(|span| format!(":{}:{}", span.start.line, span.start.col))
+                .unwrap_or_default();
+            let _ = writeln!(report, "    {}{}: {}", item.location.file.display(), position, item.message);
+        }
+
+        let _ = writeln!(report, "    = see {}\n", lint.doc_url());
+    }
+
+    let _ = write!(report, "{} from {}", plural(items.len(), "message"), plural(groups.len(), "lint"));
+    report
+}
+
+#[test]
+fn test_report() {
+    use std::rc::Rc;
+    use {Location, MessageKind};
+
+    let item = |lint: Lint, file: &str| EmittedItem {
+        lint,
+        category: lint.category(),
+        doc_url: lint.doc_url(),
+        kind: MessageKind::Warning,
+        message: "message".to_owned(),
+        location: Location { file: file.into(), span: None, source: Rc::from("") },
+        notes: Vec::new(),
+        fix: None,
+    };
+
+    let report = report(&[
+        item(Lint::TodoComments, "a.ps1"),
+        item(Lint::UnusedImports, "a.ps1"),
+        item(Lint::UnusedImports, "b.ps1"),
+    ]);
+
+    assert!(report.starts_with("warning[unused-imports] (2 messages)\n    a.ps1: message\n    b.ps1: message\n"));
+    assert!(report.contains("warning[todo-comments] (1 message)\n"));
+    assert!(report.ends_with("3 messages from 2 lints"));
+
+    let report = ::grouped::report(&[item(Lint::TodoComments, "a.ps1")]);
+    assert!(report.ends_with("1 message from 1 lint"));
+}
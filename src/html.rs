@@ -0,0 +1,208 @@
+//! Standalone HTML report of emitted messages, written by `--format html`
+
+use std::cmp;
+use std::collections::BTreeMap as Map;
+use std::fmt::Write;
+use std::path::Path;
+
+use {plural, EmittedItem, Location, MessageKind};
+
+/// Maximal number of lines of a snippet
+const MAX_SNIPPET_LINES: usize = 6;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h2 { font-family: monospace; border-bottom: 1px solid #ccc; }
+.message { margin: 1em 0; padding: 0.5em 1em; border-left: 4px solid; background: #f8f8f8; }
+.message.error { border-color: #c33; }
+.message.warning { border-color: #c90; }
+.error .heading { color: #c33; }
+.warning .heading { color: #a70; }
+.heading { font-weight: bold; }
+.position, .note-location { font-family: monospace; color: #666; }
+.heading a { color: inherit; }
+.category { float: right; font-weight: normal; color: #666; }
+pre { background: #fff; padding: 0.5em; overflow-x: auto; }
+.line-no { color: #999; user-select: none; }
+mark { background: #fd8; }
+#show-errors:not(:checked) ~ main .error { display: none; }
+#show-warnings:not(:checked) ~ main .warning { display: none; }
+";
+
+/// Renders a self-contained page with messages grouped by file.
+///
+/// Errors and warnings can be hidden with checkboxes,
+/// which works without any scripts.
+pub fn report(items: &[EmittedItem]) -> String {
+    let mut by_file: Map<&Path, Vec<&EmittedItem>> = Map::new();
+    for item in items {
+        by_file.entry(&item.location.file).or_default().push(item);
+    }
+
+    let count = |kind| items.iter().filter(|item| item.kind == kind).count();
+    let errors = count(MessageKind::Error);
+    let warnings = count(MessageKind::Warning);
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Shelly report</title>\n");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    html.push_str("<h1>Shelly report</h1>\n");
+    let _ = writeln!(
+        html,
+        "<p>{} and {} in {}</p>",
+        plural(errors, "error"), plural(warnings, "warning"), plural(by_file.len(), "file"),
+    );
+    let _ = writeln!(
+        html,
+        "<input type=\"checkbox\" id=\"show-errors\" checked><label for=\"show-errors\">Errors ({})</label>",
+        errors,
+    );
+    let _ = writeln!(
+        html,
+        "<input type=\"checkbox\" id=\"show-warnings\" checked><label for=\"show-warnings\">Warnings ({})</label>",
+        warnings,
+    );
+    html.push_str("<main>\n");
+
+    for (file, items) in by_file {
+        let _ = writeln!(html, "<section class=\"file\">\n<h2>{}</h2>", escape(&file.display().to_string()));
+        for item in items {
+            write_item(&mut html, item);
+        }
+        html.push_str("</section>\n");
+    }
+
+    html.push_str("</main>\n</body>\n</html>\n");
+    html
+}
+
+fn write_item(html: &mut String, item: &EmittedItem) {
+    let _ = writeln!(html, "<div class=\"message {}\">", item.kind.name());
+    let _ = writeln!(
+        html,
+        "<div class=\"heading\"><a href=\"{}\">{}</a>: {} <span class=\"position\">{}</span> \
+         <span class=\"category\">{}</span></div>",
+        escape(item.doc_url),
+        escape(&item.heading()),
+        escape(&item.message),
+        position(&item.location),
+        item.category.name(),
+    );
+    html.push_str(&snippet(&item.location));
+
+    if !item.notes.is_empty() || item.fix.is_some() {
+        html.push_str("<ul class=\"notes\">\n");
+        for note in &item.notes {
+            let _ = write!(html, "<li>{}", escape(&note.message));
+            if let Some(location) = &note.location {
+                let _ = write!(
+                    html,
+                    " <span class=\"note-location\">{}{}</span>{}",
+                    escape(&location.file.display().to_string()),
+                    position(location),
+                    snippet(location),
+                );
+            }
+            html.push_str("</li>\n");
+        }
+        if let Some(fix) = &item.fix {
+            let _ = writeln!(html, "<li>fix: {}</li>", escape(&fix.description));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("</div>\n");
+}
+
+/// `:line:col` of the location, if it has a span
+fn position(location: &Location) -> String {
+    location.span
+        .map(|span| format!(":{}:{}", span.start.line, span.start.col))
+        .unwrap_or_default()
+}
+
+/// Lines of code covered by the span, with the span highlighted
+fn snippet(location: &Location) -> String {
+    let span = match location.span {
+        Some(span) => span,
+        None => return String::new(),
+    };
+
+    let source = &*location.source;
+    let start = span.start.byte as usize;
+    let end = cmp::max(start, span.end.byte as usize);
+
+    let first_line = span.start.find_line(source);
+    let mut line_start = first_line.as_ptr() as usize - source.as_ptr() as usize;
+
+    let mut html = String::from("<pre>");
+
+    for line_no in (span.start.line..).take(MAX_SNIPPET_LINES) {
+        let rest = source.get(line_start..).unwrap_or("");
+        let text = rest.lines().next().unwrap_or("").trim_end_matches('\r');
+        let line_end = line_start + text.len();
+
+        let from = cmp::min(cmp::max(start, line_start), line_end);
+        let to = cmp::max(cmp::min(end, line_end), from);
+
+        let _ = writeln!(
+            html,
+            "<span class=\"line-no\">{:>4} | </span>{}<mark>{}</mark>{}",
+            line_no,
+            escape(source.get(line_start..from).unwrap_or("")),
+            escape(source.get(from..to).unwrap_or("")),
+            escape(source.get(to..line_end).unwrap_or("")),
+        );
+
+        line_start = rest.find('\n').map_or(source.len(), |newline| line_start + newline + 1);
+        if line_start >= end {
+            break;
+        }
+    }
+
+    html.push_str("</pre>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _   => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn test_report() {
+    use lint::Lint;
+    use std::rc::Rc;
+    use Span;
+
+    let source = "Set-StrictMode -Version Latest\nGet-Foo <bar>\n";
+    let span = Span::from_fragment(2, &source[31..38], source);
+
+    let item = EmittedItem {
+        lint: Lint::UnknownFunctions,
+        category: Lint::UnknownFunctions.category(),
+        doc_url: Lint::UnknownFunctions.doc_url(),
+        kind: MessageKind::Error,
+        message: "function not in scope".to_owned(),
+        location: span.in_file_source("Build.ps1", Rc::from(source)),
+        notes: Vec::new(),
+        fix: None,
+    };
+
+    let html = report(&[item]);
+    assert!(html.contains("<h2>Build.ps1</h2>"));
+    assert!(html.contains("error[unknown-functions]</a>: function not in scope"));
+    assert!(html.contains("docs/lints.md#unknown-functions"));
+    assert!(html.contains("<mark>Get-Foo</mark> &lt;bar&gt;"));
+    assert!(html.contains("1 error and 0 warnings in 1 file"));
+}
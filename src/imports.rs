@@ -0,0 +1,303 @@
+//! Lints about dot-imports: their placement and what they import
+
+use failure::{Error, ResultExt};
+use globset::GlobSet;
+use regex::Regex;
+
+use std::cmp;
+use std::collections::BTreeMap as Map;
+use std::collections::BTreeSet as Set;
+use std::path::{Path, PathBuf};
+
+use lint::Emitter;
+use lint::Lint;
+use preprocess::{FileKind, Parsed};
+use syntax::{Import, Importee, Span};
+use ConfigFile;
+use Location;
+use {Edit, Fix};
+
+pub struct Config {
+    /// Patterns matching names of test files
+    test_patterns: Vec<Regex>,
+
+    /// Scripts run directly, if declared in config
+    entrypoints: Option<Entrypoints>,
+
+    /// Canonical paths of files implicitly imported into every file
+    preludes: Vec<PathBuf>,
+
+    /// Whether all the files are analyzed, so that every importer is known
+    all_files: bool,
+}
+
+impl Config {
+    pub fn from_config_file(config_file: &ConfigFile, root: &Path) -> Result<Config, Error> {
+        let patterns = config_file.imports
+            .as_ref()
+            .and_then(|imports| imports.test_patterns.clone())
+            .unwrap_or_else(|| DEFAULT_TEST_PATTERNS.iter().map(|&pattern| pattern.to_owned()).collect());
+
+        let test_patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).with_context(|_| format!("Invalid test file pattern: {}", pattern))
+            })
+            .collect::<Result<_, _>>()?;
+
+        // Missing preludes are reported when loading the scope config
+        let preludes = config_file.extras.iter()
+            .flat_map(|extras| &extras.preludes)
+            .flatten()
+            .filter_map(|prelude| root.join(prelude).canonicalize().ok())
+            .collect();
+
+        Ok(Config {
+            test_patterns,
+            entrypoints: Entrypoints::from_config_file(config_file, root)?,
+            preludes,
+            all_files: true,
+        })
+    }
+
+    /// Sets whether all the files are analyzed. Otherwise, `unused-files`
+    /// is not checked, as importers of the analyzed files may not be parsed.
+    pub fn with_all_files(mut self, all_files: bool) -> Config {
+        self.all_files = all_files;
+        self
+    }
+
+    fn is_entrypoint(&self, path: &Path) -> bool {
+        self.entrypoints.as_ref().is_some_and(|entrypoints| entrypoints.contains(path))
+    }
+
+    fn is_test_file(&self, path: &Path) -> bool {
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        self.test_patterns.iter().any(|pattern| pattern.is_match(file_name))
+    }
+}
+
+/// Scripts run directly rather than imported, from `[entrypoints]`
+pub struct Entrypoints {
+    /// Root of the analyzed directory
+    root: PathBuf,
+
+    /// Globs relative to the root
+    globs: GlobSet,
+}
+
+impl Entrypoints {
+    /// Loads the entry points, if any are declared
+    pub fn from_config_file(config_file: &ConfigFile, root: &Path) -> Result<Option<Entrypoints>, Error> {
+        let paths = match config_file.entrypoints.as_ref().and_then(|entrypoints| entrypoints.paths.as_ref()) {
+            Some(paths) => paths,
+            None => return Ok(None),
+        };
+        let globs = ::load_globs(paths.iter()).context("Loading entry points")?;
+        Ok(Some(Entrypoints { root: root.to_owned(), globs }))
+    }
+
+    /// Checks whether a file, by its original path, is an entry point
+    pub fn contains(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root).is_ok_and(|path| self.globs.is_match(path))
+    }
+}
+
+/// Names of test files, when `[imports] test_patterns` is not set
+pub(crate) const DEFAULT_TEST_PATTERNS: &[&str] = &[r"(?i)\.Tests\.ps1$"];
+
+/// Commands setting up a script, allowed before dot-imports
+const PREAMBLE_COMMANDS: &[&str] = &[
+    "Set-StrictMode",
+    "Import-Module",
+    "Split-Path",
+    "Join-Path",
+    // Pester blocks with imports in `BeforeAll`, and the ones enclosing them
+    "BeforeAll",
+    "BeforeEach",
+    "Describe",
+    "Context",
+];
+
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[Lint::UnsortedImports, Lint::ImportsNotFirst, Lint::ImportedTests, Lint::UnusedFiles];
+
+pub fn analyze(files: &Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter) {
+    for file in files.values() {
+        if file.kind == FileKind::Manifest {
+            continue;
+        }
+
+        check_order(file, emitter);
+        check_placement(file, emitter);
+        check_imported_tests(file, config, emitter);
+    }
+
+    if config.all_files {
+        check_unused_files(files, config, emitter);
+    }
+}
+
+/// Reports scripts not imported by any other file,
+/// unless they're tests or entry points
+fn check_unused_files(files: &Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter) {
+    let imported: Set<&Path> = files.iter()
+        .flat_map(|(path, file)| file.imports.keys().filter(move |import| *import != path))
+        .chain(&config.preludes)
+        .map(|import| &**import)
+        .collect();
+
+    for (path, file) in files {
+        let is_unused = file.kind == FileKind::Script
+            && !imported.contains(&**path)
+            && !config.is_test_file(&file.original_path)
+            && !config.is_entrypoint(&file.original_path);
+
+        if is_unused {
+            Location::whole_file(file)
+                .lint(Lint::UnusedFiles, "script not imported by any file")
+                .note("Remove it if it's dead code, or list it in `entrypoints.paths` if it's run directly")
+                .emit(emitter);
+        }
+    }
+}
+
+/// Reports test files imported by non-test files
+fn check_imported_tests(file: &Parsed, config: &Config, emitter: &mut Emitter) {
+    if config.is_test_file(&file.original_path) {
+        return;
+    }
+
+    for (path, import) in &file.imports {
+        if !is_dot_import(import) || !config.is_test_file(path) {
+            continue;
+        }
+
+        import.span.in_file(file)
+            .lint(Lint::ImportedTests, "test file imported by non-test code")
+            .note("Test files are meant to be run by Pester, not imported")
+            .emit(emitter);
+    }
+}
+
+/// Whether an import is a dot-import rather than a module import
+fn is_dot_import(import: &Import) -> bool {
+    !matches!(import.importee, Importee::Module(_) | Importee::ModulePath(_) | Importee::UsingModule(_))
+}
+
+/// Reports dot-imports placed after the first statement,
+/// ie. a definition or a command other than `PREAMBLE_COMMANDS`.
+/// Imports in Pester's `BeforeAll` and `BeforeEach` are not checked.
+fn check_placement(file: &Parsed, emitter: &mut Emitter) {
+    let import_lines: Vec<u32> = file.imports.values().map(|import| import.span.start.line).collect();
+
+    let definitions = file.functions_and_classes().map(|definition| definition.span);
+    let usages = file.usages
+        .iter()
+        .filter(|usage| usage.item.is_function() || usage.item.is_executable())
+        .filter(|usage| !PREAMBLE_COMMANDS.iter().any(|command| usage.name().eq_ignore_ascii_case(command)))
+        .map(|usage| usage.span);
+
+    // Commands used on an import line, eg. `Join-Path`, are the import
+    let first_statement = definitions
+        .chain(usages)
+        .filter(|span| !import_lines.contains(&span.start.line))
+        .min_by_key(|span| span.start.byte);
+
+    let first_statement = match first_statement {
+        Some(span) => span,
+        None => return,
+    };
+
+    for import in file.imports.values().filter(|import| is_dot_import(import)) {
+        let in_setup_block = file.setup_blocks.iter().any(|block| block.contains(import.span));
+        if in_setup_block || import.span.start.line <= first_statement.start.line {
+            continue;
+        }
+
+        import.span.in_file(file)
+            .lint(Lint::ImportsNotFirst, "import after the first statement of the file")
+            .note_at(first_statement.in_file(file), "The first statement")
+            .emit(emitter);
+    }
+}
+
+/// Checks that dot-imports are sorted and grouped together
+/// before any function or class definition.
+///
+/// Blank lines and comments are allowed between imports.
+fn check_order(file: &Parsed, emitter: &mut Emitter) {
+    let source = &*file.source;
+    let lines: Vec<&str> = source.lines().collect();
+
+    let mut import_lines: Vec<u32> = file.imports
+        .values()
+        .filter(|import| is_dot_import(import))
+        .map(|import| import.span.start.line)
+        .collect();
+    import_lines.sort();
+    import_lines.dedup();
+
+    let (first, last) = match (import_lines.first(), import_lines.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return,
+    };
+
+    let line = |line_no: u32| lines[line_no as usize - 1];
+    let sort_key = |line_no: &u32| line(*line_no).trim().to_lowercase();
+
+    // Imports should be moved before the first definition
+    let start = file.functions_and_classes()
+        .map(|definition| definition.span.start.line)
+        .fold(first, cmp::min);
+
+    let other_lines: Vec<&str> = (start..last + 1)
+        .filter(|line_no| import_lines.binary_search(line_no).is_err())
+        .map(line)
+        .collect();
+
+    let is_grouped = start == first && other_lines.iter().all(|line| {
+        let line = line.trim();
+        line.is_empty() || line.starts_with('#')
+    });
+
+    let is_sorted = import_lines.windows(2).all(|pair| sort_key(&pair[0]) <= sort_key(&pair[1]));
+
+    if is_grouped && is_sorted {
+        return;
+    }
+
+    let mut sorted = import_lines.clone();
+    sorted.sort_by_key(sort_key);
+
+    let newline = if source.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let mut replacement: Vec<&str> = sorted.iter().cloned().map(line).collect();
+
+    let is_blank = |line: &&str| line.trim().is_empty();
+    let other_start = other_lines.iter().position(|line| !is_blank(line));
+    let other_end = other_lines.iter().rposition(|line| !is_blank(line));
+    if let (Some(other_start), Some(other_end)) = (other_start, other_end) {
+        replacement.push("");
+        replacement.extend(&other_lines[other_start..other_end + 1]);
+    }
+
+    let span = Span::from_fragment(start, line(start), source)
+        .to(Span::from_fragment(last, line(last), source));
+
+    let message = if is_grouped {
+        "imports are not sorted"
+    } else {
+        "imports are not grouped at the top of the file"
+    };
+
+    Span::from_fragment(first, line(first).trim(), source)
+        .in_file(file)
+        .lint(Lint::UnsortedImports, message)
+        .note("Dot-imports should be sorted and placed together before any definitions")
+        .fix(Fix {
+            description: "Sort and group the imports".to_owned(),
+            edits: vec![Edit { span, replacement: replacement.join(newline) }],
+        })
+        .emit(emitter);
+}
@@ -0,0 +1,139 @@
+//! Starter config written by `shelly init`
+
+use failure::Error;
+use unicase::UniCase;
+
+use std::collections::BTreeMap as Map;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use builtins;
+use lint::Lint;
+use {EmittedItem, Emitter, RunOpt};
+
+/// Maximal number of suggested commandlets
+const MAX_CMDLETS: usize = 30;
+
+/// Commandlets used in fewer files are not suggested
+const MIN_FILES: usize = 2;
+
+/// What the analysis found out about the directory
+#[derive(Default)]
+struct Survey {
+    test_files: Vec<PathBuf>,
+
+    /// Unknown commandlets, by the first spelling seen,
+    /// with numbers of files using them (they're reported once per file)
+    unknown: Map<UniCase<String>, (String, usize)>,
+}
+
+impl Emitter for Survey {
+    fn emit(&mut self, item: EmittedItem) {
+        if item.lint != Lint::UnknownFunctions {
+            return;
+        }
+
+        let name = match item.location.span {
+            Some(span) => item.location.source
+                .get(span.start.byte as usize..span.end.byte as usize)
+                .unwrap_or_default()
+                .to_owned(),
+            None => return,
+        };
+
+        self.unknown.entry(UniCase::new(name.clone())).or_insert((name, 0)).1 += 1;
+    }
+
+    fn analyzed_file(&mut self, path: &Path) {
+        let is_test = path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.to_lowercase().ends_with(".tests.ps1"));
+
+        if is_test {
+            self.test_files.push(path.to_owned());
+        }
+    }
+}
+
+/// Analyzes the directory and renders a `shelly.toml` with the detected
+/// known modules enabled and the other sections commented out.
+pub fn starter_config(root: &Path) -> Result<String, Error> {
+    let mut survey = Survey::default();
+    let run_opt = RunOpt { only: vec![Lint::UnknownFunctions], ..RunOpt::default() };
+    ::run(root, run_opt, &mut survey)?;
+
+    // Modules of the unknown commandlets, with an example of each
+    let mut modules: Map<&str, String> = Map::new();
+    let mut cmdlets = Vec::new();
+    for (name, files) in survey.unknown.values() {
+        let providing = builtins::modules_providing(name);
+        if providing.is_empty() {
+            cmdlets.push((name, *files));
+        }
+        for module in providing {
+            modules.entry(module).or_insert_with(|| format!("uses {}", name));
+        }
+    }
+    if let Some(test_file) = survey.test_files.first() {
+        let test_file = test_file.strip_prefix(root).unwrap_or(test_file);
+        modules.insert("Pester", format!("tests found, eg. {}", test_file.display()));
+    }
+
+    cmdlets.sort_by_key(|&(name, files)| (::std::cmp::Reverse(files), name.to_lowercase()));
+    let cmdlets: Vec<_> = cmdlets.into_iter()
+        .take_while(|&(_, files)| files >= MIN_FILES)
+        .take(MAX_CMDLETS)
+        .collect();
+
+    let mut config = String::new();
+    config.push_str("# Generated by `shelly init`. See the README for all the options:\n");
+    config.push_str("# https://github.com/krdln/shelly#configuration\n\n");
+
+    config.push_str("# Override default lint levels (see `shelly show-lints`)\n");
+    config.push_str("# [levels]\n");
+    config.push_str("# unknown-functions = \"warn\"\n");
+    config.push_str("# unsorted-imports = \"warn\"\n\n");
+
+    config.push_str("# Commandlets that are assumed to exist\n");
+    if modules.is_empty() {
+        config.push_str("# [extras]\n");
+        config.push_str("# Bundled command lists: Pester, Az, PSReadLine, ActiveDirectory\n");
+        config.push_str("# known_modules = [\"Pester\"]\n");
+    } else {
+        config.push_str("[extras]\n");
+        for (module, reason) in &modules {
+            let _ = writeln!(config, "# {}: {}", module, reason);
+        }
+        let names: Vec<_> = modules.keys().map(|module| format!("{:?}", module)).collect();
+        let _ = writeln!(config, "known_modules = [{}]", names.join(", "));
+    }
+
+    if !cmdlets.is_empty() {
+        config.push_str("# Used, but not defined anywhere. Uncomment the ones\n");
+        config.push_str("# that exist in your environment.\n");
+        config.push_str("# cmdlets = [\n");
+        for (name, files) in cmdlets {
+            let _ = writeln!(config, "#     {:?}, # used in {} files", name, files);
+        }
+        config.push_str("# ]\n");
+    }
+
+    Ok(config)
+}
+
+#[test]
+fn test_suggestions() {
+    let dir = ::testing::setup_dir(&[
+        ("Get-Bar.ps1", "Set-StrictMode -Version Latest\nInvoke-Thing\nInvoke-Once\n"),
+        ("Get-Foo.ps1", "Set-StrictMode -Version Latest\ninvoke-thing\n"),
+        ("Get-Foo.Tests.ps1", "Describe 'Get-Foo' { It 'works' { Set-ItResult -Skipped } }\n"),
+    ]).unwrap();
+
+    let config = starter_config(dir.path()).unwrap();
+    assert!(config.contains("\n[extras]\n"));
+    assert!(config.contains("# Pester: tests found, eg. Get-Foo.Tests.ps1\nknown_modules = [\"Pester\"]\n"));
+    assert!(config.contains("#     \"Invoke-Thing\", # used in 2 files\n"));
+    assert!(!config.contains("Invoke-Once"));
+
+    config.parse::<::ConfigFile>().unwrap();
+}
@@ -0,0 +1,289 @@
+//! JSON representation of emitted messages
+//!
+//! Also allows applying fixes from previously saved results.
+
+use failure::{Error, ResultExt};
+use similar::TextDiff;
+
+use std::collections::BTreeMap as Map;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use lint::Lint;
+use {plural, EmittedItem, Emitter, Encoding};
+
+/// A single emitted message
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Message {
+    /// Lint slug, eg. `unknown-functions`
+    pub lint: String,
+
+    /// `warning` or `error`
+    pub kind: String,
+
+    /// Category of the lint, eg. `imports`
+    #[serde(default)]
+    pub category: String,
+
+    /// Link to the explanation of the lint
+    #[serde(default)]
+    pub doc_url: String,
+
+    pub message: String,
+    pub file: PathBuf,
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fix: Option<Fix>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Position {
+    /// 1-indexed line
+    pub line: u32,
+
+    /// 1-indexed column
+    pub col: u16,
+
+    /// 0-indexed byte, not counting the byte order mark
+    pub byte: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Note {
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fix {
+    pub description: String,
+    pub edits: Vec<Edit>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Edit {
+    pub span: Span,
+
+    /// Replaced text, used to check whether the file has changed since
+    pub original: String,
+
+    pub replacement: String,
+}
+
+impl From<::Span> for Span {
+    fn from(span: ::Span) -> Span {
+        let position = |location: ::syntax::v2::Location| Position {
+            line: location.line,
+            col: location.col,
+            byte: location.byte,
+        };
+
+        Span {
+            start: position(span.start),
+            end: position(span.end),
+        }
+    }
+}
+
+impl Message {
+    pub fn from_item(item: &EmittedItem, with_fixes: bool) -> Message {
+        let notes = item.notes
+            .iter()
+            .map(|note| Note {
+                message: note.message.clone(),
+                file: note.location.as_ref().map(|location| location.file.clone()),
+                span: note.location.as_ref().and_then(|location| location.span).map(Span::from),
+            })
+            .collect();
+
+        let fix = item.fix.as_ref().filter(|_| with_fixes).map(|fix| Fix {
+            description: fix.description.clone(),
+            edits: fix.edits
+                .iter()
+                .map(|edit| Edit {
+                    span: edit.span.into(),
+                    original: item.location.source
+                        .get(edit.span.start.byte as usize .. edit.span.end.byte as usize)
+                        .unwrap_or_default()
+                        .to_owned(),
+                    replacement: edit.replacement.clone(),
+                })
+                .collect(),
+        });
+
+        Message {
+            lint: item.lint.slug().to_owned(),
+            kind: item.kind.name().to_owned(),
+            category: item.category.name().to_owned(),
+            doc_url: item.doc_url.to_owned(),
+            message: item.message.clone(),
+            file: item.location.file.clone(),
+            span: item.location.span.map(Span::from),
+            notes,
+            fix,
+        }
+    }
+}
+
+/// Emitter collecting messages in their JSON representation
+#[derive(Debug, Default)]
+pub struct JsonEmitter {
+    /// Whether to include suggested fixes
+    pub with_fixes: bool,
+
+    pub messages: Vec<Message>,
+}
+
+impl JsonEmitter {
+    pub fn new(with_fixes: bool) -> JsonEmitter {
+        JsonEmitter { with_fixes, messages: Vec::new() }
+    }
+}
+
+impl Emitter for JsonEmitter {
+    fn emit(&mut self, item: EmittedItem) {
+        self.messages.push(Message::from_item(&item, self.with_fixes));
+    }
+}
+
+/// Summary of `apply_fixes`
+///
+/// Displayed as `3 fixes in 2 files`.
+#[derive(Debug, Default)]
+pub struct Applied {
+    /// Number of applied fixes
+    pub applied: usize,
+
+    /// Reasons of skipping the rest of the fixes
+    pub skipped: Vec<String>,
+
+    /// Changed files
+    pub files: Vec<FixedFile>,
+}
+
+impl fmt::Display for Applied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in {}", plural(self.applied, "fix"), plural(self.files.len(), "file"))
+    }
+}
+
+/// Contents of a file before and after applying the fixes
+#[derive(Debug)]
+pub struct FixedFile {
+    pub path: PathBuf,
+    pub original: String,
+    pub fixed: String,
+
+    /// Encoding of the file, in which it's written back
+    pub encoding: Encoding,
+}
+
+impl FixedFile {
+    /// Unified diff of the changes, as printed by `diff -u`
+    pub fn unified_diff(&self) -> String {
+        let path = self.path.display().to_string();
+        TextDiff::from_lines(&self.original, &self.fixed)
+            .unified_diff()
+            .header(&path, &path)
+            .to_string()
+    }
+}
+
+/// Applies fixes from messages, optionally only those of given lints.
+///
+/// A fix is skipped when the file has changed since the analysis
+/// or when it overlaps with another fix.
+pub fn apply_fixes(messages: &[Message], lints: &[Lint]) -> Result<Applied, Error> {
+    let applied = preview_fixes(messages, lints)?;
+
+    for file in &applied.files {
+        fs::write(&file.path, file.encoding.encode(&file.fixed)).with_context(|_| format!("Writing {}", file.path.display()))?;
+    }
+
+    Ok(applied)
+}
+
+/// Computes the results of `apply_fixes`, without writing the files
+pub fn preview_fixes(messages: &[Message], lints: &[Lint]) -> Result<Applied, Error> {
+    let mut fixes_by_file: Map<&PathBuf, Vec<&Fix>> = Map::new();
+
+    for message in messages {
+        let fix = match &message.fix {
+            Some(fix) => fix,
+            None => continue,
+        };
+
+        if !lints.is_empty() && !lints.iter().any(|lint| lint.slug() == message.lint) {
+            continue;
+        }
+
+        fixes_by_file.entry(&message.file).or_default().push(fix);
+    }
+
+    let mut applied = Applied::default();
+
+    for (file, fixes) in fixes_by_file {
+        let (original, encoding) = ::read_text(file)?;
+        let mut source = original.clone();
+
+        // Offsets don't count the byte order mark
+        let bom_len = if source.starts_with('\u{feff}') { '\u{feff}'.len_utf8() } else { 0 };
+
+        let mut edits: Vec<(usize, usize, &str)> = Vec::new();
+
+        'fixes: for fix in fixes {
+            let mut fix_edits = Vec::new();
+
+            for edit in &fix.edits {
+                let start = bom_len + edit.span.start.byte as usize;
+                let end = bom_len + edit.span.end.byte as usize;
+
+                if source.get(start..end) != Some(&*edit.original) {
+                    applied.skipped.push(format!(
+                        "{}: file changed since the analysis, skipping `{}`", file.display(), fix.description
+                    ));
+                    continue 'fixes;
+                }
+
+                let overlaps = edits
+                    .iter()
+                    .chain(&fix_edits)
+                    .any(|&(other_start, other_end, _)| start < other_end && other_start < end);
+
+                if overlaps {
+                    applied.skipped.push(format!(
+                        "{}: overlapping fixes, skipping `{}`", file.display(), fix.description
+                    ));
+                    continue 'fixes;
+                }
+
+                fix_edits.push((start, end, &*edit.replacement));
+            }
+
+            edits.extend(fix_edits);
+            applied.applied += 1;
+        }
+
+        // Apply from the end, so the offsets stay valid
+        edits.sort_by_key(|&(start, _, _)| ::std::cmp::Reverse(start));
+        for (start, end, replacement) in edits {
+            source.replace_range(start..end, replacement);
+        }
+
+        if source != original {
+            applied.files.push(FixedFile { path: file.clone(), original, fixed: source, encoding });
+        }
+    }
+
+    Ok(applied)
+}
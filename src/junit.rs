@@ -0,0 +1,108 @@
+//! JUnit XML report, written by `--format junit`
+//!
+//! Every analyzed file is a test case, failed if it has any errors.
+//! Warnings don't fail the test case, but are listed in its output.
+
+use std::collections::BTreeMap as Map;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use {plural, EmittedItem, Emitter, MessageKind};
+
+/// Emitter collecting messages by file
+#[derive(Debug, Default)]
+pub struct JunitEmitter {
+    files: Map<PathBuf, Vec<EmittedItem>>,
+}
+
+impl JunitEmitter {
+    pub fn new() -> JunitEmitter {
+        JunitEmitter::default()
+    }
+
+    pub fn report(&self) -> String {
+        let is_error = |item: &&EmittedItem| item.kind == MessageKind::Error;
+        let failures = self.files
+            .values()
+            .filter(|items| items.iter().any(|item| is_error(&item)))
+            .count();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"shelly\" tests=\"{}\" failures=\"{}\" errors=\"0\">",
+            self.files.len(), failures,
+        );
+
+        for (file, items) in &self.files {
+            let _ = write!(xml, "    <testcase classname=\"shelly\" name=\"{}\"", escape(&file.display().to_string()));
+            if items.is_empty() {
+                xml.push_str("/>\n");
+                continue;
+            }
+            xml.push_str(">\n");
+
+            let (errors, warnings): (Vec<&EmittedItem>, Vec<&EmittedItem>) = items.iter().partition(is_error);
+
+            if !errors.is_empty() {
+                let _ = writeln!(
+                    xml,
+                    "      <failure message=\"{}\" type=\"shelly\">{}</failure>",
+                    plural(errors.len(), "error"), describe(&errors),
+                );
+            }
+            if !warnings.is_empty() {
+                let _ = writeln!(xml, "      <system-out>{}</system-out>", describe(&warnings));
+            }
+
+            xml.push_str("    </testcase>\n");
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+}
+
+impl Emitter for JunitEmitter {
+    fn emit(&mut self, item: EmittedItem) {
+        self.files.entry(item.location.file.clone()).or_default().push(item);
+    }
+
+    fn analyzed_file(&mut self, path: &Path) {
+        self.files.entry(path.to_owned()).or_default();
+    }
+}
+
+/// Messages in the `path:line:col: level[lint] message` form, followed by their notes
+fn describe(items: &[&EmittedItem]) -> String {
+    let mut text = String::new();
+    for item in items {
+        let position = item.location.span
+            .map(|span| format!(":{}:{}", span.start.line, span.start.col))
+            .unwrap_or_default();
+        let _ = writeln!(
+            text,
+            "{}{}: {} {}",
+            item.location.file.display(), position, item.heading(), item.message,
+        );
+        for note in &item.notes {
+            let _ = writeln!(text, "  = {}", note.message);
+        }
+    }
+    escape(&text)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&'  => escaped.push_str("&amp;"),
+            '<'  => escaped.push_str("&lt;"),
+            '>'  => escaped.push_str("&gt;"),
+            '"'  => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _    => escaped.push(c),
+        }
+    }
+    escaped
+}
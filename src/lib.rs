@@ -7,116 +7,783 @@ extern crate lazy_static;
 extern crate toml;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+#[cfg(feature = "testing")]
+extern crate tempdir;
+#[cfg(feature = "lsp")]
+extern crate lsp_types;
 extern crate unicase;
+extern crate similar;
+extern crate globset;
+#[cfg(feature = "pretty")]
 extern crate yansi;
 
 pub mod lint;
+pub mod baseline;
+pub mod formats;
+pub mod json;
+pub mod html;
+pub mod graph;
+pub mod grouped;
+pub mod junit;
+pub mod watch;
+mod builtins;
 mod config;
+mod deprecated;
+mod environment;
+mod filenames;
+mod imports;
+mod init;
+mod manifest;
 mod syntax;
+mod patterns;
 mod preprocess;
 mod scope;
+mod snapshot;
 mod strictness;
 mod testnames;
+mod timings;
+mod todos;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+#[cfg(feature = "pretty")]
+pub mod pretty;
 
 use walkdir::WalkDir;
 
 use failure::Error;
 use failure::ResultExt;
+use unicase::UniCase;
 
 use std::collections::BTreeMap as Map;
+use std::collections::VecDeque;
 use std::rc::Rc;
 use std::path::{Path, PathBuf};
+use std::fmt;
 use std::fs;
+use std::time::Instant;
 
 use lint::Lint;
+use timings::Timings;
 
-pub use config::ConfigFile;
+pub use config::{ConfigFile, ConfigValue, Source};
+pub use preprocess::{read_text, Encoding};
 
-pub fn run(root_path: impl AsRef<Path>, run_opt: RunOpt, emitter: &mut Emitter) -> Result<(), Error> {
-    run_(root_path.as_ref(), run_opt, emitter)
+pub fn run(root_path: impl AsRef<Path>, run_opt: RunOpt, emitter: &mut dyn Emitter) -> Result<Summary, Error> {
+    run_(root_path.as_ref(), run_opt, emitter, &mut |_, _, _| ())
 }
 
-fn run_(root_path: &Path, run_opt: RunOpt, raw_emitter: &mut Emitter) -> Result<(), Error> {
-    use preprocess::PreprocessOutput;
-
-    let config = load_config_from_dir(root_path).context("Loading shelly config")?;
-    let lint_config = lint::Config::from_config_file(&config)
-        .context("Loading lint levels config")?
-        .with_overrides(&run_opt.lint_overrides);
-
-    let mut emitter = lint::Emitter::new(raw_emitter, lint_config);
-
-    let mut files = Map::new();
-
-    for entry in WalkDir::new(root_path) {
+/// Files with their scopes, inspected after the scope analysis
+type Inspect<'i> = dyn FnMut(&Map<PathBuf, preprocess::Parsed>, &Map<&Path, scope::Scope>, &scope::Config) + 'i;
+
+fn run_(root_path: &Path, run_opt: RunOpt, raw_emitter: &mut dyn Emitter, inspect: &mut Inspect) -> Result<Summary, Error> {
+    use preprocess::{FileKind, PreprocessOutput};
+
+    let config = load_config(root_path, run_opt.config.as_deref()).context("Loading shelly config")?;
+    let lint_config = load_lint_config(&[(root_path, &config)], &run_opt)?;
+    let filenames_config = filenames::Config::from_config_file(&config)
+        .context("Loading file naming config")?;
+    let deprecated_config = deprecated::Config::from_config_file(&config)
+        .context("Loading deprecated and banned functions config")?;
+    // Otherwise, importers of the analyzed files may not be parsed
+    let all_files = run_opt.files.is_empty() && run_opt.dirs.is_empty();
+    let imports_config = imports::Config::from_config_file(&config, root_path)
+        .context("Loading imports config")?
+        .with_all_files(all_files);
+    let strictness_config = strictness::Config::from_config_file(&config, root_path)
+        .context("Loading strictness config")?;
+    let environment_config = environment::Config::from_config_file(&config);
+    let todos_config = todos::Config::from_config_file(&config)
+        .context("Loading comment markers")?;
+    let config_dir = config_dir(root_path, run_opt.config.as_deref());
+    let preprocess_config = preprocess::Config::from_config_file(&config, config_dir)
+        .context("Loading long-lines options")?;
+    let testnames_config = testnames::Config::from_config_file(&config)
+        .context("Loading testname conventions")?;
+    let patterns_config = patterns::Config::from_config_file(&config)
+        .context("Loading custom patterns")?;
+
+    let config_excludes = config.files.as_ref().and_then(|files| files.exclude.as_ref());
+    let mut excludes = vec![(
+        root_path.to_owned(),
+        load_globs(config_excludes.into_iter().flatten().chain(&run_opt.exclude))
+            .context("Loading exclude patterns")?,
+    )];
+    let kinds = analyzed_kinds(&config)?;
+    let baseline = baseline::Baseline::load(root_path).context("Loading baseline")?;
+
+    let mut emitter = lint::Emitter::new(raw_emitter, lint_config).with_baseline(baseline);
+    let mut timings = Timings::new(run_opt.timings);
+
+    let start = Instant::now();
+    let mut paths = Vec::new();
+    let mut nested_config_paths = Vec::new();
+
+    let traversal = config.traversal.as_ref();
+    let skip_hidden = traversal.and_then(|traversal| traversal.skip_hidden).unwrap_or(false);
+    let max_depth = traversal.and_then(|traversal| traversal.max_depth).unwrap_or(usize::MAX);
+    let skip: Vec<&str> = match traversal.and_then(|traversal| traversal.skip.as_ref()) {
+        Some(skip) => skip.iter().map(|pattern| &**pattern).collect(),
+        None => config::DEFAULT_SKIP.to_vec(),
+    };
+
+    // Files outside of the analyzed directories are still
+    // collected, to be parsed as dependencies when imported
+    let dirs = analyzed_dirs(root_path, &run_opt.dirs)?;
+
+    // Sorted, so the order of files (and messages) doesn't depend on the platform
+    let walk = WalkDir::new(root_path)
+        .max_depth(max_depth)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|entry| !(skip_hidden && is_hidden_dir(entry)));
+
+    for entry in walk {
         let entry = entry.context("traversing")?;
-        if entry.path().to_str().unwrap_or("").contains("_Old_Tests") {
+        let path = entry.path().to_str().unwrap_or("");
+        if skip.iter().any(|pattern| path.contains(pattern)) {
             continue;
         }
         if !entry.file_type().is_file() {
             continue;
         }
-        if entry.path().extension().and_then(|ext| ext.to_str()) != Some("ps1") {
+        let is_config = CONFIG_FILE_NAMES.iter().any(|&name| entry.file_name() == name);
+        if is_config && entry.path().parent() != Some(root_path) {
+            nested_config_paths.push(entry.into_path());
             continue;
         }
+        if let Some(kind) = FileKind::from_path(entry.path()) {
+            paths.push((entry.into_path(), kind));
+        }
+    }
+
+    // Configs of subdirectories override the ones of their parents,
+    // so they're sorted by depth
+    let mut nested_configs = Vec::new();
+    for config_path in nested_config_paths {
+        let nested_config = read_config(&config_path)
+            .with_context(|_| format!("Loading {}", config_path.display()))?;
+        let dir = config_path.parent().unwrap_or(root_path).to_owned();
+        nested_configs.push((dir, nested_config));
+    }
+    nested_configs.sort_by_key(|(dir, _)| dir.components().count());
+
+    for (i, (dir, nested_config)) in nested_configs.iter().enumerate() {
+        let mut configs = vec![(root_path, &config)];
+        configs.extend(
+            nested_configs[..=i].iter()
+                .filter(|(parent, _)| dir.starts_with(parent))
+                .map(|(parent, config)| (&**parent, config))
+        );
+        let lint_config = load_lint_config(&configs, &run_opt)
+            .with_context(|_| format!("Loading lint levels of {}", dir.display()))?;
+        emitter.add_nested_config(dir, lint_config);
+
+        let patterns = nested_config.files.as_ref().and_then(|files| files.exclude.as_ref());
+        let nested_excludes = load_globs(patterns.into_iter().flatten())
+            .with_context(|_| format!("Loading exclude patterns of {}", dir.display()))?;
+        excludes.push((dir.clone(), nested_excludes));
+    }
+
+    // Exclude patterns are relative to the directory of their config
+    let is_excluded = |path: &Path| excludes.iter().any(|(dir, excludes)| {
+        path.strip_prefix(dir).is_ok_and(|relative_path| excludes.is_match(relative_path))
+    });
+
+    // Files from external roots are only parsed when imported
+    let mut external_paths = Vec::new();
+    for external_root in config.paths.iter().flat_map(|paths| &paths.external_roots).flatten() {
+        let external_root = config_dir.join(external_root);
+        if !external_root.is_dir() {
+            bail!("External root not found: {}", external_root.display());
+        }
+        let walk = WalkDir::new(&external_root).sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        for entry in walk {
+            let entry = entry.context("traversing external root")?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if let Some(kind) = FileKind::from_path(entry.path()) {
+                external_paths.push((entry.into_path(), kind));
+            }
+        }
+    }
 
-        match preprocess::parse_and_preprocess(entry.path(), &run_opt, &mut emitter)? {
+    // Modules of the analyzed tree take precedence over external ones
+    let mut modules = find_modules(&paths)?;
+    for (name, path) in find_modules(&external_paths)? {
+        modules.entry(name).or_insert(path);
+    }
+
+    timings.phase("walking", start);
+
+    let start = Instant::now();
+    let mut files = Map::new();
+
+    // With explicit files or directories, the rest is parsed on demand,
+    // when imported. Same for excluded files and ones with not analyzed
+    // extensions (unless given explicitly).
+    let mut unselected = Map::new();
+    let canonical_root = root_path.canonicalize()?;
+    for (path, kind) in external_paths {
+        let canonical_path = path.canonicalize()?;
+        if !canonical_path.starts_with(&canonical_root) {
+            unselected.insert(canonical_path, (path, kind));
+        }
+    }
+    let mut queue = VecDeque::new();
+    let is_selected = |path: &Path| run_opt.files.is_empty() && dirs.iter().any(|dir| path.starts_with(dir));
+    for (path, kind) in paths {
+        if is_excluded(&path) || !kinds.contains(&kind) || !is_selected(&path) {
+            unselected.insert(path.canonicalize()?, (path, kind));
+        } else {
+            queue.push_back((path, kind));
+        }
+    }
+    for path in &run_opt.files {
+        let canonical_path = path.canonicalize()
+            .with_context(|_| format!("Opening {}", path.display()))?;
+        let file = match unselected.remove(&canonical_path) {
+            Some(file) => file,
+            None => match FileKind::from_path(path) {
+                Some(kind) => (path.clone(), kind),
+                None => bail!("{} is not a PowerShell file", path.display()),
+            },
+        };
+        queue.push_back(file);
+    }
+
+    // Preludes are implicitly imported by every file.
+    // Missing ones are reported when loading the scope config.
+    for prelude in config.extras.iter().flat_map(|extras| &extras.preludes).flatten() {
+        let prelude = root_path.join(prelude).canonicalize().ok();
+        if let Some((path, kind)) = prelude.and_then(|prelude| unselected.remove(&prelude)) {
+            emitter.add_dependency(&path);
+            queue.push_back((path, kind));
+        }
+    }
+
+    while let Some((path, kind)) = queue.pop_front() {
+        emitter.analyzed_file(&path);
+
+        let file_start = Instant::now();
+        let output = match kind {
+            FileKind::Manifest => preprocess::parse_manifest(&path, &mut emitter)?,
+            _ => preprocess::parse_and_preprocess(&path, &modules, &preprocess_config, &run_opt, &mut emitter)?,
+        };
+        timings.file(&path, file_start);
+
+        match output {
             PreprocessOutput::Valid(mut parsed) => {
-                let path = entry.path().canonicalize()?;
+                let canonical_path = path.canonicalize()?;
 
-                strictness::preprocess(&mut parsed);
+                patterns::preprocess(&mut parsed, &patterns_config);
+                strictness::preprocess(&mut parsed, &strictness_config);
 
-                files.insert(path, parsed);
-            }
-            PreprocessOutput::InvalidImports => {
-                eprintln!(
-                    "Stopping analysis for this file because of import errors: {}\n",
-                    entry.path().display()
-                );
+                let imports: Vec<&Path> = parsed.imports.keys().map(|import| &**import).collect();
+                emitter.parsed_imports(&canonical_path, &imports);
+                for (import, info) in &parsed.imports {
+                    emitter.parsed_import(&canonical_path, import, &info.span.in_file(&parsed));
+                }
+
+                for import in parsed.imports.keys() {
+                    if let Some((path, kind)) = unselected.remove(import) {
+                        emitter.add_dependency(&path);
+                        queue.push_back((path, kind));
+                    }
+                }
+
+                files.insert(canonical_path, *parsed);
             }
             PreprocessOutput::SyntaxErrors => {
                 eprintln!(
                     "Stopping analysis for this file because of syntax errors: {}\n",
-                    entry.path().display()
+                    path.display()
                 );
             }
         };
     }
 
-    let scopes = scope::analyze(&files, &config, &mut emitter).context("analyzing")?;
+    timings.phase("parsing", start);
+
+    let start = Instant::now();
+    let mut commands = builtins::load_lockfile(root_path, &mut emitter)
+        .with_context(|_| format!("Loading {}", builtins::LOCKFILE))?;
+    commands.extend(builtins::load_builtins_files(&config, config_dir)?);
+    let nested_commands = nested_configs.iter()
+        .map(|(dir, nested_config)| builtins::load_builtins_files(nested_config, dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut scope_config = scope::Config::from_config_file(&config, &commands, root_path)
+        .context("Loading scope config")?
+        .with_target(target_version(&config, &run_opt)?);
+    for ((dir, nested_config), commands) in nested_configs.iter().zip(&nested_commands) {
+        scope_config = scope_config.with_nested(dir.canonicalize()?, nested_config, commands)
+            .with_context(|_| format!("Loading extras of {}", dir.display()))?;
+    }
+    timings.phase("loading builtins", start);
+
+    // Skip the passes whose lints are all allowed.
+    // Strictness analysis needs the scopes too.
+    let enabled = |lints| emitter.any_enabled(lints);
+    let run_scope = enabled(scope::LINTS) || enabled(strictness::LINTS);
+    let run_strictness = enabled(strictness::LINTS);
+    let run_testnames = enabled(testnames::LINTS);
+    let run_filenames = enabled(filenames::LINTS);
+    let run_deprecated = enabled(deprecated::LINTS);
+    let run_imports = enabled(imports::LINTS);
+    let run_environment = enabled(environment::LINTS);
+    let run_todos = enabled(todos::LINTS);
+
+    // Orphaned files are required to enable strict mode, unless reported as unused
+    let strictness_config = strictness_config
+        .with_unused_files(all_files && enabled(&[Lint::UnusedFiles]));
+
+    let start = Instant::now();
+    let scopes = if run_scope {
+        scope::analyze(&files, &scope_config, &mut emitter).context("analyzing")?
+    } else {
+        Map::new()
+    };
+    timings.phase("scope", start);
+
+    inspect(&files, &scopes, &scope_config);
+
+    if run_strictness {
+        let start = Instant::now();
+        strictness::analyze(&files, &scopes, &strictness_config, &mut emitter);
+        timings.phase("strictness", start);
+    }
+    if run_testnames {
+        let start = Instant::now();
+        testnames::analyze(&files, &testnames_config, &mut emitter);
+        timings.phase("testnames", start);
+    }
+    if run_filenames {
+        let start = Instant::now();
+        filenames::analyze(&files, &filenames_config, &mut emitter);
+        timings.phase("filenames", start);
+    }
+    if run_deprecated {
+        let start = Instant::now();
+        deprecated::analyze(&files, &deprecated_config, &mut emitter);
+        timings.phase("deprecated", start);
+    }
+    if run_imports {
+        let start = Instant::now();
+        imports::analyze(&files, &imports_config, &mut emitter);
+        timings.phase("imports", start);
+    }
+    if run_environment {
+        let start = Instant::now();
+        environment::analyze(&files, &environment_config, &mut emitter);
+        timings.phase("environment", start);
+    }
+    if run_todos {
+        let start = Instant::now();
+        todos::analyze(&files, &todos_config, &mut emitter);
+        timings.phase("todos", start);
+    }
+
+    let summary = emitter.finish();
+    timings.report();
+
+    Ok(summary)
+}
+
+/// Analyzed directories: the root or, if given, the directories inside of it
+/// (as paths under the root, skipping the ones nested in other given ones)
+fn analyzed_dirs(root_path: &Path, dirs: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    if dirs.is_empty() {
+        return Ok(vec![root_path.to_owned()]);
+    }
+
+    let canonical_root = root_path.canonicalize()?;
+    let mut relative_dirs = Vec::new();
+    for dir in dirs {
+        let canonical_dir = dir.canonicalize().with_context(|_| format!("Opening {}", dir.display()))?;
+        match canonical_dir.strip_prefix(&canonical_root) {
+            Ok(relative_dir) => relative_dirs.push(relative_dir.to_owned()),
+            Err(_) => bail!("{} is not inside of the analyzed directory {}", dir.display(), root_path.display()),
+        }
+    }
+
+    relative_dirs.sort();
+    relative_dirs.dedup();
+    let outermost: Vec<&PathBuf> = relative_dirs.iter()
+        .filter(|&dir| !relative_dirs.iter().any(|other| other != dir && dir.starts_with(other)))
+        .collect();
+
+    Ok(outermost.into_iter()
+        .map(|dir| if dir.as_os_str().is_empty() { root_path.to_owned() } else { root_path.join(dir) })
+        .collect())
+}
+
+/// Checks whether the entry is a directory starting with a dot, eg. `.git`.
+/// The root is never considered hidden.
+fn is_hidden_dir(entry: &walkdir::DirEntry) -> bool {
+    entry.depth() > 0
+        && entry.file_type().is_dir()
+        && entry.file_name().to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+/// Finds modules, which are importable by the name of their manifest
+/// or, when there's no manifest, of their .psm1 file.
+fn find_modules(paths: &[(PathBuf, preprocess::FileKind)]) -> Result<preprocess::Modules, Error> {
+    use preprocess::FileKind;
+
+    let mut modules = preprocess::Modules::new();
+    for &(ref path, kind) in paths {
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(name) => UniCase::new(name.to_owned()),
+            None => continue,
+        };
+        match kind {
+            FileKind::Manifest => { modules.insert(name, path.canonicalize()?); }
+            FileKind::Module   => { modules.entry(name).or_insert(path.canonicalize()?); }
+            FileKind::Script   => (),
+        }
+    }
+
+    Ok(modules)
+}
+
+/// Builds a matcher of path globs, eg. `[files] exclude` patterns.
+/// `*` doesn't match path separators, `**` does.
+fn load_globs<'a>(patterns: impl Iterator<Item=&'a String>) -> Result<globset::GlobSet, Error> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = globset::GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|_| format!("Invalid pattern {:?}", pattern))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// Kinds of files with `[files] extensions`, all of them by default
+fn analyzed_kinds(config: &ConfigFile) -> Result<Vec<preprocess::FileKind>, Error> {
+    use preprocess::FileKind;
+
+    let extensions = match config.files.as_ref().and_then(|files| files.extensions.as_ref()) {
+        Some(extensions) => extensions,
+        None => return Ok(vec![FileKind::Script, FileKind::Module, FileKind::Manifest]),
+    };
+
+    extensions.iter()
+        .map(|extension| {
+            FileKind::from_extension(extension.trim_start_matches('.'))
+                .ok_or_else(|| format_err!("Unknown extension {:?}, expected one of ps1, psm1, psd1", extension))
+        })
+        .collect()
+}
+
+/// Lists all commandlets assumed to exist in a given directory:
+/// shipped builtins, extras from config and `builtins.lock`.
+pub fn dump_builtins(root_path: impl AsRef<Path>, config_path: Option<&Path>, raw_emitter: &mut dyn Emitter)
+    -> Result<Vec<String>, Error>
+{
+    let root_path = root_path.as_ref();
+
+    let config = load_config(root_path, config_path).context("Loading shelly config")?;
+    let lint_config = lint::Config::from_config_file(&config)
+        .context("Loading lint levels config")?;
+
+    let mut emitter = lint::Emitter::new(raw_emitter, lint_config);
+
+    let mut commands = builtins::load_lockfile(root_path, &mut emitter)
+        .with_context(|_| format!("Loading {}", builtins::LOCKFILE))?;
+    commands.extend(builtins::load_builtins_files(&config, config_dir(root_path, config_path))?);
+    let scope_config = scope::Config::from_config_file(&config, &commands, root_path)
+        .context("Loading scope config")?
+        .with_target(target_version(&config, &RunOpt::default())?);
+
+    emitter.finish();
+
+    let shipped = builtins::SHIPPED.iter().filter(|item| scope_config.is_shipped(item)).map(|item| *item.name);
+
+    let mut cmdlets = Map::new();
+    for cmdlet in shipped.chain(scope_config.custom_cmdlets()) {
+        cmdlets.entry(UniCase::new(cmdlet)).or_insert(cmdlet);
+    }
+
+    Ok(cmdlets.values().map(|&cmdlet| cmdlet.to_owned()).collect())
+}
+
+/// Converts a `Get-Command` snapshot (JSON or CSV) to a builtins file,
+/// to be listed in `extras.builtins_files`
+pub fn import_builtins(snapshot: &str) -> Result<String, Error> {
+    snapshot::import_builtins(snapshot)
+}
+
+/// Renders a starter `shelly.toml` for a given directory,
+/// with known modules and commandlets detected by analyzing it
+pub fn starter_config(root_path: impl AsRef<Path>) -> Result<String, Error> {
+    init::starter_config(root_path.as_ref())
+}
+
+/// Where a function used in a file comes from, as explained by `shelly why`
+#[derive(Debug)]
+pub enum Origin {
+    /// Defined in the file itself or in a file it (possibly indirectly) imports
+    Defined {
+        /// Original paths of files through which the function is imported,
+        /// starting with the queried file and ending with the defining one
+        chain: Vec<PathBuf>,
+
+        /// Import statement for each link of the chain,
+        /// `None` for preludes, which are imported implicitly
+        imports: Vec<Option<Location>>,
+
+        definition: Location,
+    },
 
-    strictness::analyze(&files, &scopes, &mut emitter);
-    testnames::analyze(&files, &mut emitter);
+    /// Assumed to exist, for a given reason
+    Assumed(&'static str),
 
-    Ok(())
+    NotInScope,
+}
+
+/// Explains how a function gets into scope of a file in a given directory
+pub fn why(root_path: impl AsRef<Path>, run_opt: RunOpt, file: &Path, function: &str) -> Result<Origin, Error> {
+    let canonical_file = file.canonicalize().with_context(|_| format!("Opening {}", file.display()))?;
+
+    // Scopes are computed only when some of their lints are enabled
+    let run_opt = RunOpt {
+        only: vec![Lint::UnknownFunctions],
+        lint_overrides: Some((Lint::UnknownFunctions, lint::Level::Warn)).into_iter().collect(),
+        files: vec![file.to_owned()],
+        ..run_opt
+    };
+
+    let mut origin = None;
+    run_(root_path.as_ref(), run_opt, &mut VecEmitter::new(), &mut |files, scopes, config| {
+        let path = match files.get_key_value(&canonical_file) {
+            Some((path, _)) => path,
+            None => return,
+        };
+        let item = syntax::Item::function(function);
+        origin = Some(match scope::explain(path, &item, files, scopes, config) {
+            scope::Explanation::Imported(chain, definition) => Origin::Defined {
+                imports: chain.windows(2)
+                    .map(|link| files[link[0]].imports.get(link[1]).map(|import| import.span.in_file(&files[link[0]])))
+                    .collect(),
+                definition: definition.span.in_file(&files[*chain.last().unwrap()]),
+                chain: chain.iter().map(|path| files[*path].original_path.clone()).collect(),
+            },
+            scope::Explanation::Assumed(reason) => Origin::Assumed(reason),
+            scope::Explanation::NotInScope => Origin::NotInScope,
+        });
+    })?;
+
+    origin.ok_or_else(|| format_err!("{} could not be analyzed", file.display()))
+}
+
+/// Resolves the configuration of a given directory, as used
+/// by `run` with given options, and where each value comes from.
+pub fn effective_config(root_path: impl AsRef<Path>, run_opt: &RunOpt) -> Result<Vec<ConfigValue>, Error> {
+    let config = load_config(root_path.as_ref(), run_opt.config.as_deref()).context("Loading shelly config")?;
+    let lint_config = load_lint_config(&[(root_path.as_ref(), &config)], run_opt)?;
+    // Fail on invalid lint options, as `run` would
+    testnames::Config::from_config_file(&config).context("Loading testname conventions")?;
+    preprocess::max_line_length(&config).context("Loading long-lines options")?;
+
+    Ok(config::effective_values(&config, &lint_config, run_opt))
+}
+
+/// Loads lint levels from the root config and nested ones (each overriding the previous),
+/// with their directories, overridden by the command line options
+fn load_lint_config(configs: &[(&Path, &ConfigFile)], run_opt: &RunOpt) -> Result<lint::Config, Error> {
+    let mut lint_config = lint::Config::from_config_file(configs[0].1)
+        .context("Loading lint levels config")?;
+    for &(_, nested_config) in &configs[1..] {
+        lint_config = lint_config.with_levels_from(nested_config)?;
+    }
+    for &(dir, config) in configs {
+        lint_config = lint_config.with_path_overrides_from(dir, config)?;
+    }
+
+    Ok(lint_config
+        .with_overrides(&run_opt.lint_overrides)
+        .with_deny_warnings(run_opt.deny_warnings)
+        .with_max_per_lint(run_opt.max_per_lint)
+        .with_only(&run_opt.only)
+        .with_no_default_lints(run_opt.no_default_lints))
 }
 
 #[derive(Default)]
 pub struct RunOpt {
     pub debug_parser: bool,
     pub lint_overrides: Map<lint::Lint, lint::Level>,
+
+    /// Promote all warnings to errors
+    pub deny_warnings: bool,
+
+    /// Maximal number of messages of a single lint in a single file
+    pub max_per_lint: Option<usize>,
+
+    /// Run only these lints (all if empty)
+    pub only: Vec<lint::Lint>,
+
+    /// Allow lints whose levels are not set explicitly
+    pub no_default_lints: bool,
+
+    /// Print time spent in each phase of the analysis on stderr
+    pub timings: bool,
+
+    /// Analyze only these files (all if empty).
+    /// Files they import are parsed too, but not reported on.
+    pub files: Vec<PathBuf>,
+
+    /// Analyze only these directories (the whole root if empty), as one unit.
+    /// Files they import from the rest of the root are parsed too, but not reported on.
+    pub dirs: Vec<PathBuf>,
+
+    /// Config file to use instead of the one in the analyzed directory
+    pub config: Option<PathBuf>,
+
+    /// Glob patterns of files not to analyze, in addition to the ones from config
+    pub exclude: Vec<String>,
+
+    /// Version of PowerShell the scripts are run with, overriding `target` from config
+    pub ps_version: Option<String>,
+}
+
+/// Targeted PowerShell version, from `--ps-version` or `target` in config
+fn target_version(config: &ConfigFile, run_opt: &RunOpt) -> Result<Option<builtins::PsVersion>, Error> {
+    run_opt.ps_version.as_ref().or(config.target.as_ref())
+        .map(|target| target.parse())
+        .transpose()
+}
+
+/// Loads a given config file or, if there's none, the one in the directory
+pub fn load_config(dir_path: &Path, config_path: Option<&Path>) -> Result<ConfigFile, Error> {
+    match config_path {
+        Some(config_path) => read_config(config_path),
+        None => load_config_from_dir(dir_path),
+    }
+}
+
+/// Directory of the config, which paths in the config are relative to:
+/// the one of the file given by `--config` or the analyzed one
+fn config_dir<'a>(root_path: &'a Path, config_path: Option<&'a Path>) -> &'a Path {
+    match config_path.and_then(Path::parent) {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => root_path,
+    }
+}
+
+/// Names of config files, in the analyzed directory or its subdirectories
+const CONFIG_FILE_NAMES: &[&str] = &["shelly.toml", "Shelly.toml"];
+
+fn read_config(config_path: &Path) -> Result<ConfigFile, Error> {
+    let config_str = fs::read_to_string(config_path)
+        .with_context(|_| format!("Reading {}", config_path.display()))?;
+    parse_config(&config_str)
+}
+
+fn parse_config(config_str: &str) -> Result<ConfigFile, Error> {
+    let config: ConfigFile = config_str.parse()?;
+    config.check_lint_options()?;
+    Ok(config)
 }
 
 pub fn load_config_from_dir(dir_path: &Path) -> Result<ConfigFile, Error> {
-    for &filename in &["shelly.toml", "Shelly.toml"] {
+    for &filename in CONFIG_FILE_NAMES {
         let config_path = dir_path.join(filename);
         if config_path.exists() {
             let config_str = fs::read_to_string(config_path)?;
-            return Ok(config_str.parse()?);
+            return parse_config(&config_str);
         }
     }
     Ok(ConfigFile::default())
 }
 
+/// Numbers of messages reported by `run`, eg. to decide whether the analysis failed.
+///
+/// Messages omitted because of `max_per_lint` are counted too.
+///
+/// Displayed as `2 errors, 3 warnings across 10 files (unknown-functions: 2, ...)`.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Summary {
+    pub errors: usize,
+    pub warnings: usize,
+
+    /// Number of analyzed files
+    pub files: usize,
+
+    /// Numbers of messages of each lint
+    pub lints: Map<Lint, usize>,
+}
+
+impl Summary {
+    fn add(&mut self, lint: Lint, kind: MessageKind) {
+        match kind {
+            MessageKind::Error   => self.errors += 1,
+            MessageKind::Warning => self.warnings += 1,
+        }
+        *self.lints.entry(lint).or_insert(0) += 1;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}, {} across {}",
+            plural(self.errors, "error"),
+            plural(self.warnings, "warning"),
+            plural(self.files, "file"),
+        )?;
+
+        if !self.lints.is_empty() {
+            let mut lints: Vec<_> = self.lints.iter().collect();
+            lints.sort_by_key(|&(_, &count)| ::std::cmp::Reverse(count));
+            let lints: Vec<_> = lints.iter().map(|(lint, count)| format!("{}: {}", lint.slug(), count)).collect();
+            write!(f, " ({})", lints.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Count with a noun in singular or plural, eg. `1 file` or `2 fixes`
+pub fn plural(count: usize, noun: &str) -> String {
+    let ending = if count == 1 {
+        ""
+    } else if ["s", "x", "ch", "sh"].iter().any(|ending| noun.ends_with(ending)) {
+        "es"
+    } else {
+        "s"
+    };
+
+    match noun.strip_suffix('y') {
+        Some(stem) if count != 1 && !stem.ends_with(['a', 'e', 'o', 'u']) => format!("{} {}ies", count, stem),
+        _ => format!("{} {}{}", count, noun, ending),
+    }
+}
+
 /// Kind of error message
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
 pub enum MessageKind {
     Warning,
+    #[default]
     Error,
 }
 
-impl Default for MessageKind {
-    fn default() -> MessageKind { MessageKind::Error }
+impl MessageKind {
+    /// Lowercase name of the kind, as printed in the output
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageKind::Warning => "warning",
+            MessageKind::Error   => "error",
+        }
+    }
 }
 
 pub use syntax::Span;
@@ -159,17 +826,75 @@ impl Span {
 
 pub trait Emitter {
     fn emit(&mut self, item: EmittedItem);
+
+    /// Called for every analyzed file, before any of its messages
+    fn analyzed_file(&mut self, _path: &Path) {}
+
+    /// Called for every successfully parsed file (including the ones
+    /// parsed only as dependencies) with canonical paths of the file
+    /// and of the files it imports
+    fn parsed_imports(&mut self, _path: &Path, _imports: &[&Path]) {}
+
+    /// Called for every import of a successfully parsed file, with
+    /// canonical paths of the file and of the imported file and
+    /// the location of the import statement
+    fn parsed_import(&mut self, _path: &Path, _import: &Path, _location: &Location) {}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EmittedItem {
     pub lint: Lint,
+
+    /// Category of the lint, for grouping the messages
+    pub category: lint::Category,
+
+    /// Link to the explanation of the lint
+    pub doc_url: &'static str,
+
     pub kind: MessageKind,
     pub message: String,
     pub location: Location,
-    pub notes: Option<String>,
+    pub notes: Vec<Note>,
+    pub fix: Option<Fix>,
+}
+
+/// Additional information attached to a message
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub message: String,
+
+    /// Location the note refers to, eg. a definition in another file
+    pub location: Option<Location>,
+}
+
+/// A machine-applicable change fixing the problem
+#[derive(Debug, Clone)]
+pub struct Fix {
+    /// Short description, eg. "Replace with `Get-CimInstance`"
+    pub description: String,
+
+    /// Edits in the file of the message's location
+    pub edits: Vec<Edit>,
+}
+
+/// Replacement of a fragment of a file
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
 }
 
+impl EmittedItem {
+    /// Heading of the message, eg. `warning[unused-imports]`.
+    ///
+    /// Textual emitters should use it, so the lint name
+    /// is always presented in the same way.
+    pub fn heading(&self) -> String {
+        format!("{}[{}]", self.kind.name(), self.lint.slug())
+    }
+}
+
+#[derive(Default)]
 pub struct VecEmitter {
     pub emitted_items: Vec<EmittedItem>,
 }
@@ -178,6 +903,44 @@ impl VecEmitter {
     pub fn new() -> VecEmitter {
         VecEmitter { emitted_items: Vec::new() }
     }
+
+    pub fn len(&self) -> usize {
+        self.emitted_items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.emitted_items.is_empty()
+    }
+
+    /// Lints of all emitted messages, in order of emission
+    pub fn lints(&self) -> Vec<Lint> {
+        self.emitted_items.iter().map(|item| item.lint).collect()
+    }
+
+    /// Messages of a given lint
+    pub fn only(&self, lint: Lint) -> Vec<&EmittedItem> {
+        self.emitted_items.iter().filter(|item| item.lint == lint).collect()
+    }
+
+    /// Messages located in a file, matched by the ending of its path
+    pub fn in_file(&self, file: impl AsRef<Path>) -> Vec<&EmittedItem> {
+        self.emitted_items.iter().filter(|item| item.location.file.ends_with(file.as_ref())).collect()
+    }
+
+    /// Panics unless there's a message of a given lint
+    /// containing `message` in its text or in one of its notes.
+    pub fn assert_contains(&self, lint: Lint, message: &str) {
+        let found = self.only(lint).iter().any(|item| {
+            item.message.contains(message) || item.notes.iter().any(|note| note.message.contains(message))
+        });
+
+        if !found {
+            panic!(
+                "no {} message containing {:?}, emitted: {:#?}",
+                lint.slug(), message, self.emitted_items
+            );
+        }
+    }
 }
 
 impl Emitter for VecEmitter {
@@ -185,3 +948,44 @@ impl Emitter for VecEmitter {
         self.emitted_items.push(to_emit)
     }
 }
+
+#[test]
+fn test_heading() {
+    let dir = ::testing::setup_dir(&[
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Foo.ps1\nGet-Bar\n"),
+    ]).unwrap();
+
+    let errors = ::testing::test_dir(dir.path());
+    let headings: Vec<_> = errors.emitted_items.iter().map(EmittedItem::heading).collect();
+    assert_eq!(headings, ["error[unknown-functions]", "warning[unused-imports]"]);
+}
+
+#[test]
+fn test_plural() {
+    assert_eq!(plural(1, "file"), "1 file");
+    assert_eq!(plural(0, "file"), "0 files");
+    assert_eq!(plural(2, "fix"), "2 fixes");
+    assert_eq!(plural(1, "entry"), "1 entry");
+    assert_eq!(plural(3, "entry"), "3 entries");
+    assert_eq!(plural(2, "key"), "2 keys");
+}
+
+#[test]
+fn test_summary() {
+    let mut summary = Summary { files: 1, ..Summary::default() };
+    assert_eq!(summary.to_string(), "0 errors, 0 warnings across 1 file");
+
+    summary.add(Lint::UnknownFunctions, MessageKind::Error);
+    summary.add(Lint::UnusedImports, MessageKind::Warning);
+    assert_eq!(summary.to_string(), "1 error, 1 warning across 1 file (unknown-functions: 1, unused-imports: 1)");
+
+    // Most frequent lints first, ties in the order of lints
+    summary.files = 2;
+    summary.add(Lint::UnusedImports, MessageKind::Warning);
+    summary.add(Lint::NoStrictMode, MessageKind::Warning);
+    assert_eq!(
+        summary.to_string(),
+        "1 error, 3 warnings across 2 files (unused-imports: 2, unknown-functions: 1, no-strict-mode: 1)",
+    );
+}
@@ -1,16 +1,25 @@
 use std::collections::BTreeMap as Map;
+use std::borrow::Cow;
 use std::collections::BTreeSet as Set;
 use std::str::FromStr;
 use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use regex::Regex;
 use failure;
+use globset;
 
 use EmittedItem;
+use Fix;
+use Summary;
+use Note;
 use Location;
 use Span;
 use MessageKind;
 use ConfigFile;
+use baseline::Baseline;
+use config::Source;
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
 pub enum Level {
@@ -35,8 +44,81 @@ impl FromStr for Level {
     }
 }
 
+/// Group of related lints
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+pub enum Category {
+    /// Code likely failing or behaving unexpectedly at runtime
+    Correctness,
+
+    /// Naming and formatting conventions
+    Style,
+
+    /// Dot-imports and module imports
+    Imports,
+
+    /// Pester tests
+    Pester,
+
+    /// Functions which shouldn't be called at all
+    Security,
+}
+
+impl Category {
+    /// Lowercase name of the category, as printed in the output
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Correctness => "correctness",
+            Category::Style       => "style",
+            Category::Imports     => "imports",
+            Category::Pester      => "pester",
+            Category::Security    => "security",
+        }
+    }
+
+    pub fn categories() -> impl Iterator<Item=Category> {
+        [
+            Category::Correctness,
+            Category::Style,
+            Category::Imports,
+            Category::Pester,
+            Category::Security,
+        ].iter().cloned()
+    }
+
+    /// Lints belonging to this category
+    pub fn lints(self) -> impl Iterator<Item=Lint> {
+        Lint::lints().filter(move |lint| lint.category() == self)
+    }
+}
+
+/// Lint or a whole category of lints, as accepted by `-A`/`-W`/`-D` and `[levels]`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Selector {
+    Lint(Lint),
+    Category(Category),
+}
+
+impl Selector {
+    pub fn lints(self) -> Vec<Lint> {
+        match self {
+            Selector::Lint(lint) => vec![lint],
+            Selector::Category(category) => category.lints().collect(),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = UnknownLint;
+    fn from_str(s: &str) -> Result<Selector, UnknownLint> {
+        match Category::categories().find(|category| category.name() == s) {
+            Some(category) => Ok(Selector::Category(category)),
+            None => s.parse().map(Selector::Lint),
+        }
+    }
+}
+
 macro_rules! lints {
-    ( $( #[$attr:meta] $name:ident : $slug:tt => $level:ident ),+ $(,)* ) => {
+    ( $( #[$attr:meta] $name:ident : $slug:tt => $level:ident in $category:ident ),+ $(,)* ) => {
 
         /// Lint is a type of error or warning that a linter can emit
         #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
@@ -57,6 +139,19 @@ macro_rules! lints {
                 }
             }
 
+            pub fn category(&self) -> Category {
+                match self {
+                    $( Lint::$name => Category::$category ),+
+                }
+            }
+
+            /// Link to the explanation of the lint in `docs/lints.md`
+            pub fn doc_url(&self) -> &'static str {
+                match self {
+                    $( Lint::$name => concat!("https://github.com/krdln/shelly/blob/master/docs/lints.md#", $slug) ),+
+                }
+            }
+
             pub fn lints() -> impl Iterator<Item=Lint> {
                 [ $( Lint::$name ),+ ].iter().cloned()
             }
@@ -79,34 +174,106 @@ macro_rules! lints {
 
 lints!{
     /// Imported file not found
-    NonexistingImports: "nonexisting-imports" => Deny,
+    NonexistingImports: "nonexisting-imports" => Deny in Imports,
+
+    /// Function exported by a module manifest not defined in its modules
+    NonexistingExports: "nonexisting-exports" => Warn in Imports,
+
+    /// Import creating a cycle of imports
+    RecursiveImports: "recursive-imports" => Deny in Imports,
 
     /// Import in an unrecognized form
-    UnrecognizedImports: "unrecognized-imports" => Warn,
+    UnrecognizedImports: "unrecognized-imports" => Warn in Imports,
 
     /// Function not in scope
-    UnknownFunctions: "unknown-functions" => Deny,
+    UnknownFunctions: "unknown-functions" => Deny in Correctness,
 
     /// Usage of indirectly imported item (through multiple levels of dot-imports)
-    IndirectImports: "indirect-imports" => Warn,
+    IndirectImports: "indirect-imports" => Warn in Imports,
+
+    /// Usage matches definitions from multiple imported files
+    AmbiguousUsages: "ambiguous-usages" => Warn in Correctness,
+
+    /// External executable not listed in `extras.executables`
+    UnknownExecutables: "unknown-executables" => Warn in Correctness,
+
+    /// Function defined with the same name as an imported one
+    ShadowedImports: "shadowed-imports" => Warn in Correctness,
+
+    /// Function neither exported by `Export-ModuleMember` nor used in its module
+    UnusedPrivateFunctions: "unused-private-functions" => Warn in Correctness,
 
     /// Invalid characters in testname
-    InvalidTestnameCharacters: "invalid-testname-characters" => Warn,
+    InvalidTestnameCharacters: "invalid-testname-characters" => Warn in Pester,
+
+    /// Testname is a reserved or otherwise invalid Windows file name
+    ReservedTestnames: "reserved-testnames" => Warn in Pester,
+
+    /// Testname doesn't match the naming convention from config
+    InvalidTestnames: "invalid-testnames" => Warn in Pester,
+
+    /// `<placeholder>` in testname not defined in `-TestCases`
+    UnknownTestcasePlaceholders: "unknown-testcase-placeholders" => Warn in Pester,
+
+    /// The same `Describe` block name used in multiple files
+    DuplicateDescribes: "duplicate-describes" => Allow in Pester,
 
     /// Strict mode not enabled
-    NoStrictMode: "no-strict-mode" => Warn,
+    NoStrictMode: "no-strict-mode" => Warn in Correctness,
 
     /// Function name differs between usage and definition
-    InvalidLetterCasing: "invalid-letter-casing" => Warn,
+    InvalidLetterCasing: "invalid-letter-casing" => Warn in Style,
 
     /// Unknown lint allowed in a comment
-    UnknownLints: "unknown-lints" => Warn,
+    UnknownLints: "unknown-lints" => Warn in Correctness,
 
     /// Shelly couldn't parse this syntax
-    SyntaxErrors: "syntax-errors" => Warn,
+    SyntaxErrors: "syntax-errors" => Warn in Correctness,
 
     /// File was imported but no direct definitions from it are being used
-    UnusedImports: "unused-imports" => Warn,
+    UnusedImports: "unused-imports" => Warn in Imports,
+
+    /// File name doesn't match the naming convention from config
+    InvalidFileNames: "invalid-file-names" => Warn in Style,
+
+    /// Entry in builtins.lock conflicts with another builtin
+    BuiltinsConflicts: "builtins-conflicts" => Warn in Correctness,
+
+    /// Usage of a function marked as deprecated in config
+    DeprecatedFunctions: "deprecated-functions" => Warn in Correctness,
+
+    /// Usage of a function banned in config
+    BannedFunctions: "banned-functions" => Deny in Security,
+
+    /// Dot-imports not sorted or not grouped at the top of the file
+    UnsortedImports: "unsorted-imports" => Allow in Imports,
+
+    /// Dot-import after the first statement of the file
+    ImportsNotFirst: "imports-not-first" => Allow in Style,
+
+    /// Non-test file dot-imports a test file
+    ImportedTests: "imported-tests" => Warn in Imports,
+
+    /// Script neither imported by any file, nor a test, nor an entry point from config
+    UnusedFiles: "unused-files" => Allow in Imports,
+
+    /// Requirement from config not met, similarly to `no-strict-mode`
+    MissingRequirements: "missing-requirements" => Warn in Correctness,
+
+    /// `#Requires -Version` newer than the target PowerShell version from config
+    IncompatibleVersion: "incompatible-version" => Warn in Correctness,
+
+    /// Environment variable read, but neither required in config nor set or checked before
+    UncheckedEnvVariables: "unchecked-env-variables" => Warn in Correctness,
+
+    /// `TODO`, `FIXME` or other marker from config in a comment
+    TodoComments: "todo-comments" => Allow in Style,
+
+    /// Built-in alias used instead of the commandlet's name, eg. `gci`
+    AvoidAliases: "avoid-aliases" => Allow in Style,
+
+    /// Line longer than `[lint-options.long-lines] max` from config
+    LongLines: "long-lines" => Allow in Style,
 }
 
 impl fmt::Display for UnknownLint {
@@ -117,20 +284,84 @@ impl fmt::Display for UnknownLint {
 
 impl Lint {
     pub fn level(&self, config: &Config) -> Level {
-        let uncapped_level = config
+        let uncapped_level = match self.explicit_level(config) {
+            Level::Warn if config.deny_warnings => Level::Deny,
+            level => level,
+        };
+
+        match &config.only {
+            Some(only) if !only.contains(self) => Level::Allow,
+            _ => uncapped_level.min(config.cap),
+        }
+    }
+
+    /// Where the level of the lint comes from
+    pub fn level_source(&self, config: &Config) -> Source {
+        if let Some(only) = &config.only {
+            if !only.contains(self) {
+                return Source::CommandLine;
+            }
+        }
+
+        if self.explicit_level(config) == Level::Warn && config.deny_warnings {
+            return config.deny_warnings_source;
+        }
+
+        match config.sources.get(self) {
+            Some(&source) => source,
+            None if config.no_default_lints => Source::CommandLine,
+            None => Source::Default,
+        }
+    }
+
+    /// Level before promoting warnings and other adjustments
+    fn explicit_level(&self, config: &Config) -> Level {
+        config
             .overrides
             .get(self)
             .cloned()
-            .unwrap_or(self.default_level());
-        uncapped_level.min(config.cap)
+            .unwrap_or(if config.no_default_lints { Level::Allow } else { self.default_level() })
     }
 }
 
+/// Parses a `[levels]`-like table of lint or category names and levels.
+/// Levels of single lints take precedence over their categories.
+fn parse_levels(levels: &Map<String, String>) -> Result<Map<Lint, Level>, failure::Error> {
+    let mut from_categories = Map::new();
+    let mut parsed = Map::new();
+
+    for (lint_name, level) in levels {
+        let selector = lint_name.parse()
+            .map_err(|_| format_err!("Unknown lint name: '{}'", lint_name))?;
+
+        let level = level.parse()
+            .map_err(|_| format_err!("Unknown level '{}' for '{}'", level, lint_name))?;
+
+        match selector {
+            Selector::Lint(lint) => {
+                let previous_entry = parsed.insert(lint, level);
+                if previous_entry.is_some() {
+                    // Toml parsing gets rid of duplicates for us,
+                    // but if we introduce lint-name-aliases, duplicates
+                    // may occur.
+                    bail!("Duplicated entry for '{}' lint", lint_name);
+                }
+            }
+            Selector::Category(category) => {
+                from_categories.extend(category.lints().map(|lint| (lint, level)));
+            }
+        }
+    }
+
+    from_categories.extend(parsed);
+    Ok(from_categories)
+}
+
 fn parse_allow_annotation(line: &str) -> Result<Option<(Lint, Option<&str>)>, &str> {
     lazy_static!(
         static ref RE: Regex = Regex::new(
             r"(?ix) ^ [^\#]* \# \s* (?: shelly:|analyzer:)? \s*
-              allow \s* ( [[:word:]-]+ ) (?: \( (.*) \) )? $"
+              allow \s* ( [[:word:]-]+ ) (?: \( (.*?) \) )? (?: \s+ -- .* )? \s* $"
         ).unwrap();
     );
 
@@ -172,54 +403,203 @@ fn test_parse_allow_annotation() {
         parse_allow_annotation("New-Foo # whatever: allow unknown-functions"),
         Ok(None),
     );
+    assert_eq!(
+        parse_allow_annotation("New-Foo # allow unknown-functions(New-Foo) -- defined by a plugin (v2)"),
+        Ok(Some((Lint::UnknownFunctions, Some("New-Foo")))),
+    );
+    assert_eq!(
+        parse_allow_annotation("New-Foo # allow unknown-functions -- legacy"),
+        Ok(Some((Lint::UnknownFunctions, None))),
+    );
 }
 
+#[derive(Clone)]
 pub struct Config {
     /// Overrides default levels for lints
     overrides: Map<Lint, Level>,
 
+    /// Where the overrides come from
+    sources: Map<Lint, Source>,
+
     /// Maximal severity level
     cap: Level,
+
+    /// Whether to promote warnings to errors
+    deny_warnings: bool,
+
+    /// Where the promotion was requested
+    deny_warnings_source: Source,
+
+    /// Maximal number of messages of a single lint in a single file
+    max_per_lint: Option<usize>,
+
+    /// If set, all the other lints are allowed
+    only: Option<Set<Lint>>,
+
+    /// Whether lints without an explicit level are allowed
+    no_default_lints: bool,
+
+    /// Levels for files matching globs, from `[[overrides]]`
+    path_overrides: Vec<PathOverride>,
+}
+
+/// Levels from an `[[overrides]]` entry
+#[derive(Clone)]
+struct PathOverride {
+    /// Directory of the config, which the glob is relative to
+    dir: PathBuf,
+
+    glob: globset::GlobMatcher,
+
+    levels: Map<Lint, Level>,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
             overrides: Map::default(),
+            sources: Map::default(),
             cap: Level::Deny,
+            deny_warnings: false,
+            deny_warnings_source: Source::Default,
+            max_per_lint: None,
+            only: None,
+            no_default_lints: false,
+            path_overrides: Vec::new(),
         }
     }
 }
 
 impl Config {
     pub fn from_config_file(config_file: &ConfigFile) -> Result<Config, failure::Error> {
-        let mut config = Config::default();
+        let mut config = Config::default().with_levels_from(config_file)?;
 
-        if let Some(levels) = &config_file.levels {
-            for (lint_name, level) in levels {
-                let lint = lint_name.parse()
-                    .map_err(|_| format_err!("Unknown lint name: '{}'", lint_name))?;
+        config.deny_warnings = config_file.deny_warnings.unwrap_or(false);
+        if config_file.deny_warnings.is_some() {
+            config.deny_warnings_source = Source::ConfigFile;
+        }
+        config.max_per_lint = config_file.max_per_lint;
 
-                let level = level.parse()
-                    .map_err(|_| format_err!("Unknown level '{}' for '{}'", level, lint_name))?;
+        Ok(config)
+    }
 
-                let previous_entry = config.overrides.insert(lint, level);
-                if previous_entry.is_some() {
-                    // Toml parsing gets rid of duplicates for us,
-                    // but if we introduce lint-name-aliases, duplicates
-                    // may occur.
-                    bail!("Duplicated entry for '{}' lint", lint_name);
-                }
+    /// Overrides levels with the ones from a config file,
+    /// eg. a nested one applying to a subdirectory
+    pub fn with_levels_from(mut self, config_file: &ConfigFile) -> Result<Self, failure::Error> {
+        if let Some(levels) = &config_file.levels {
+            for (lint, level) in parse_levels(levels)? {
+                self.sources.insert(lint, Source::ConfigFile);
+                self.overrides.insert(lint, level);
             }
         }
+        Ok(self)
+    }
 
-        Ok(config)
+    /// Adds levels from `[[overrides]]` of a config file in a given directory.
+    ///
+    /// They take precedence over `[levels]` (also the ones from nested configs),
+    /// but not over the command line.
+    pub fn with_path_overrides_from(mut self, dir: &Path, config_file: &ConfigFile)
+        -> Result<Self, failure::Error>
+    {
+        for entry in config_file.overrides.iter().flatten() {
+            let glob = globset::GlobBuilder::new(&entry.path)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format_err!("Invalid override path {:?}: {}", entry.path, e))?
+                .compile_matcher();
+
+            let levels = parse_levels(&entry.levels)
+                .map_err(|e| format_err!("{} in override for {:?}", e, entry.path))?;
+
+            self.path_overrides.push(PathOverride { dir: dir.to_owned(), glob, levels });
+        }
+        Ok(self)
+    }
+
+    /// Config for a given file, with its `[[overrides]]` applied
+    pub fn for_file(&self, file: &Path) -> Cow<'_, Config> {
+        let mut matching = self.path_overrides.iter()
+            .filter(|path_override| {
+                file.strip_prefix(&path_override.dir)
+                    .is_ok_and(|relative_path| path_override.glob.is_match(relative_path))
+            })
+            .peekable();
+
+        if matching.peek().is_none() {
+            return Cow::Borrowed(self);
+        }
+
+        let mut config = self.clone();
+        for path_override in matching {
+            config.apply_path_override(path_override);
+        }
+        Cow::Owned(config)
+    }
+
+    fn apply_path_override(&mut self, path_override: &PathOverride) {
+        for (&lint, &level) in &path_override.levels {
+            if self.sources.get(&lint) != Some(&Source::CommandLine) {
+                self.sources.insert(lint, Source::ConfigFile);
+                self.overrides.insert(lint, level);
+            }
+        }
     }
 
     pub fn with_overrides(mut self, overrides: &Map<Lint, Level>) -> Self {
         self.overrides.extend(overrides);
+        self.sources.extend(overrides.keys().map(|&lint| (lint, Source::CommandLine)));
         self
     }
+
+    /// Promotes warnings to errors, if `deny_warnings` is set.
+    /// Doesn't undo the promotion requested in config.
+    pub fn with_deny_warnings(mut self, deny_warnings: bool) -> Self {
+        if deny_warnings && !self.deny_warnings {
+            self.deny_warnings_source = Source::CommandLine;
+        }
+        self.deny_warnings |= deny_warnings;
+        self
+    }
+
+    /// Whether warnings are promoted to errors and where it was requested
+    pub fn deny_warnings(&self) -> (bool, Source) {
+        (self.deny_warnings, self.deny_warnings_source)
+    }
+
+    /// Overrides the limit of messages per lint and file from config
+    pub fn with_max_per_lint(mut self, max_per_lint: Option<usize>) -> Self {
+        self.max_per_lint = max_per_lint.or(self.max_per_lint);
+        self
+    }
+
+    /// Allows all the lints except of given ones.
+    /// Does nothing if `lints` is empty.
+    pub fn with_only(mut self, lints: &[Lint]) -> Self {
+        if !lints.is_empty() {
+            self.only = Some(lints.iter().cloned().collect());
+        }
+        self
+    }
+
+    /// Allows every lint, unless its level is set explicitly
+    /// in config or by overrides.
+    pub fn with_no_default_lints(mut self, no_default_lints: bool) -> Self {
+        self.no_default_lints |= no_default_lints;
+        self
+    }
+
+    /// Checks whether any of the lints can be emitted,
+    /// so passes emitting only allowed lints can be skipped.
+    pub fn any_enabled(&self, lints: &[Lint]) -> bool {
+        let enabled = |config: &Config| lints.iter().any(|lint| lint.level(config) != Level::Allow);
+
+        enabled(self) || self.path_overrides.iter().any(|path_override| {
+            let mut config = self.clone();
+            config.apply_path_override(path_override);
+            enabled(&config)
+        })
+    }
 }
 
 #[test]
@@ -259,6 +639,106 @@ fn overrides() {
     assert_eq!(Lint::UnknownFunctions.level(&config), Level::Deny);
 }
 
+#[test]
+fn deny_warnings() {
+    let mut config = Config::default().with_deny_warnings(true);
+    config.overrides.insert(Lint::UnusedImports, Level::Allow);
+
+    assert_eq!(Lint::NoStrictMode.level(&config), Level::Deny);
+    assert_eq!(Lint::UnknownFunctions.level(&config), Level::Deny);
+    assert_eq!(Lint::UnusedImports.level(&config), Level::Allow);
+
+    config.cap = Level::Warn;
+    assert_eq!(Lint::NoStrictMode.level(&config), Level::Warn);
+}
+
+#[test]
+fn only() {
+    let config = Config::default().with_only(&[Lint::UnusedImports]);
+
+    assert_eq!(Lint::UnusedImports.level(&config), Level::Warn);
+    assert_eq!(Lint::UnknownFunctions.level(&config), Level::Allow);
+
+    assert!(config.any_enabled(&[Lint::UnknownFunctions, Lint::UnusedImports]));
+    assert!(!config.any_enabled(&[Lint::UnknownFunctions]));
+}
+
+#[test]
+fn no_default_lints() {
+    let overrides = ::std::iter::once((Lint::UnusedImports, Level::Deny)).collect();
+    let config = Config::default()
+        .with_no_default_lints(true)
+        .with_overrides(&overrides);
+
+    assert_eq!(Lint::UnusedImports.level(&config), Level::Deny);
+    assert_eq!(Lint::UnknownFunctions.level(&config), Level::Allow);
+}
+
+#[test]
+fn level_sources() {
+    let config_file = ConfigFile::from_str("deny_warnings = true\n[levels]\nunused-imports = \"deny\"\n").unwrap();
+    let overrides = ::std::iter::once((Lint::UnknownFunctions, Level::Allow)).collect();
+    let config = Config::from_config_file(&config_file).unwrap().with_overrides(&overrides);
+
+    assert_eq!(Lint::UnusedImports.level_source(&config), Source::ConfigFile);
+    assert_eq!(Lint::UnknownFunctions.level_source(&config), Source::CommandLine);
+    assert_eq!(Lint::NoStrictMode.level(&config), Level::Deny);
+    assert_eq!(Lint::NoStrictMode.level_source(&config), Source::ConfigFile);
+    assert_eq!(Lint::UnsortedImports.level_source(&config), Source::Default);
+
+    let config = config.with_only(&[Lint::UnusedImports]);
+    assert_eq!(Lint::UnsortedImports.level_source(&config), Source::CommandLine);
+}
+
+#[test]
+fn category_levels() {
+    for category in Category::categories() {
+        assert!(category.name().parse::<Lint>().is_err());
+        assert_eq!(category.name().parse(), Ok(Selector::Category(category)));
+    }
+
+    let config_file = ConfigFile::from_str("[levels]\nstyle = \"deny\"\nlong-lines = \"allow\"\n").unwrap();
+    let config = Config::from_config_file(&config_file).unwrap();
+
+    assert_eq!(Lint::InvalidFileNames.level(&config), Level::Deny);
+    assert_eq!(Lint::LongLines.level(&config), Level::Allow);
+    assert_eq!(Lint::UnusedImports.level(&config), Lint::UnusedImports.default_level());
+
+    let config_file = ConfigFile::from_str("[levels]\npester = \"deny\"\nsecurity = \"warn\"\ncorrectness = \"allow\"\n").unwrap();
+    let config = Config::from_config_file(&config_file).unwrap();
+
+    assert_eq!(Lint::InvalidTestnames.level(&config), Level::Deny);
+    assert_eq!(Lint::DuplicateDescribes.level(&config), Level::Deny);
+    assert_eq!(Lint::BannedFunctions.level(&config), Level::Warn);
+    assert_eq!(Lint::UnknownFunctions.level(&config), Level::Allow);
+    assert_eq!(Lint::InvalidFileNames.level(&config), Lint::InvalidFileNames.default_level());
+}
+
+#[test]
+fn path_overrides() {
+    let config_file = ConfigFile::from_str(concat!(
+        "[levels]\nunused-imports = \"deny\"\n",
+        "[[overrides]]\npath = \"tests/**\"\nlevels = { no-strict-mode = \"allow\", unused-imports = \"warn\" }\n",
+    )).unwrap();
+    let overrides = ::std::iter::once((Lint::UnusedImports, Level::Allow)).collect();
+    let config = Config::from_config_file(&config_file).unwrap()
+        .with_path_overrides_from(Path::new("root"), &config_file).unwrap();
+
+    let test_file = Path::new("root/tests/unit/Foo.Tests.ps1");
+    assert_eq!(Lint::NoStrictMode.level(&config.for_file(test_file)), Level::Allow);
+    assert_eq!(Lint::UnusedImports.level(&config.for_file(test_file)), Level::Warn);
+    assert_eq!(Lint::NoStrictMode.level(&config.for_file(Path::new("root/Foo.ps1"))), Level::Warn);
+    assert_eq!(Lint::NoStrictMode.level(&config.for_file(Path::new("tests/Foo.ps1"))), Level::Warn);
+
+    // Command line wins
+    let config = config.with_overrides(&overrides);
+    assert_eq!(Lint::UnusedImports.level(&config.for_file(test_file)), Level::Allow);
+
+    let config_file = ConfigFile::from_str("[[overrides]]\npath = \"*.ps1\"\nlevels = { todo-comments = \"warn\" }\n");
+    let config = Config::default().with_path_overrides_from(Path::new("."), &config_file.unwrap()).unwrap();
+    assert!(config.any_enabled(&[Lint::TodoComments]));
+}
+
 #[test]
 fn slug_roundtrip() {
     assert!(Lint::lints().count() > 0);
@@ -267,6 +747,26 @@ fn slug_roundtrip() {
     }
 }
 
+#[test]
+fn documented_lints() {
+    let docs = include_str!("../docs/lints.md");
+    for lint in Lint::lints() {
+        assert!(docs.contains(&format!("\n### {}\n", lint.slug())), "{} not documented", lint.slug());
+        assert!(lint.doc_url().ends_with(&format!("#{}", lint.slug())));
+    }
+
+    // Lints are documented in sections of their categories
+    for section in docs.split("\n## ").skip(1) {
+        let name = section.lines().next().unwrap().to_lowercase();
+        let category = Category::categories().find(|category| category.name() == name).unwrap();
+        let mut documented: Vec<Lint> = section.split("\n### ").skip(1)
+            .map(|lint| lint.lines().next().unwrap().parse().unwrap())
+            .collect();
+        documented.sort();
+        assert_eq!(documented, category.lints().collect::<Vec<_>>(), "lints documented in {}", name);
+    }
+}
+
 // Emitting
 
 /// Lint Emitter
@@ -276,23 +776,146 @@ fn slug_roundtrip() {
 ///
 /// Use `MessageBuilder::emit` to emit the message.
 pub struct Emitter<'e> {
-    raw_emitter: &'e mut ::Emitter,
+    raw_emitter: &'e mut dyn (::Emitter),
     config: Config,
     encountered_lints: Set<Lint>,
+
+    /// Already emitted messages, so passes reporting
+    /// the same problem don't produce duplicates
+    emitted: Set<MessageKey>,
+
+    /// Numbers of messages per lint and file, for `max_per_lint`
+    counts: Map<(Lint, PathBuf), LintCount>,
+
+    /// Known messages, which are not emitted
+    baseline: Baseline,
+
+    /// Files analyzed only because the selected ones import them,
+    /// whose messages are not emitted
+    dependencies: Set<PathBuf>,
+
+    /// Configs of subdirectories with their own `shelly.toml`
+    nested_configs: Vec<(PathBuf, Config)>,
+
+    summary: Summary,
+}
+
+struct LintCount {
+    count: usize,
+    kind: MessageKind,
+    source: Rc<str>,
 }
 
+/// Identity of a message: lint, file, span (as byte range),
+/// text, the syntax element it refers to and notes
+type MessageKey = (Lint, PathBuf, Option<(u32, u32)>, String, Option<String>, Vec<String>);
+
 impl<'e> Emitter<'e> {
     /// Creates a new emitter.
-    pub fn new(emitter: &'e mut ::Emitter, config: Config) -> Emitter<'e> {
+    pub fn new(emitter: &'e mut dyn (::Emitter), config: Config) -> Emitter<'e> {
         Emitter {
             raw_emitter: emitter,
             config,
             encountered_lints: Set::new(),
+            emitted: Set::new(),
+            counts: Map::new(),
+            baseline: Baseline::default(),
+            dependencies: Set::new(),
+            nested_configs: Vec::new(),
+            summary: Summary::default(),
+        }
+    }
+
+    /// Skips messages present in the baseline
+    pub fn with_baseline(mut self, baseline: Baseline) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    /// Uses a given config for messages in files under a directory.
+    /// The config of the deepest directory containing the file wins.
+    pub fn add_nested_config(&mut self, dir: &Path, config: Config) {
+        self.nested_configs.push((dir.to_owned(), config));
+    }
+
+    /// Skips messages in a file, which is analyzed only as an import of the selected ones
+    pub fn add_dependency(&mut self, path: &Path) {
+        self.dependencies.insert(path.to_owned());
+    }
+
+    /// Emits summaries of messages omitted because of `max_per_lint`
+    /// and returns the numbers of all the messages.
+    ///
+    /// Should be called after all the messages are emitted.
+    pub fn finish(self) -> Summary {
+        let max = match self.config.max_per_lint {
+            Some(max) => max,
+            None => return self.summary,
+        };
+
+        for ((lint, file), count) in self.counts {
+            if count.count <= max {
+                continue;
+            }
+
+            self.raw_emitter.emit(EmittedItem {
+                lint,
+                category: lint.category(),
+                doc_url: lint.doc_url(),
+                kind: count.kind,
+                message: format!("… and {} more", count.count - max),
+                location: Location { file, source: count.source, span: None },
+                notes: vec![Note {
+                    message: format!("Only the first {} messages of this lint are shown", max),
+                    location: None,
+                }],
+                fix: None,
+            });
         }
+
+        self.summary
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Config used for messages in a given file
+    pub fn config_for(&self, file: &Path) -> &Config {
+        self.nested_configs.iter()
+            .filter(|(dir, _)| file.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+            .map_or(&self.config, |(_, config)| config)
+    }
+
+    /// Checks whether any of the lints can be emitted in any of the files
+    pub fn any_enabled(&self, lints: &[Lint]) -> bool {
+        self.config.any_enabled(lints) || self.nested_configs.iter().any(|(_, config)| config.any_enabled(lints))
+    }
+
+    pub fn analyzed_file(&mut self, path: &Path) {
+        if self.dependencies.contains(path) {
+            return;
+        }
+        self.summary.files += 1;
+        self.raw_emitter.analyzed_file(path);
+    }
+
+    pub fn parsed_imports(&mut self, path: &Path, imports: &[&Path]) {
+        self.raw_emitter.parsed_imports(path, imports);
+    }
+
+    pub fn parsed_import(&mut self, path: &Path, import: &Path, location: &Location) {
+        self.raw_emitter.parsed_import(path, import, location);
     }
 
     fn emit(&mut self, mut message: MessageBuilder) {
-        let kind = match message.lint.level(&self.config) {
+        if self.dependencies.contains(&message.location.file) {
+            return;
+        }
+
+        let file = &message.location.file;
+        let kind = match message.lint.level(&self.config_for(file).for_file(file)) {
             Level::Allow => return,
             Level::Warn => MessageKind::Warning,
             Level::Deny => MessageKind::Error,
@@ -326,12 +949,44 @@ impl<'e> Emitter<'e> {
             }
         }
 
-        if self.encountered_lints.insert(message.lint) == true
+        if self.baseline.contains(message.lint, &message.message, &message.location) {
+            return;
+        }
+
+        let key = (
+            message.lint,
+            message.location.file.clone(),
+            message.location.span.map(|span| (span.start.byte, span.end.byte)),
+            message.message.clone(),
+            message.what.clone(),
+            message.notes.iter().map(|note| note.message.clone()).collect(),
+        );
+        if !self.emitted.insert(key) {
+            return;
+        }
+
+        self.summary.add(message.lint, kind);
+
+        if let Some(max) = self.config.max_per_lint {
+            let count = self.counts
+                .entry((message.lint, message.location.file.clone()))
+                .or_insert_with(|| LintCount {
+                    count: 0,
+                    kind,
+                    source: Rc::clone(&message.location.source),
+                });
+            count.count += 1;
+            if count.count > max {
+                return;
+            }
+        }
+
+        if self.encountered_lints.insert(message.lint)
         && message.location.span.is_some()
         && message.lint != Lint::UnknownLints {
             let elem_str = message.what.as_ref()
                 .map(|what| format!("({})", what))
-                .unwrap_or_else(String::new);
+                .unwrap_or_default();
             let note = format!(
                 "To allow, add a comment `allow {}{}` in this line",
                 message.lint.slug(),
@@ -343,9 +998,12 @@ impl<'e> Emitter<'e> {
         let item = EmittedItem {
             kind,
             lint: message.lint,
+            category: message.lint.category(),
+            doc_url: message.lint.doc_url(),
             message: message.message,
             location: message.location,
             notes: message.notes,
+            fix: message.fix,
         };
 
         self.raw_emitter.emit(item);
@@ -358,8 +1016,9 @@ impl Location {
             location: self,
             lint,
             message: message.into(),
-            notes: None,
+            notes: Vec::new(),
             what: None,
+            fix: None,
         }
     }
 }
@@ -369,12 +1028,14 @@ pub struct MessageBuilder {
     lint: Lint,
     message: String,
     location: Location,
-    notes: Option<String>,
+    notes: Vec<Note>,
 
     /// Specific syntax element that the lint refers to,
     /// used for allow comment logic. Eg. the function name
     /// for the UnkonwnFunctions lint.
     what: Option<String>,
+
+    fix: Option<Fix>,
 }
 
 impl MessageBuilder {
@@ -382,18 +1043,14 @@ impl MessageBuilder {
     ///
     /// Could be called multiple times.
     pub fn note(mut self, note: impl Into<String>) -> MessageBuilder {
-        let note = note.into();
-
-        match &mut self.notes {
-            Some(current_note) => {
-                current_note.push('\n');
-                current_note.push_str(&note);
-            }
-            notes => {
-                *notes = Some(note)
-            }
-        }
+        self.notes.push(Note { message: note.into(), location: None });
+        self
+    }
 
+    /// Adds a note referring to some other location,
+    /// eg. a definition of a function.
+    pub fn note_at(mut self, location: Location, note: impl Into<String>) -> MessageBuilder {
+        self.notes.push(Note { message: note.into(), location: Some(location) });
         self
     }
 
@@ -404,6 +1061,12 @@ impl MessageBuilder {
         self
     }
 
+    /// Attaches a machine-applicable fix
+    pub fn fix(mut self, fix: Fix) -> MessageBuilder {
+        self.fix = Some(fix);
+        self
+    }
+
     /// Checks the allow-logic and emits the message
     /// according to overrides used in config.
     pub fn emit(self, emitter: &mut Emitter) {
@@ -433,3 +1096,45 @@ fn test_ignoring_allowed_messages() {
     }
     assert_eq!(raw_emitter.emitted_items.len(), 1);
 }
+
+#[test]
+fn test_max_per_lint() {
+    let source = "Foo\nFoo\nFoo\n";
+    let location = |line: u32| {
+        let start = (line as usize - 1) * 4;
+        Span::from_fragment(line, &source[start..start + 3], source).in_file_source("foo", source.into())
+    };
+    let mut raw_emitter = ::VecEmitter::new();
+
+    {
+        let mut emitter = Emitter::new(&mut raw_emitter, Config::default().with_max_per_lint(Some(1)));
+        for line in 1..4 {
+            location(line).lint(Lint::UnknownFunctions, "Boo").emit(&mut emitter);
+        }
+        location(1).lint(Lint::InvalidLetterCasing, "Boo").emit(&mut emitter);
+        emitter.finish();
+    }
+
+    assert_eq!(raw_emitter.lints(), [Lint::UnknownFunctions, Lint::InvalidLetterCasing, Lint::UnknownFunctions]);
+    assert_eq!(raw_emitter.emitted_items[2].message, "… and 2 more");
+}
+
+#[test]
+fn test_deduplication() {
+    let source = "Foo\nFoo\n";
+    let location = |line: u32| {
+        let start = (line as usize - 1) * 4;
+        Span::from_fragment(line, &source[start..start + 3], source).in_file_source("foo", source.into())
+    };
+    let mut raw_emitter = ::VecEmitter::new();
+
+    {
+        let mut emitter = Emitter::new(&mut raw_emitter, Config::default());
+        location(1).lint(Lint::UnknownFunctions, "Boo").emit(&mut emitter);
+        location(1).lint(Lint::UnknownFunctions, "Boo").emit(&mut emitter);
+        location(1).lint(Lint::UnknownFunctions, "Other").emit(&mut emitter);
+        location(1).lint(Lint::InvalidLetterCasing, "Boo").emit(&mut emitter);
+        location(2).lint(Lint::UnknownFunctions, "Boo").emit(&mut emitter);
+    }
+    assert_eq!(raw_emitter.len(), 4);
+}
@@ -0,0 +1,97 @@
+//! Conversions to Language Server Protocol types
+//!
+//! Shelly's locations count bytes, while LSP columns count UTF-16 code units,
+//! so the conversions need the source of the file.
+
+use lsp_types;
+use lsp_types::{Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, NumberOrString, Position, Range, Url};
+
+use std::path::Path;
+
+use syntax::v2;
+use {EmittedItem, Location, MessageKind, Span};
+
+/// Converts a location to a 0-indexed LSP position
+pub fn position(location: v2::Location, source: &str) -> Position {
+    let before = source.get(..location.byte as usize).unwrap_or("");
+    let before = before.rfind('\n').map_or(before, |newline| &before[newline + 1..]);
+
+    Position {
+        line: location.line - 1,
+        character: before.encode_utf16().count() as u32,
+    }
+}
+
+pub fn range(span: Span, source: &str) -> Range {
+    Range {
+        start: position(span.start, source),
+        end: position(span.end, source),
+    }
+}
+
+/// Range of a location, or the start of the file if it has no span
+pub fn location_range(location: &Location) -> Range {
+    location.span
+        .map(|span| range(span, &location.source))
+        .unwrap_or_default()
+}
+
+/// Converts a message to a diagnostic.
+///
+/// Notes pointing to some location become the related information,
+/// if the location's path can be converted to an URL (ie. it's absolute).
+/// The other notes are appended to the message.
+pub fn diagnostic(item: &EmittedItem) -> Diagnostic {
+    let severity = match item.kind {
+        MessageKind::Error   => DiagnosticSeverity::ERROR,
+        MessageKind::Warning => DiagnosticSeverity::WARNING,
+    };
+
+    let mut message = item.message.clone();
+    let mut related = Vec::new();
+
+    for note in &item.notes {
+        let uri = note.location.as_ref().and_then(|location| file_url(&location.file));
+        match (&note.location, uri) {
+            (Some(location), Some(uri)) => related.push(DiagnosticRelatedInformation {
+                location: lsp_types::Location { uri, range: location_range(location) },
+                message: note.message.clone(),
+            }),
+            _ => {
+                message.push('\n');
+                message.push_str(&note.message);
+            }
+        }
+    }
+
+    Diagnostic {
+        range: location_range(&item.location),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(item.lint.slug().to_owned())),
+        source: Some("shelly".to_owned()),
+        message,
+        related_information: if related.is_empty() { None } else { Some(related) },
+        ..Diagnostic::default()
+    }
+}
+
+fn file_url(path: &Path) -> Option<Url> {
+    Url::from_file_path(path).ok()
+}
+
+#[test]
+fn test_utf16_columns() {
+    let source = "$x = 'zażółć'; Get-Foo\n$y = '😀' + $x\n";
+
+    let get_foo = source.find("Get-Foo").unwrap();
+    let span = Span::from_fragment(1, &source[get_foo..get_foo + 7], source);
+    assert_eq!(range(span, source), Range {
+        start: Position { line: 0, character: 15 },
+        end: Position { line: 0, character: 22 },
+    });
+
+    // The emoji is two UTF-16 code units
+    let x = source.rfind("$x").unwrap();
+    let span = Span::from_fragment(2, &source[x..x + 2], source);
+    assert_eq!(range(span, source).start, Position { line: 1, character: 12 });
+}
@@ -1,17 +1,25 @@
 extern crate shelly;
 
 extern crate failure;
-use failure::Error;
+use failure::{Error, ResultExt};
 
 extern crate yansi;
-use yansi::{Color, Paint, Style};
+use yansi::Paint;
 
+use std::fs;
+use std::io::{self, BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::collections::BTreeMap as Map;
 
-use shelly::{EmittedItem, RunOpt, lint::{Lint, self}};
+use shelly::{EmittedItem, Emitter, RunOpt, lint::{Lint, self}};
+use shelly::pretty::PrettyEmitter;
+use shelly::watch::{ImportGraph, Rerun};
+
+extern crate serde_json;
+
+extern crate notify;
+use notify::Watcher;
 
-#[macro_use]
 extern crate structopt;
 
 use structopt::StructOpt;
@@ -30,54 +38,319 @@ struct Opt {
 enum Subcommand {
     /// Show available lints
     #[structopt(name = "show-lints")]
-    ShowLints,
+    ShowLints(ConfigOpt),
+
+    /// Show available output formats
+    #[structopt(name = "show-formats")]
+    ShowFormats,
+
+    /// Write a starter shelly.toml based on the analyzed code
+    #[structopt(name = "init")]
+    Init(ConfigOpt),
+
+    /// Print all commandlets assumed to exist, in the builtins.lock format
+    #[structopt(name = "dump-builtins")]
+    DumpBuiltins(ConfigOpt),
 
     /// Run analysis (also default when no command specified)
     #[structopt(name = "analyze")]
     Analyze(AnalyzeOpt),
+
+    /// Analyze, then re-analyze changed files and files importing them on every change
+    #[structopt(name = "watch")]
+    Watch(WatchOpt),
+
+    /// Analyze and apply suggested fixes in place
+    #[structopt(name = "fix")]
+    Fix(FixOpt),
+
+    /// Apply fixes from results saved by `analyze --format json --with-fixes`
+    #[structopt(name = "apply-fixes")]
+    ApplyFixes(ApplyFixesOpt),
+
+    /// Inspect the configuration
+    #[structopt(name = "config")]
+    Config(ConfigSubcommand),
+
+    /// Manage lists of builtin commandlets
+    #[structopt(name = "builtins")]
+    Builtins(BuiltinsSubcommand),
+
+    /// Print the graph of dot-imports, with unused and indirect imports marked
+    #[structopt(name = "graph")]
+    Graph(GraphOpt),
+
+    /// Explain through which imports a function gets into scope of a file
+    #[structopt(name = "why")]
+    Why(WhyOpt),
 }
 
-#[derive(StructOpt, Debug, Default)]
-struct AnalyzeOpt {
+#[derive(StructOpt, Debug)]
+enum ConfigSubcommand {
+    /// Print the effective configuration for `analyze` with given
+    /// options and where each value comes from
+    #[structopt(name = "show")]
+    Show(RunFlags),
+}
+
+#[derive(StructOpt, Debug)]
+enum BuiltinsSubcommand {
+    /// Convert a snapshot of `Get-Command` (exported with `ConvertTo-Json` or `Export-Csv`)
+    /// to a builtins file, to be listed in `extras.builtins_files`
+    #[structopt(name = "import")]
+    Import(ImportOpt),
+}
+
+#[derive(StructOpt, Debug)]
+struct ImportOpt {
+    /// JSON or CSV file with the output of `Get-Command`
+    #[structopt(parse(from_os_str))]
+    snapshot: PathBuf,
+
+    /// Write the builtins file here instead of stdout
+    #[structopt(long = "output", value_name = "FILE", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct GraphOpt {
+    #[structopt(flatten)]
+    config_opt: ConfigOpt,
+
+    /// Output format: `dot` (Graphviz) or `json`
+    #[structopt(
+        long = "format",
+        value_name = "FORMAT",
+        default_value = "dot",
+        raw(possible_values = r#"&["dot", "json"]"#)
+    )]
+    format: String,
+
+    /// Write the graph to this file instead of stdout
+    #[structopt(long = "output", value_name = "FILE", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct WhyOpt {
+    #[structopt(flatten)]
+    config_opt: ConfigOpt,
+
+    /// File using the function
+    #[structopt(long = "file", value_name = "FILE", parse(from_os_str))]
+    file: PathBuf,
+
+    /// Name of the function
+    #[structopt(long = "function", value_name = "NAME")]
+    function: String,
+}
+
+#[derive(StructOpt, Debug)]
+struct FixOpt {
+    #[structopt(flatten)]
+    run: RunFlags,
+
+    /// Apply only fixes for this lint
+    #[structopt(long = "lint", value_name = "LINT")]
+    lints: Vec<Lint>,
+
+    /// Print diffs of the changes instead of writing them
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct ApplyFixesOpt {
+    /// JSON file with analysis results
+    #[structopt(parse(from_os_str))]
+    results: PathBuf,
+
+    /// Apply only fixes for this lint
+    #[structopt(long = "lint", value_name = "LINT")]
+    lints: Vec<Lint>,
+
+    /// Print diffs of the changes instead of writing them
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+/// Checks that the format is known
+fn parse_format(name: &str) -> Result<String, String> {
+    match shelly::formats::output_format(name) {
+        Some(format) => Ok(format.name.to_owned()),
+        None => {
+            let names: Vec<_> = shelly::formats::output_formats().iter().map(|format| format.name).collect();
+            Err(format!("Unknown format `{}`, expected one of: {}", name, names.join(", ")))
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct ConfigOpt {
+    /// Config file to use instead of shelly.toml in the directory
+    #[structopt(long = "config", value_name = "FILE", parse(from_os_str))]
+    config: Option<PathBuf>,
+}
+
+/// Options of the analysis, shared by the subcommands running it
+#[derive(StructOpt, Debug)]
+struct RunFlags {
+    #[structopt(flatten)]
+    config_opt: ConfigOpt,
+
     /// Print output of the parser (tastes best with `| less -R`)
     #[structopt(long = "debug-parser")]
     debug_parser: bool,
 
-    /// Set the level of this lint to `allow`
+    /// Set the level of this lint or category of lints to `allow`
     #[structopt(short = "A", long = "allow", value_name = "LINT")]
-    allowed_lints: Vec<Lint>,
+    allowed_lints: Vec<lint::Selector>,
 
-    /// Set the level of this lint to `warn`
+    /// Set the level of this lint or category of lints to `warn`
     #[structopt(short = "W", long = "warn", value_name = "LINT")]
-    warned_lints: Vec<Lint>,
+    warned_lints: Vec<lint::Selector>,
 
-    /// Set the level of this lint to `deny`
+    /// Set the level of this lint or category of lints to `deny`
     #[structopt(short = "D", long = "deny", value_name = "LINT")]
-    denied_lints: Vec<Lint>,
+    denied_lints: Vec<lint::Selector>,
+
+    /// Promote all warnings to errors
+    #[structopt(long = "deny-warnings")]
+    deny_warnings: bool,
+
+    /// Show at most N messages of each lint per file
+    #[structopt(long = "max-per-lint", value_name = "N")]
+    max_per_lint: Option<usize>,
+
+    /// Run only these lints (comma-separated), skipping unneeded analysis
+    #[structopt(long = "only", value_name = "LINTS", raw(use_delimiter = "true"))]
+    only: Vec<Lint>,
+
+    /// Allow all lints except of the ones enabled with -W/-D or in config
+    #[structopt(long = "no-default-lints")]
+    no_default_lints: bool,
+
+    /// Print time spent in each phase of the analysis
+    #[structopt(long = "timings")]
+    timings: bool,
+
+    /// Don't analyze files matching this glob, relative to the directory (repeatable)
+    #[structopt(long = "exclude", value_name = "GLOB", raw(number_of_values = "1"))]
+    exclude: Vec<String>,
+
+    /// Version of PowerShell the scripts are run with, eg. `5.1` or `7.2` (overrides `target` from config)
+    #[structopt(long = "ps-version", value_name = "VERSION")]
+    ps_version: Option<String>,
+
+    /// Analyze only these files, resolving their imports in the directory.
+    /// Given directories are analyzed together, without parsing the rest of the directory.
+    #[structopt(value_name = "FILES", parse(from_os_str))]
+    files: Vec<PathBuf>,
 }
 
-impl AnalyzeOpt {
+/// Options of the output format, shared by `analyze` and `watch`
+#[derive(StructOpt, Debug)]
+struct FormatFlags {
+    /// Output format, see `shelly show-formats`
+    #[structopt(
+        long = "output-format",
+        value_name = "FORMAT",
+        default_value = "human",
+        raw(alias = r#""format""#),
+        parse(try_from_str = "parse_format")
+    )]
+    format: String,
+
+    /// Same as `--output-format json`
+    #[structopt(long = "json")]
+    json: bool,
+
+    /// Include suggested fixes in the JSON output
+    #[structopt(long = "with-fixes")]
+    with_fixes: bool,
+}
+
+impl FormatFlags {
+    /// Creates a report in the chosen format, writing to `out`
+    fn report(&self, out: Box<dyn Write>) -> Result<Box<dyn shelly::formats::Report>, Error> {
+        let name = if self.json { "json" } else { &self.format };
+        let format = shelly::formats::output_format(name)
+            .ok_or_else(|| failure::err_msg(format!("Unknown format `{}`", name)))?;
+
+        let options = shelly::formats::FormatOptions { with_fixes: self.with_fixes };
+        Ok(format.create(out, &options))
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct WatchOpt {
+    #[structopt(flatten)]
+    run: RunFlags,
+
+    #[structopt(flatten)]
+    format: FormatFlags,
+}
+
+#[derive(StructOpt, Debug)]
+struct AnalyzeOpt {
+    #[structopt(flatten)]
+    run: RunFlags,
+
+    #[structopt(flatten)]
+    format: FormatFlags,
+
+    /// Write the output to this file instead of stdout
+    #[structopt(long = "output", value_name = "FILE", parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Fail if there are more than N warnings
+    #[structopt(long = "max-warnings", value_name = "N")]
+    max_warnings: Option<usize>,
+
+    /// Step through the messages, offering to fix, allow or baseline each of them
+    #[structopt(long = "interactive")]
+    interactive: bool,
+}
+
+impl RunFlags {
     fn run_opt(&self) -> RunOpt {
         let mut lint_overrides = Map::new();
 
-        for &(lints, level) in &[
-            (&self.allowed_lints, lint::Level::Allow),
-            (&self.warned_lints, lint::Level::Warn),
-            (&self.denied_lints, lint::Level::Deny),
-        ] {
-            for &lint in lints {
-                lint_overrides.insert(lint, level);
+        // Levels of single lints take precedence over their categories
+        for &categories in &[true, false] {
+            for &(selectors, level) in &[
+                (&self.allowed_lints, lint::Level::Allow),
+                (&self.warned_lints, lint::Level::Warn),
+                (&self.denied_lints, lint::Level::Deny),
+            ] {
+                for &selector in selectors {
+                    if matches!(selector, lint::Selector::Category(_)) == categories {
+                        lint_overrides.extend(selector.lints().into_iter().map(|lint| (lint, level)));
+                    }
+                }
             }
         }
 
         RunOpt {
             debug_parser: self.debug_parser,
             lint_overrides,
+            deny_warnings: self.deny_warnings,
+            max_per_lint: self.max_per_lint,
+            only: self.only.clone(),
+            no_default_lints: self.no_default_lints,
+            timings: self.timings,
+            files: self.files.iter().filter(|path| !path.is_dir()).cloned().collect(),
+            dirs: self.files.iter().filter(|path| path.is_dir()).cloned().collect(),
+            config: self.config_opt.config.clone(),
+            exclude: self.exclude.clone(),
+            ps_version: self.ps_version.clone(),
         }
     }
 }
 
-fn run() -> Result<(), Error> {
+/// Runs the command and returns whether it succeeded
+/// (a failed analysis is not an `Err`)
+fn run() -> Result<bool, Error> {
     if cfg!(windows) && !Paint::enable_windows_ascii() {
         Paint::disable();
     }
@@ -89,18 +362,49 @@ fn run() -> Result<(), Error> {
     }
 
     match opt.cmd {
-        Some(Subcommand::ShowLints) => {
-            print_lints(&opt.directory);
+        Some(Subcommand::ShowLints(ref config_opt)) => {
+            print_lints(&opt.directory, config_opt);
+        }
+        Some(Subcommand::ShowFormats) => {
+            print_formats();
+        }
+        Some(Subcommand::Init(ref config_opt)) => {
+            init(&opt.directory, config_opt)?;
+        }
+        Some(Subcommand::DumpBuiltins(ref config_opt)) => {
+            dump_builtins(&opt.directory, config_opt)?;
         }
         Some(Subcommand::Analyze(ref analyze_opt)) => {
-            shelly::run(opt.directory, analyze_opt.run_opt(), &mut CliEmitter {})?
+            return analyze(&opt.directory, analyze_opt);
+        }
+        Some(Subcommand::Watch(ref watch_opt)) => {
+            watch(&opt.directory, watch_opt)?;
+        }
+        Some(Subcommand::Fix(ref fix_opt)) => {
+            fix(&opt.directory, fix_opt)?;
+        }
+        Some(Subcommand::ApplyFixes(ref apply_opt)) => {
+            apply_fixes(apply_opt)?;
+        }
+        Some(Subcommand::Config(ConfigSubcommand::Show(ref run_flags))) => {
+            show_config(&opt.directory, run_flags)?;
+        }
+        Some(Subcommand::Builtins(BuiltinsSubcommand::Import(ref import_opt))) => {
+            import_builtins(import_opt)?;
+        }
+        Some(Subcommand::Graph(ref graph_opt)) => {
+            graph(&opt.directory, graph_opt)?;
+        }
+        Some(Subcommand::Why(ref why_opt)) => {
+            return why(&opt.directory, why_opt);
         }
         None => {
-            shelly::run(opt.directory, Default::default(), &mut CliEmitter {})?
+            // Parse no arguments to get the defaults
+            return analyze(&opt.directory, &AnalyzeOpt::from_iter(&["analyze"]));
         }
     }
 
-    Ok(())
+    Ok(true)
 }
 
 fn main() {
@@ -108,17 +412,21 @@ fn main() {
     // capture-and pretty-print the error-chain.
     // All actual logic should happen in `run`.
 
-    if let Err(e) = run() {
-        for cause in e.causes() {
-            println!("Error: {}", cause);
+    match run() {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            for cause in e.iter_chain() {
+                println!("Error: {}", cause);
+            }
+            drop(e);
+            std::process::exit(1);
         }
-        drop(e);
-        std::process::exit(1);
     }
 }
 
-fn print_lints(dir: &Path) {
-    let maybe_config = shelly::load_config_from_dir(&dir)
+fn print_lints(dir: &Path, config_opt: &ConfigOpt) {
+    let maybe_config = shelly::load_config(dir, config_opt.config.as_deref())
         .and_then(|config| shelly::lint::Config::from_config_file(&config));
 
     let config = match maybe_config {
@@ -138,94 +446,450 @@ fn print_lints(dir: &Path) {
         } else {
             String::new()
         };
-        println!("{:>30}: {:?}{}", lint.slug(), level, note);
+        println!("{:>30}: {:<10} {:?}{}", lint.slug(), lint.category().name(), level, note);
     }
 
     println!(r"
 Use `shelly.toml` config or -A/-W/-D flags for `analyze` subcommand
-to change the default levels.");
+to change the default levels. They accept category names too, eg. `-A style`.");
 }
 
-struct CliEmitter {}
+/// Runs the analysis and returns whether it passed, ie. there were no errors
+/// and no more warnings than `--max-warnings`
+fn analyze(dir: &Path, analyze_opt: &AnalyzeOpt) -> Result<bool, Error> {
+    if analyze_opt.interactive {
+        triage(dir, analyze_opt)?;
+        return Ok(true);
+    }
+
+    let out: Box<dyn Write> = match &analyze_opt.output {
+        Some(path) => {
+            // Escape codes would only clutter the file
+            Paint::disable();
+            let file = fs::File::create(path).with_context(|_| format!("Creating {}", path.display()))?;
+            Box::new(io::BufWriter::new(file))
+        }
+        None => Box::new(io::stdout()),
+    };
+
+    let mut report = analyze_opt.format.report(out)?;
+    let summary = shelly::run(dir, analyze_opt.run.run_opt(), &mut *report)?;
+    report.finish()?;
 
-impl shelly::Emitter for CliEmitter {
-    fn emit(&mut self, item: EmittedItem) {
-        // Style of error message inspired by Rust
+    // On stderr, so it doesn't break machine-readable output
+    match &analyze_opt.output {
+        Some(path) => eprintln!("{}, written to {}", summary, path.display()),
+        None => eprintln!("{}", summary),
+    }
 
-        let line_no = item.location.span
-            .as_ref()
-            .map_or_else(
-                || " ".to_string(),
-                |span| span.start.line.to_string()
-            );
+    let too_many_warnings = analyze_opt.max_warnings.is_some_and(|max| summary.warnings > max);
+    if too_many_warnings {
+        eprintln!(
+            "error: {}, more than allowed by --max-warnings",
+            shelly::plural(summary.warnings, "warning"),
+        );
+    }
+
+    Ok(summary.errors == 0 && !too_many_warnings)
+}
 
-        let offset = || {
-            for _ in 0..line_no.len() {
-                print!(" ")
+/// Runs the analysis on every change in the directory.
+///
+/// After the first, full run, only the changed files and files importing
+/// them (directly or not) are analyzed. A change of the config (also the one
+/// given by `--config`), builtins.lock or baseline triggers a full run.
+/// Other files are ignored.
+fn watch(dir: &Path, watch_opt: &WatchOpt) -> Result<(), Error> {
+    let (sender, receiver) = ::std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(dir, notify::RecursiveMode::Recursive)?;
+
+    // The config may be outside of the directory
+    let config_path = match &watch_opt.run.config_opt.config {
+        Some(path) => {
+            watcher.watch(path, notify::RecursiveMode::NonRecursive)?;
+            Some(path.canonicalize()?)
+        }
+        None => None,
+    };
+
+    let mut graph = ImportGraph::default();
+    // Directories given with the files stay in `run_opt.dirs`
+    let mut files = watch_opt.run.run_opt().files;
+
+    loop {
+        let mut run_opt = watch_opt.run.run_opt();
+        run_opt.files = files;
+
+        if let Err(e) = watched_run(dir, &watch_opt.format, run_opt, &mut graph) {
+            for cause in e.iter_chain() {
+                println!("Error: {}", cause);
             }
-        };
+        }
 
-        let blue = Color::Blue.style().bold();
-        let pipe = blue.paint("|");
+        println!("Watching for changes…");
 
-        let (accent_style, message_kind) = match item.kind {
-            shelly::MessageKind::Error   => (Color::Red.style().bold(), "error"),
-            shelly::MessageKind::Warning => (Color::Yellow.style().bold(), "warning"),
+        files = loop {
+            let changed = wait_for_changes(&receiver)?;
+
+            match graph.rerun(&changed, config_path.as_deref()) {
+                Some(Rerun::Everything) => {
+                    // Exclusions may have changed
+                    graph = ImportGraph::default();
+                    break watch_opt.run.run_opt().files;
+                }
+                Some(Rerun::Files(files)) => break files,
+                None => (),
+            }
         };
 
-        println!(
-            "{}: {}",
-            accent_style.paint(message_kind),
-            Style::new().bold().paint(item.message)
-        );
+        println!();
+    }
+}
 
-        offset();
-        println!(
-            "{} {}{}",
-            blue.paint("-->"),
-            item.location.file.display(),
-            item.location.span.as_ref().map(
-                |span| format!(":{}:{}", span.start.line, span.start.col)
-            ).unwrap_or_default()
-        );
+fn watched_run(dir: &Path, format: &FormatFlags, run_opt: RunOpt, graph: &mut ImportGraph)
+    -> Result<(), Error>
+{
+    let mut report = format.report(Box::new(io::stdout()))?;
+    shelly::run(dir, run_opt, &mut graph.recorder(&mut *report))?;
+    report.finish()
+}
+
+/// Waits for changes, ignoring plain accesses, and returns the changed paths.
+///
+/// Changes coming shortly one after another are returned together,
+/// so eg. saving multiple files triggers only one analysis.
+fn wait_for_changes(receiver: &::std::sync::mpsc::Receiver<notify::Result<notify::Event>>)
+    -> Result<Vec<PathBuf>, Error>
+{
+    let timeout = ::std::time::Duration::from_millis(200);
+
+    let mut changed = Vec::new();
+    let mut received = receiver.recv()?;
+    loop {
+        let event = received?;
+        if !event.kind.is_access() {
+            changed.extend(event.paths);
+        }
+
+        received = match receiver.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(_) if changed.is_empty() => receiver.recv()?,
+            Err(_) => return Ok(changed),
+        };
+    }
+}
 
-        if let Some(span) = item.location.span {
-            offset();
-            println!(" {}", pipe);
+fn print_formats() {
+    println!("Available output formats:");
+    for format in shelly::formats::output_formats() {
+        println!("{:>10}: {}", format.name, format.description);
+    }
+}
 
-            let line = span.start.find_line(&item.location.source);
-            println!("{} {} {}", blue.paint(&line_no), pipe, line);
+/// Interactive mode of `analyze`.
+///
+/// Chosen actions are performed at the end, so fixes
+/// don't get invalidated by inserted comments.
+fn triage(dir: &Path, analyze_opt: &AnalyzeOpt) -> Result<(), Error> {
+    let mut emitter = shelly::VecEmitter::new();
+    shelly::run(dir, analyze_opt.run.run_opt(), &mut emitter)?;
 
-            // Now, let's print squiggles
+    let mut fixes = Vec::new();
+    let mut allows = Vec::new();
+    let mut baseline = shelly::baseline::Baseline::load(dir)?;
+    let mut baselined = 0;
 
-            offset();
-            print!(" {} ", pipe);
+    let total = emitter.len();
 
-            let underlinee = &item.location.source[span.start.byte as usize .. span.end.byte as usize];
-            // Trim the span to current line
-            let underlinee = underlinee.split(&['\r', '\n'] as &[char]).next().unwrap();
-            let width = ::std::cmp::max(1, underlinee.chars().count());
+    'items: for (i, item) in emitter.emitted_items.into_iter().enumerate() {
+        println!("[{}/{}]", i + 1, total);
+        PrettyEmitter::stdout().emit(item.clone());
 
-            // Print space before squiggles.
-            // We're printing it char-by-char to handle tabs the same way as original line.
-            for c in line.chars().take(span.start.col as usize - 1) {
-                if c.is_whitespace() {
-                    print!("{}", c);
-                } else {
-                    print!(" ");
+        let mut actions = Vec::new();
+        if item.fix.is_some() {
+            actions.push("[f]ix");
+        }
+        if item.location.span.is_some() {
+            actions.push("[a]llow");
+        }
+        actions.extend(&["[b]aseline", "[s]kip", "[q]uit"]);
+        let question = format!("{}? ", actions.join(", "));
+
+        loop {
+            let answer = match prompt(&question)? {
+                Some(answer) => answer,
+                None => break 'items,
+            };
+
+            match &*answer {
+                "f" if item.fix.is_some() => fixes.push(shelly::json::Message::from_item(&item, true)),
+                "a" if item.location.span.is_some() => {
+                    let reason = prompt("Reason (optional): ")?.unwrap_or_default();
+                    allows.push((item, reason));
+                }
+                "b" => {
+                    baseline.insert(item.lint, &item.message, &item.location);
+                    baselined += 1;
                 }
+                "s" => (),
+                "q" => break 'items,
+                _ => continue,
             }
+            break;
+        }
+        println!();
+    }
 
-            println!("{}", accent_style.paint("^".repeat(width)));
+    let applied = shelly::json::apply_fixes(&fixes, &[])?;
+    for reason in &applied.skipped {
+        eprintln!("warning: {}", reason);
+    }
+
+    let mut allowed = 0;
+    for (item, reason) in &allows {
+        if insert_allow_comment(item, reason)? {
+            allowed += 1;
         }
+    }
 
-        if let Some(notes) = item.notes {
-            for line in notes.lines() {
-                offset();
-                println!(" {} {}", blue.paint("="), line);
-            }
+    if baselined > 0 {
+        baseline.save()?;
+    }
+
+    println!(
+        "Applied {}, added {} and {}",
+        shelly::plural(applied.applied, "fix"),
+        shelly::plural(allowed, "allow comment"),
+        shelly::plural(baselined, "baseline entry"),
+    );
+
+    Ok(())
+}
+
+/// Asks a question and returns the trimmed answer, or `None` at the end of input
+fn prompt(question: &str) -> Result<Option<String>, Error> {
+    print!("{}", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(answer.trim().to_owned()))
+}
+
+/// Appends an `allow` comment to the line of the message.
+///
+/// Returns whether the comment was inserted.
+fn insert_allow_comment(item: &EmittedItem, reason: &str) -> Result<bool, Error> {
+    let span = match item.location.span {
+        Some(span) => span,
+        None => return Ok(false),
+    };
+
+    let path = &item.location.file;
+    let (source, encoding) = shelly::read_text(path)?;
+    let mut lines: Vec<&str> = source.split('\n').collect();
+
+    let index = span.start.line as usize - 1;
+    let expected = span.start.find_line(&item.location.source).trim_end_matches('\r');
+    let line = match lines.get(index) {
+        Some(line) if line.trim_end_matches('\r').trim_start_matches('\u{feff}') == expected => *line,
+        _ => {
+            eprintln!("warning: {}: line changed since the analysis, not allowing `{}`", path.display(), item.lint.slug());
+            return Ok(false);
         }
+    };
 
-        println!();
+    if line.contains('#') {
+        eprintln!(
+            "warning: {}:{}: line already contains a comment, add `allow {}` to it manually",
+            path.display(), span.start.line, item.lint.slug(),
+        );
+        return Ok(false);
+    }
+
+    let (text, cr) = match line.ends_with('\r') {
+        true  => (&line[..line.len() - 1], "\r"),
+        false => (line, ""),
+    };
+    let reason = if reason.is_empty() { String::new() } else { format!(" -- {}", reason) };
+    let commented = format!("{} # allow {}{}{}", text, item.lint.slug(), reason, cr);
+
+    lines[index] = &commented;
+    fs::write(path, encoding.encode(&lines.join("\n")))?;
+
+    Ok(true)
+}
+
+fn show_config(dir: &Path, run_flags: &RunFlags) -> Result<(), Error> {
+    let values = shelly::effective_config(dir, &run_flags.run_opt())?;
+
+    let width = values.iter().map(|value| value.key.len() + value.value.len()).max().unwrap_or(0);
+
+    for value in values {
+        let assignment = format!("{} = {}", value.key, value.value);
+        println!("{:<width$}  # {}", assignment, value.source, width = width + 3);
     }
+
+    Ok(())
+}
+
+fn fix(dir: &Path, fix_opt: &FixOpt) -> Result<(), Error> {
+    let mut emitter = shelly::json::JsonEmitter::new(true);
+    shelly::run(dir, fix_opt.run.run_opt(), &mut emitter)?;
+
+    apply_or_preview(&emitter.messages, &fix_opt.lints, fix_opt.dry_run)?;
+
+    let unfixed = emitter.messages.iter().filter(|message| message.fix.is_none()).count();
+    println!(
+        "{} {} no automatic fix",
+        shelly::plural(unfixed, "message"),
+        if unfixed == 1 { "has" } else { "have" },
+    );
+
+    Ok(())
+}
+
+/// Applies the fixes or, with `dry_run`, prints their diffs
+fn apply_or_preview(messages: &[shelly::json::Message], lints: &[Lint], dry_run: bool) -> Result<(), Error> {
+    let applied = if dry_run {
+        shelly::json::preview_fixes(messages, lints)?
+    } else {
+        shelly::json::apply_fixes(messages, lints)?
+    };
+
+    for reason in &applied.skipped {
+        eprintln!("warning: {}", reason);
+    }
+
+    if dry_run {
+        for file in &applied.files {
+            print!("{}", file.unified_diff());
+        }
+        println!("Would apply {}", applied);
+    } else {
+        println!("Applied {}", applied);
+    }
+
+    Ok(())
+}
+
+fn apply_fixes(apply_opt: &ApplyFixesOpt) -> Result<(), Error> {
+    let results = fs::read_to_string(&apply_opt.results)?;
+    let messages: Vec<shelly::json::Message> = serde_json::from_str(&results)?;
+
+    apply_or_preview(&messages, &apply_opt.lints, apply_opt.dry_run)
+}
+
+fn init(dir: &Path, config_opt: &ConfigOpt) -> Result<(), Error> {
+    let path = match &config_opt.config {
+        Some(path) => path.clone(),
+        None => dir.join("shelly.toml"),
+    };
+
+    for path in &[path.clone(), dir.join("Shelly.toml")] {
+        if path.exists() {
+            return Err(failure::err_msg(format!("{} already exists", path.display())));
+        }
+    }
+
+    let config = shelly::starter_config(dir)?;
+    fs::write(&path, config).with_context(|_| format!("Writing {}", path.display()))?;
+    println!("Written {}", path.display());
+
+    Ok(())
+}
+
+fn dump_builtins(dir: &Path, config_opt: &ConfigOpt) -> Result<(), Error> {
+    let mut emitter = shelly::VecEmitter::new();
+    let builtins = shelly::dump_builtins(dir, config_opt.config.as_deref(), &mut emitter)?;
+
+    // Report problems on stderr, so the output can be redirected to a file
+    for item in emitter.emitted_items {
+        eprintln!("{}: {} ({})", item.heading(), item.message, item.location.file.display());
+    }
+
+    println!("# Generated by `shelly dump-builtins`");
+    for builtin in builtins {
+        println!("{}", builtin);
+    }
+
+    Ok(())
+}
+
+fn import_builtins(import_opt: &ImportOpt) -> Result<(), Error> {
+    let path = &import_opt.snapshot;
+    let snapshot = fs::read_to_string(path).with_context(|_| format!("Reading {}", path.display()))?;
+    let builtins = shelly::import_builtins(&snapshot).with_context(|_| format!("Importing {}", path.display()))?;
+
+    match &import_opt.output {
+        Some(output) => {
+            fs::write(output, builtins).with_context(|_| format!("Writing {}", output.display()))?;
+            println!("Written {}", output.display());
+        }
+        None => print!("{}", builtins),
+    }
+
+    Ok(())
+}
+
+fn graph(dir: &Path, graph_opt: &GraphOpt) -> Result<(), Error> {
+    let run_opt = RunOpt { config: graph_opt.config_opt.config.clone(), ..RunOpt::default() };
+    let graph = shelly::graph::import_graph(dir, run_opt)?;
+
+    let rendered = match &*graph_opt.format {
+        "json" => serde_json::to_string_pretty(&graph)? + "\n",
+        _ => graph.to_dot(),
+    };
+
+    match &graph_opt.output {
+        Some(output) => {
+            fs::write(output, rendered).with_context(|_| format!("Writing {}", output.display()))?;
+            println!("Written {}", output.display());
+        }
+        None => print!("{}", rendered),
+    }
+
+    Ok(())
+}
+
+/// Prints the chain of imports bringing the function, returns false if it's not in scope
+fn why(dir: &Path, why_opt: &WhyOpt) -> Result<bool, Error> {
+    let run_opt = RunOpt { config: why_opt.config_opt.config.clone(), ..RunOpt::default() };
+    let file = &why_opt.file;
+    let function = &why_opt.function;
+
+    let (chain, imports, definition) = match shelly::why(dir, run_opt, file, function)? {
+        shelly::Origin::Defined { chain, imports, definition } => (chain, imports, definition),
+        shelly::Origin::Assumed(reason) => {
+            println!("`{}` is assumed to exist in {}, because {}", function, file.display(), reason);
+            return Ok(true);
+        }
+        shelly::Origin::NotInScope => {
+            println!("`{}` is not in scope of {}", function, file.display());
+            return Ok(false);
+        }
+    };
+
+    let files: Vec<_> = chain.iter().map(|path| path.display().to_string()).collect();
+    println!("{} defines `{}`", files.join(" ← "), function);
+
+    for (importer, import) in chain.iter().zip(&imports) {
+        match import {
+            Some(location) => println!("  {}", source_line(location)),
+            None => println!("  {}: prelude from `extras.preludes`", importer.display()),
+        }
+    }
+    println!("  {}", source_line(&definition));
+
+    Ok(true)
+}
+
+/// `file:line: code` of the line where the location starts
+fn source_line(location: &shelly::Location) -> String {
+    let line = location.span.map_or(1, |span| span.start.line);
+    let code = location.source.lines().nth(line as usize - 1).unwrap_or_default();
+    format!("{}:{}: {}", location.file.display(), line, code.trim())
 }
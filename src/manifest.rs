@@ -0,0 +1,139 @@
+//! Parsing of module manifests (`.psd1` files)
+
+use syntax::v2::{self, Delimiter, FileStr, Span, TokenTree as TT};
+
+/// Fields of a module manifest relevant to scope analysis
+#[derive(Debug, Default)]
+pub struct Manifest {
+    /// `RootModule` (or `ModuleToProcess` in older manifests)
+    pub root_module: Option<Entry>,
+
+    /// `NestedModules`
+    pub nested_modules: Vec<Entry>,
+
+    /// `FunctionsToExport`, `None` if all functions are exported
+    pub functions_to_export: Option<Vec<Entry>>,
+
+    /// `AliasesToExport`, `None` if all aliases are exported
+    pub aliases_to_export: Option<Vec<Entry>>,
+}
+
+/// A string value in a manifest
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: String,
+    pub span: Span,
+}
+
+/// Parses a module manifest.
+///
+/// Note: Assumes BOM (byte order mark) is stripped.
+pub fn parse(source: &str) -> v2::Result<Manifest> {
+    let (stream, errors) = v2::parse(source, false);
+
+    // Manifests are simple enough to not bother with partial results
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error);
+    }
+
+    let hashtable = stream.iter().find_map(|tt| match tt {
+        TT::Group { interior, delimiter: Delimiter::Brace, prefix: Some('@'), .. } => Some(interior),
+        _ => None,
+    });
+
+    let hashtable = match hashtable {
+        Some(hashtable) => hashtable,
+        None => return Err(v2::Error {
+            what: "Module manifest should contain a hashtable".into(),
+            where_: v2::Location::start(),
+        }),
+    };
+
+    let mut manifest = Manifest::default();
+
+    for statement in hashtable.split(|tt| matches!(tt, TT::Symbol { symbol: ';', .. })) {
+        let (key, value) = match statement {
+            [TT::Field { ident, .. }, TT::Symbol { symbol: '=', .. }, value @ ..] => {
+                (ident.cut_from(source), value)
+            }
+            _ => continue,
+        };
+
+        let mut entries = Vec::new();
+        collect_strings(value, source, &mut entries);
+
+        match &*key.to_lowercase() {
+            "rootmodule" | "moduletoprocess" => {
+                manifest.root_module = entries.into_iter().next();
+            }
+            "nestedmodules" => {
+                manifest.nested_modules = entries;
+            }
+            "functionstoexport" if !entries.iter().any(|entry| entry.value == "*") => {
+                manifest.functions_to_export = Some(entries);
+            }
+            "aliasestoexport" if !entries.iter().any(|entry| entry.value == "*") => {
+                manifest.aliases_to_export = Some(entries);
+            }
+            _ => (),
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Collects string literals from a value, including `@( )` arrays.
+fn collect_strings(stream: &[TT], source: &str, entries: &mut Vec<Entry>) {
+    for tt in stream {
+        match tt {
+            &TT::String { span, .. } => {
+                let value = FileStr::from(span).cut_from(source).trim_matches(&['"', '\''] as &[char]);
+                entries.push(Entry { value: value.to_owned(), span });
+            }
+            TT::Group { interior, delimiter: Delimiter::Parenthesis, .. } => {
+                collect_strings(interior, source, entries);
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn test_parse() {
+    let source = r#"
+        # Module manifest for module 'Utils'
+        @{
+            RootModule = 'Utils.psm1'
+            ModuleVersion = '1.0.0'
+            NestedModules = @('Nested\A.psm1', "Nested\B.psm1")
+            FunctionsToExport = @(
+                'Get-Util',
+                'Set-Util'
+            )
+            AliasesToExport = 'gu'
+            PrivateData = @{
+                PSData = @{ Tags = @('foo') }
+            }
+        }
+    "#;
+
+    let manifest = parse(source).unwrap();
+
+    assert_eq!(manifest.root_module.unwrap().value, "Utils.psm1");
+
+    let nested: Vec<_> = manifest.nested_modules.iter().map(|e| &*e.value).collect();
+    assert_eq!(nested, [r"Nested\A.psm1", r"Nested\B.psm1"]);
+
+    let exported: Vec<_> = manifest.functions_to_export.unwrap().into_iter().map(|e| e.value).collect();
+    assert_eq!(exported, ["Get-Util", "Set-Util"]);
+
+    let aliases: Vec<_> = manifest.aliases_to_export.unwrap().into_iter().map(|e| e.value).collect();
+    assert_eq!(aliases, ["gu"]);
+}
+
+#[test]
+fn test_export_everything() {
+    let manifest = parse("@{ RootModule = 'X.psm1'; FunctionsToExport = '*' }").unwrap();
+    assert!(manifest.functions_to_export.is_none());
+    assert!(manifest.aliases_to_export.is_none());
+}
@@ -0,0 +1,79 @@
+use failure::{Error, ResultExt};
+use regex::Regex;
+
+use std::rc::Rc;
+
+use config::PatternKind;
+use preprocess::Parsed;
+use syntax::{Definition, Item, Span, Usage};
+use ConfigFile;
+
+/// Custom patterns producing synthetic definitions and usages
+#[derive(Default)]
+pub struct Config {
+    patterns: Vec<Pattern>,
+}
+
+struct Pattern {
+    /// Regex matched against each line. The first capture group is the item name.
+    regex: Regex,
+    kind: PatternKind,
+    class: bool,
+}
+
+impl Config {
+    pub fn from_config_file(config_file: &ConfigFile) -> Result<Config, Error> {
+        let mut config = Config::default();
+
+        for pattern in config_file.patterns.iter().flatten() {
+            let regex = Regex::new(&pattern.regex)
+                .with_context(|_| format!("Invalid pattern: {}", pattern.regex))?;
+
+            if regex.captures_len() < 2 {
+                bail!("Pattern `{}` should have a capture group with the item name", pattern.regex);
+            }
+
+            let class = match pattern.item.as_deref() {
+                None | Some("function") => false,
+                Some("class")           => true,
+                Some(other)             => bail!("Unknown item kind in pattern: {}", other),
+            };
+
+            config.patterns.push(Pattern { regex, kind: pattern.kind, class });
+        }
+
+        Ok(config)
+    }
+}
+
+/// Adds definitions and usages matched by custom patterns.
+pub fn preprocess(file: &mut Parsed, config: &Config) {
+    if config.patterns.is_empty() {
+        return;
+    }
+
+    let source = Rc::clone(&file.source);
+
+    for (line, line_no) in source.lines().zip(1..) {
+        for pattern in &config.patterns {
+            for captures in pattern.regex.captures_iter(line) {
+                let name = match captures.get(1) {
+                    Some(name) => name.as_str(),
+                    None => continue,
+                };
+
+                let span = Span::from_fragment(line_no, name, &source);
+                let item = if pattern.class {
+                    Item::class(name.to_owned())
+                } else {
+                    Item::function(name.to_owned())
+                };
+
+                match pattern.kind {
+                    PatternKind::Definition => file.definitions.push(Definition { span, item, visible_in: None }),
+                    PatternKind::Usage      => file.usages.push(Usage { span, item }),
+                }
+            }
+        }
+    }
+}
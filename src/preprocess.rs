@@ -1,22 +1,99 @@
-use failure::Error;
+use failure::{Error, ResultExt};
+
+use unicase::UniCase;
 
 use std::collections::BTreeMap as Map;
+use std::collections::BTreeSet as Set;
 use std::rc::Rc;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::panic;
 
 use lint::Lint;
 use lint::Emitter;
+use manifest;
 use syntax;
+use syntax::{Item, Span};
+use ConfigFile;
 use RunOpt;
 
+/// Modules available for `Import-Module`, by name
+pub type Modules = Map<UniCase<String>, PathBuf>;
+
+/// Directories of environment variables from `[imports] env_roots`
+pub type EnvRoots = Map<UniCase<String>, PathBuf>;
+
+/// Default limit checked by `long-lines`
+pub(crate) const DEFAULT_MAX_LINE_LENGTH: usize = 120;
+
+/// Options of `long-lines`, in `[lint-options.long-lines]`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LongLinesOptions {
+    /// Maximal line length, in characters
+    pub(crate) max: Option<usize>,
+}
+
+/// Maximal line length from `[lint-options.long-lines]`
+/// or from the deprecated top-level `max_line_length`
+pub(crate) fn max_line_length(config_file: &ConfigFile) -> Result<Option<usize>, Error> {
+    let options: LongLinesOptions = config_file.lint_options(Lint::LongLines)?.unwrap_or_default();
+    Ok(options.max.or(config_file.max_line_length))
+}
+
+pub struct Config {
+    /// Maximal line length, in characters
+    max_line_length: usize,
+
+    env_roots: EnvRoots,
+}
+
+impl Config {
+    /// Paths in the config are relative to `config_dir`
+    pub fn from_config_file(config_file: &ConfigFile, config_dir: &Path) -> Result<Config, Error> {
+        let env_roots = config_file.imports.iter().flat_map(|imports| &imports.env_roots).flatten();
+
+        Ok(Config {
+            max_line_length: max_line_length(config_file)?.unwrap_or(DEFAULT_MAX_LINE_LENGTH),
+            env_roots: env_roots.map(|(name, dir)| (UniCase::new(name.clone()), config_dir.join(dir))).collect(),
+        })
+    }
+}
+
 /// Parsed and preprocessed source file
 #[derive(Debug)]
 pub struct Parsed {
     pub imports: Map<PathBuf, syntax::Import>,
+
+    /// Imports of files which don't exist, reported by `nonexisting-imports`
+    pub broken_imports: Vec<syntax::Import>,
+
     pub definitions: Vec<syntax::Definition>,
     pub usages: Vec<syntax::Usage>,
     pub testcases: Vec<syntax::Testcase>,
+    pub describes: Vec<syntax::Describe>,
+
+    /// Scriptblocks of Pester's `BeforeAll` and `BeforeEach`
+    pub setup_blocks: Vec<syntax::Span>,
+
+    pub mocks: Vec<syntax::Mock>,
+    pub env_variables: Vec<syntax::EnvVariable>,
+    pub comments: Vec<syntax::Span>,
+
+    /// Names of modules from `#Requires -Modules`
+    pub required_modules: Vec<String>,
+
+    /// Version from the `#Requires -Version` statement
+    pub required_version: Option<syntax::RequiredVersion>,
+
+    pub kind: FileKind,
+
+    /// Items visible to files importing this one
+    pub exports: Exports,
+
+    /// Functions and aliases listed by a module manifest, to be checked
+    /// against its modules. Empty if any of the modules is not a script module.
+    pub manifest_exports: Vec<manifest::Entry>,
 
     pub source: Rc<str>,
 
@@ -28,76 +105,428 @@ pub struct Parsed {
 impl Default for Parsed {
     fn default() -> Self {
         Parsed {
-            imports:       Default::default(),
-            definitions:   Default::default(),
-            usages:        Default::default(),
-            testcases:     Default::default(),
-            original_path: Default::default(),
-            source:        From::from(""),
+            imports:          Default::default(),
+            broken_imports:   Default::default(),
+            definitions:      Default::default(),
+            usages:           Default::default(),
+            testcases:        Default::default(),
+            describes:        Default::default(),
+            setup_blocks:     Default::default(),
+            mocks:            Default::default(),
+            env_variables:    Default::default(),
+            comments:         Default::default(),
+            required_modules: Default::default(),
+            required_version: Default::default(),
+            kind:             FileKind::Script,
+            exports:          Exports::Everything,
+            manifest_exports: Default::default(),
+            original_path:    Default::default(),
+            source:           From::from(""),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FileKind {
+    /// `.ps1`
+    Script,
+
+    /// `.psm1`
+    Module,
+
+    /// `.psd1`
+    Manifest,
+}
+
+impl FileKind {
+    pub fn from_path(path: &Path) -> Option<FileKind> {
+        FileKind::from_extension(path.extension()?.to_str()?)
+    }
+
+    /// Kind of files with a given extension (without the dot)
+    pub fn from_extension(extension: &str) -> Option<FileKind> {
+        match &*extension.to_lowercase() {
+            "ps1"  => Some(FileKind::Script),
+            "psm1" => Some(FileKind::Module),
+            "psd1" => Some(FileKind::Manifest),
+            _      => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Exports {
+    /// Everything in scope, as with dot-sourcing
+    Everything,
+
+    /// Only functions, either all of them or a given list.
+    /// The names can contain `*` and `?` wildcards.
+    Functions(Option<Set<UniCase<String>>>),
+}
+
+impl Exports {
+    pub fn exports(&self, item: &Item<UniCase<&str>>) -> bool {
+        match self {
+            Exports::Everything => true,
+            Exports::Functions(None) => item.is_function(),
+            Exports::Functions(Some(functions)) => {
+                item.is_function() && functions.iter().any(|function| matches_wildcard(function, &item.name))
+            }
         }
     }
 }
 
+/// Case-insensitive match of a name against a PowerShell wildcard pattern
+fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    // Position in the pattern and in the name right after the last `*`
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 #[derive(Debug)]
 pub enum PreprocessOutput {
     /// Parsed and preprocessed file
-    Valid(Parsed),
-
-    /// A file can't be preprocessed since it contains invalid imports
-    InvalidImports,
+    Valid(Box<Parsed>),
 
     /// A file can't be preprocessed since it contains syntax errors
     SyntaxErrors,
 }
 
 /// Parses and preprocesses a file for further analysys.
-pub fn parse_and_preprocess(path: &Path, run_opt: &RunOpt, emitter: &mut Emitter) -> Result<PreprocessOutput, Error> {
-    let source = fs::read_to_string(path)?;
+///
+/// Module manifests are handled by `parse_manifest`.
+pub fn parse_and_preprocess(
+    path: &Path,
+    modules: &Modules,
+    config: &Config,
+    run_opt: &RunOpt,
+    emitter: &mut Emitter,
+) -> Result<PreprocessOutput, Error>
+{
+    let kind = FileKind::from_path(path).unwrap_or(FileKind::Script);
+
+    let source = match read_source(path, emitter)? {
+        Some(source) => source,
+        None         => return Ok(PreprocessOutput::SyntaxErrors),
+    };
 
     // Strip BOM
     // TODO move this to muncher after getting rid of regexes in syntax::parse.
-    let source = source.trim_left_matches('\u{feff}');
+    let source = source.trim_start_matches('\u{feff}');
 
     if run_opt.debug_parser { println!("Trying to parse {}", path.display()); }
-    let file = match syntax::parse(&source, run_opt.debug_parser) {
+    let file = catch_ice(|| Ok(syntax::parse(source, run_opt.debug_parser)));
+
+    let source = Rc::from(source);
+
+    let file = match file {
         Ok(file) => file,
         Err(e)   => {
-            e.where_.to_span()
-                .in_file_source(path, Rc::from(source))
-                .lint(Lint::SyntaxErrors, format!("syntax error: {}", e.what))
-                .note("If this is valid PowerShell syntax, please file an issue")
-                .emit(emitter);
+            emit_syntax_error(e, path, &source, emitter);
             return Ok(PreprocessOutput::SyntaxErrors);
         }
     };
 
-    let source = Rc::from(source);
+    // The parser has recovered from these errors,
+    // so the rest of the file can still be analyzed.
+    for e in file.errors {
+        emit_syntax_error(e, path, &source, emitter);
+    }
+
+    if emitter.any_enabled(&[Lint::LongLines]) {
+        check_line_lengths(path, &source, config.max_line_length, emitter);
+    }
+
+    // Files with broken imports are still analyzed, without these imports
+    let (resolved_imports, broken_imports) =
+        resolve_imports(&source, path, file.imports, modules, &config.env_roots, emitter)?;
 
-    let resolved_imports = match resolve_imports(&source, path, file.imports, emitter)? {
-        Some(imports) => imports,
-        None => return Ok(PreprocessOutput::InvalidImports),
+    let exports = match kind {
+        FileKind::Module => Exports::Functions(file.exported_functions.map(|functions| {
+            functions.into_iter().map(UniCase::new).collect()
+        })),
+        _                => Exports::Everything,
     };
 
-    Ok(PreprocessOutput::Valid(Parsed {
+    Ok(PreprocessOutput::Valid(Box::new(Parsed {
         imports: resolved_imports,
+        broken_imports,
         definitions: file.definitions,
         usages: file.usages,
         testcases: file.testcases,
+        describes: file.describes,
+        setup_blocks: file.setup_blocks,
+        mocks: file.mocks,
+        env_variables: file.env_variables,
+        comments: file.comments,
+        required_modules: file.required_modules,
+        required_version: file.required_version,
+        kind,
+        exports,
+        manifest_exports: Vec::new(),
+        original_path: path.to_owned(),
+        source,
+    })))
+}
+
+/// Text encoding of a source file, kept when the file is written back
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Encoding {
+    /// Also with the byte order mark, which is then kept in the decoded text
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Encodes text decoded by `decode`, restoring the byte order mark of UTF-16
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        let utf16 = |to_bytes: fn(u16) -> [u8; 2]| {
+            ::std::iter::once(0xfeff).chain(text.encode_utf16()).flat_map(to_bytes).collect()
+        };
+
+        match self {
+            Encoding::Utf8    => text.as_bytes().to_vec(),
+            Encoding::Utf16Le => utf16(u16::to_le_bytes),
+            Encoding::Utf16Be => utf16(u16::to_be_bytes),
+        }
+    }
+}
+
+/// Decodes contents of a file, as UTF-16 if it starts with its BOM
+/// (which is stripped), as UTF-8 otherwise.
+///
+/// Returns `None` if the file is neither UTF-8 nor UTF-16.
+pub fn decode(bytes: &[u8]) -> Option<(String, Encoding)> {
+    let utf16 = |bytes: &[u8], to_unit: fn([u8; 2]) -> u16| {
+        let pairs = bytes.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return None;
+        }
+        let units: Vec<u16> = pairs.map(|pair| to_unit([pair[0], pair[1]])).collect();
+        String::from_utf16(&units).ok()
+    };
+
+    match bytes.get(..2) {
+        Some([0xff, 0xfe]) => utf16(&bytes[2..], u16::from_le_bytes).map(|text| (text, Encoding::Utf16Le)),
+        Some([0xfe, 0xff]) => utf16(&bytes[2..], u16::from_be_bytes).map(|text| (text, Encoding::Utf16Be)),
+        _                  => ::std::str::from_utf8(bytes).ok().map(|text| (text.to_owned(), Encoding::Utf8)),
+    }
+}
+
+/// Reads and decodes a file, eg. to apply fixes to it
+pub fn read_text(path: &Path) -> Result<(String, Encoding), Error> {
+    let bytes = fs::read(path).with_context(|_| format!("Reading {}", path.display()))?;
+    decode(&bytes).ok_or_else(|| format_err!("{} is neither UTF-8 nor UTF-16 text", path.display()))
+}
+
+/// Reads a file, decoding it as UTF-16 if it starts with its BOM.
+///
+/// Files which are neither UTF-8 nor UTF-16 are
+/// reported as syntax errors and `None` is returned.
+fn read_source(path: &Path, emitter: &mut Emitter) -> Result<Option<String>, Error> {
+    let bytes = fs::read(path)?;
+    let source = decode(&bytes).map(|(source, _)| source);
+
+    if source.is_none() {
+        ::Location {
+            file: path.to_owned(),
+            source: Rc::from(String::from_utf8_lossy(&bytes)),
+            span: None,
+        }
+            .lint(Lint::SyntaxErrors, "file is neither UTF-8 nor UTF-16 text")
+            .note("Save the file as UTF-8 to analyze it")
+            .emit(emitter);
+    }
+
+    Ok(source)
+}
+
+fn emit_syntax_error(e: syntax::v2::Error, path: &Path, source: &Rc<str>, emitter: &mut Emitter) {
+    e.where_.to_span()
+        .in_file_source(path, Rc::clone(source))
+        .lint(Lint::SyntaxErrors, format!("syntax error: {}", e.what))
+        .note("If this is valid PowerShell syntax, please file an issue")
+        .emit(emitter);
+}
+
+/// Reports lines longer than `max_line_length` characters.
+///
+/// Here-string bodies and lines containing URLs are skipped,
+/// as they usually can't be wrapped.
+fn check_line_lengths(path: &Path, source: &Rc<str>, max_line_length: usize, emitter: &mut Emitter) {
+    // Terminator of the here-string we're in
+    let mut here_string_end = None;
+
+    for (line_no, line) in (1..).zip(source.lines()) {
+        if let Some(end) = here_string_end {
+            if !line.starts_with(end) {
+                continue;
+            }
+            here_string_end = None;
+        }
+
+        let trimmed = line.trim_end();
+        if trimmed.ends_with("@\"") {
+            here_string_end = Some("\"@");
+        } else if trimmed.ends_with("@'") {
+            here_string_end = Some("'@");
+        }
+
+        if line.contains("://") {
+            continue;
+        }
+
+        let overflow = match line.char_indices().nth(max_line_length) {
+            Some((offset, _)) => &line[offset..],
+            None => continue,
+        };
+
+        Span::from_fragment(line_no, overflow, source)
+            .in_file_source(path, Rc::clone(source))
+            .lint(Lint::LongLines, "line too long")
+            .note(format!(
+                "Line has {} characters, the limit is {}",
+                line.chars().count(),
+                max_line_length,
+            ))
+            .emit(emitter);
+    }
+}
+
+/// Runs the parser, converting its panics to errors,
+/// so one pathological file can't stop the whole analysis.
+fn catch_ice<T>(parse: impl FnOnce() -> syntax::Result<T>) -> syntax::Result<T> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(parse)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+
+            Err(syntax::v2::Error {
+                what: format!("internal parser error: {}", message),
+                where_: syntax::v2::Location::start(),
+            })
+        }
+    }
+}
+
+/// Parses a module manifest.
+///
+/// Root and nested modules become imports of the manifest,
+/// so the manifest's scope consists of everything they export.
+pub fn parse_manifest(path: &Path, emitter: &mut Emitter) -> Result<PreprocessOutput, Error> {
+    let source = match read_source(path, emitter)? {
+        Some(source) => source,
+        None         => return Ok(PreprocessOutput::SyntaxErrors),
+    };
+    let source = source.trim_start_matches('\u{feff}');
+
+    let manifest = catch_ice(|| manifest::parse(source));
+
+    let source = Rc::from(source);
+
+    let manifest = match manifest {
+        Ok(manifest) => manifest,
+        Err(e)   => {
+            emit_syntax_error(e, path, &source, emitter);
+            return Ok(PreprocessOutput::SyntaxErrors);
+        }
+    };
+
+    let is_script = |entry: &manifest::Entry| {
+        matches!(FileKind::from_path(Path::new(&entry.value)), Some(FileKind::Script) | Some(FileKind::Module))
+    };
+
+    let modules: Vec<_> = manifest.root_module.into_iter().chain(manifest.nested_modules).collect();
+
+    // Binary modules and modules given by name may define any functions
+    let only_script_modules = !modules.is_empty() && modules.iter().all(is_script);
+
+    // Only script modules are analyzed, binary
+    // modules and modules given by name are skipped.
+    let imports = modules
+        .into_iter()
+        .filter(is_script)
+        .map(|entry| syntax::Import {
+            span: entry.span,
+            importee: syntax::Importee::Relative(entry.value.replace(r"\", "/").into()),
+        })
+        .collect();
+
+    let (resolved_imports, broken_imports) =
+        resolve_imports(&source, path, imports, &Modules::new(), &EnvRoots::new(), emitter)?;
+
+    // Aliases are represented as functions, so both lists are merged
+    let aliases = manifest.aliases_to_export;
+    let listed = manifest.functions_to_export.map(|functions| {
+        functions.into_iter().chain(aliases.into_iter().flatten()).collect::<Vec<_>>()
+    });
+
+    let exported_functions = listed.as_ref().map(|listed| {
+        listed.iter().map(|entry| UniCase::new(entry.value.clone())).collect()
+    });
+
+    let manifest_exports = match listed {
+        Some(listed) if only_script_modules => listed,
+        _ => Vec::new(),
+    };
+
+    Ok(PreprocessOutput::Valid(Box::new(Parsed {
+        imports: resolved_imports,
+        broken_imports,
+        kind: FileKind::Manifest,
+        exports: Exports::Functions(exported_functions),
+        manifest_exports,
         original_path: path.to_owned(),
         source,
-    }))
+        ..Parsed::default()
+    })))
 }
 
 /// Verifies imports and canonicalizes their paths
 ///
-/// Returns None if any of imports were not recognized
+/// Returns the resolved imports and the ones pointing to nonexisting files
 // TODO the `source` argument is weird here.
 // Perhaps the whole in_file_source was a bad idea.
-fn resolve_imports(source: &Rc<str>, source_path: &Path, imports: Vec<syntax::Import>, emitter: &mut Emitter)
-    -> Result<Option<Map<PathBuf, syntax::Import>>, Error>
+fn resolve_imports(
+    source: &Rc<str>,
+    source_path: &Path,
+    imports: Vec<syntax::Import>,
+    modules: &Modules,
+    env_roots: &EnvRoots,
+    emitter: &mut Emitter,
+) -> Result<(Map<PathBuf, syntax::Import>, Vec<syntax::Import>), Error>
 {
-    let mut import_error = false;
     let mut resolved_imports = Map::new();
+    let mut broken_imports = Vec::new();
 
     for import in imports {
         use syntax::Importee;
@@ -106,18 +535,35 @@ fn resolve_imports(source: &Rc<str>, source_path: &Path, imports: Vec<syntax::Im
         let filename = source_path.file_name().unwrap().to_str().unwrap();
 
         let dest_path = match import.importee {
-            Importee::Relative(ref relative_path) => dir.join(relative_path),
+            Importee::Relative(ref relative_path) | Importee::ModulePath(ref relative_path) => dir.join(relative_path),
             Importee::HereSut => dir.join(filename.replace(".Tests", "")),
-            Importee::Unrecognized(_) => {
-                // Should we treat unrecognized import as an error also?
-                // This will stop processing the file further and will result in
-                // less spammy output, because we'll probably get some
-                // "Not in scope" errors later on.
-                // import_error = true;
+            Importee::EnvRelative(ref variable, ref relative_path) => {
+                match env_roots.get(&UniCase::new(variable.clone())) {
+                    Some(env_root) => env_root.join(relative_path),
+                    None => {
+                        import.span.in_file_source(source_path, Rc::clone(source))
+                            .lint(Lint::UnrecognizedImports, "import relative to an unknown environment variable")
+                            .what(variable.as_str())
+                            .note(format!("Map `{}` to a directory in `[imports] env_roots` to resolve it", variable))
+                            .emit(emitter);
 
+                        continue;
+                    }
+                }
+            }
+            Importee::UsingModule(ref module) if is_module_path(module) => dir.join(module),
+            Importee::Module(ref name) | Importee::UsingModule(ref name) => {
+                // Modules not found in the analyzed tree are
+                // assumed to be installed in the system.
+                match modules.get(&UniCase::new(name.clone())) {
+                    Some(path) => path.clone(),
+                    None => continue,
+                }
+            }
+            Importee::Unrecognized(_) => {
                 import.span.in_file_source(source_path, Rc::clone(source))
                     .lint(Lint::UnrecognizedImports, "unrecognized import statement")
-                    .note("Note: Recognized imports are `$PSScriptRoot\\..` or `$here\\$sut`")
+                    .note("Recognized imports are `$PSScriptRoot\\..`, `(Join-Path $PSScriptRoot ..)` or `$here\\$sut`")
                     .emit(emitter);
 
                 continue;
@@ -127,20 +573,22 @@ fn resolve_imports(source: &Rc<str>, source_path: &Path, imports: Vec<syntax::Im
         if dest_path.exists() {
             resolved_imports.insert(dest_path.canonicalize()?, import);
         } else {
-            import_error = true;
-
             import.span.in_file_source(source_path, Rc::clone(source))
                 .lint(Lint::NonexistingImports, "invalid import")
                 .note(format!("File not found: {}", dest_path.display()))
                 .emit(emitter);
+
+            broken_imports.push(import);
         }
     }
 
-    if import_error {
-        return Ok(None)
-    }
+    Ok((resolved_imports, broken_imports))
+}
 
-    Ok(Some(resolved_imports))
+/// Whether a module of `using module` is given by a path rather than by name
+fn is_module_path(module: &str) -> bool {
+    let module = module.to_lowercase();
+    module.contains('/') || [".psm1", ".psd1", ".dll"].iter().any(|extension| module.ends_with(extension))
 }
 
 impl Parsed {
@@ -156,3 +604,20 @@ impl Parsed {
     }
 }
 
+#[test]
+fn test_catch_ice() {
+    let result: syntax::Result<()> = catch_ice(|| panic!("oops"));
+    assert_eq!(result.unwrap_err().what, "internal parser error: oops");
+}
+
+#[test]
+fn test_matches_wildcard() {
+    assert!(matches_wildcard("Get-Foo", "get-foo"));
+    assert!(matches_wildcard("*", "Get-Foo"));
+    assert!(matches_wildcard("Get-*", "Get-Foo"));
+    assert!(matches_wildcard("*-F?o", "Get-Foo"));
+    assert!(matches_wildcard("*o*o", "Get-Foo"));
+    assert!(!matches_wildcard("Get-*", "Set-Foo"));
+    assert!(!matches_wildcard("Get-Fo", "Get-Foo"));
+    assert!(!matches_wildcard("Get-Foo?", "Get-Foo"));
+}
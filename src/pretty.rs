@@ -0,0 +1,258 @@
+//! Human-readable output of emitted messages, enabled by the `pretty` feature
+
+use failure::Error;
+use yansi::{Color, Style};
+
+use std::io::{self, Write};
+
+use formats::Report;
+use {EmittedItem, Emitter, Location, MessageKind, Span};
+
+/// Emitter printing messages with code snippets and colors, in the style of rustc
+pub struct PrettyEmitter {
+    out: Box<dyn Write>,
+
+    /// The first error of writing the output
+    error: Option<io::Error>,
+}
+
+impl PrettyEmitter {
+    pub fn new(out: Box<dyn Write>) -> PrettyEmitter {
+        PrettyEmitter { out, error: None }
+    }
+
+    pub fn stdout() -> PrettyEmitter {
+        PrettyEmitter::new(Box::new(io::stdout()))
+    }
+
+    fn write_item(&mut self, item: EmittedItem) -> io::Result<()> {
+        // Style of error message inspired by Rust
+        let out = &mut self.out;
+
+        let accent_style = match item.kind {
+            MessageKind::Error   => Color::Red.style().bold(),
+            MessageKind::Warning => Color::Yellow.style().bold(),
+        };
+
+        writeln!(
+            out,
+            "{}: {}",
+            accent_style.paint(item.heading()),
+            Style::new().bold().paint(&item.message)
+        )?;
+
+        let offset = print_location(&mut **out, &item.location, accent_style)?;
+
+        let blue = Color::Blue.style().bold();
+
+        for note in &item.notes {
+            for line in note.message.lines() {
+                write!(out, "{}", offset)?;
+                writeln!(out, " {} {}", blue.paint("="), line)?;
+            }
+
+            if let Some(location) = &note.location {
+                print_location(&mut **out, location, blue)?;
+            }
+        }
+
+        if let Some(fix) = item.fix {
+            write!(out, "{}", offset)?;
+            writeln!(out, " {} fix: {}", blue.paint("="), fix.description)?;
+        }
+
+        writeln!(out)
+    }
+}
+
+impl Emitter for PrettyEmitter {
+    fn emit(&mut self, item: EmittedItem) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.write_item(item) {
+            self.error = Some(e);
+        }
+    }
+}
+
+impl Report for PrettyEmitter {
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        match self.error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(self.out.flush()?),
+        }
+    }
+}
+
+/// Prints a path to the location and the underlined
+/// lines of code, if the location has a span.
+///
+/// Returns the whitespace to print before a continuation line.
+fn print_location(out: &mut dyn Write, location: &Location, accent_style: Style) -> io::Result<String> {
+    let lines = location.span
+        .map(|span| underlined_lines(&location.source, span))
+        .unwrap_or_default();
+
+    let line_no_width = lines
+        .last()
+        .map_or(1, |line| line.line_no.to_string().len());
+
+    let offset = " ".repeat(line_no_width);
+
+    let blue = Color::Blue.style().bold();
+    let pipe = blue.paint("|");
+
+    writeln!(
+        out,
+        "{}{} {}{}",
+        offset,
+        blue.paint("-->"),
+        location.file.display(),
+        location.span.as_ref().map(
+            |span| format!(":{}:{}", span.start.line, span.start.col)
+        ).unwrap_or_default()
+    )?;
+
+    if lines.is_empty() {
+        return Ok(offset);
+    }
+
+    writeln!(out, "{} {}", offset, pipe)?;
+
+    // For long spans, print only a few lines from the beginning and the end
+    const MAX_LINES: usize = 6;
+    let elide = lines.len() > MAX_LINES;
+
+    for (i, line) in lines.iter().enumerate() {
+        if elide && i >= MAX_LINES / 2 && i < lines.len() - MAX_LINES / 2 {
+            if i == MAX_LINES / 2 {
+                writeln!(out, "{}", blue.paint("..."))?;
+            }
+            continue;
+        }
+
+        let line_no = format!("{:>width$}", line.line_no, width = line_no_width);
+        writeln!(out, "{} {} {}", blue.paint(&line_no), pipe, line.text)?;
+
+        // Now, let's print squiggles
+        write!(out, "{} {} ", offset, pipe)?;
+
+        // Print space before squiggles.
+        // We're printing it char-by-char to handle tabs the same way as original line.
+        for c in line.text.chars().take(line.underline_start) {
+            if c.is_whitespace() {
+                write!(out, "{}", c)?;
+            } else {
+                write!(out, " ")?;
+            }
+        }
+
+        writeln!(out, "{}", accent_style.paint("^".repeat(line.underline_width)))?;
+    }
+
+    Ok(offset)
+}
+
+/// A line of code with a part of the span underlined
+struct UnderlinedLine<'a> {
+    line_no: u32,
+    text: &'a str,
+
+    /// In chars
+    underline_start: usize,
+
+    /// In chars, at least one
+    underline_width: usize,
+}
+
+/// Splits the span into lines.
+fn underlined_lines(source: &str, span: Span) -> Vec<UnderlinedLine<'_>> {
+    let start = span.start.byte as usize;
+    let end = ::std::cmp::max(start, span.end.byte as usize);
+
+    let first_line = span.start.find_line(source);
+    let first_line_start = first_line.as_ptr() as usize - source.as_ptr() as usize;
+
+    let mut lines = Vec::new();
+    let mut line_start = first_line_start;
+
+    for (text, line_no) in source[first_line_start..].lines().zip(span.start.line..) {
+        let text = text.trim_end_matches('\r');
+        let line_end = line_start + text.len();
+
+        let underline_from = ::std::cmp::max(start, line_start);
+        let underline_to = ::std::cmp::min(end, line_end);
+
+        // Lines after the first one are included only if
+        // they contain the span (not only its trailing newline)
+        if !lines.is_empty() && underline_from >= underline_to {
+            break;
+        }
+
+        let before = source.get(line_start..underline_from).unwrap_or("");
+        let underlined = source.get(underline_from..underline_to).unwrap_or("");
+
+        lines.push(UnderlinedLine {
+            line_no,
+            text,
+            underline_start: before.chars().count(),
+            underline_width: ::std::cmp::max(1, underlined.chars().count()),
+        });
+
+        // Skip also the newline (\n or \r\n). `lines` already stripped it.
+        line_start = source.get(line_start..)
+            .and_then(|rest| rest.find('\n'))
+            .map_or(source.len(), |newline| line_start + newline + 1);
+
+        if line_start >= end {
+            break;
+        }
+    }
+
+    lines
+}
+
+#[test]
+fn test_underlined_lines() {
+    let source = "$x = @'\r\n  foo\r\nbar'@\r\nWrite-Host $x\n";
+    let span = Span::from_fragment(1, &source[5..7], source)
+        .to(Span::from_fragment(3, &source[16..21], source));
+
+    let lines = underlined_lines(source, span);
+    let summary: Vec<_> = lines
+        .iter()
+        .map(|line| (line.line_no, line.text, line.underline_start, line.underline_width))
+        .collect();
+
+    assert_eq!(summary, [
+        (1, "$x = @'", 5, 2),
+        (2, "  foo", 0, 5),
+        (3, "bar'@", 0, 5),
+    ]);
+}
+
+#[test]
+fn test_note_locations() {
+    use std::fs;
+
+    let dir = ::testing::setup_dir(&[
+        ("A.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/B.ps1\nGet-C\n"),
+        ("B.ps1", ". $PSScriptRoot/C.ps1\nfunction Get-B { Get-C }\n"),
+        ("C.ps1", "\nfunction Get-C { }\n"),
+    ]).unwrap();
+
+    let output_path = dir.path().join("output.txt");
+    let mut emitter = PrettyEmitter::new(Box::new(fs::File::create(&output_path).unwrap()));
+    let run_opt = ::RunOpt { only: vec![::lint::Lint::IndirectImports], ..Default::default() };
+    ::run(dir.path(), run_opt, &mut emitter).unwrap();
+    Box::new(emitter).finish().unwrap();
+
+    let output = fs::read_to_string(&output_path).unwrap();
+    let locations: Vec<_> = output.lines()
+        .filter(|line| line.contains("-->"))
+        .filter_map(|line| line.split_whitespace().last())
+        .map(|location| location.rsplit(['/', '\\']).next().unwrap())
+        .collect();
+    assert_eq!(locations, ["A.ps1:3:1", "A.ps1:2:3", "C.ps1:2:10"]);
+}
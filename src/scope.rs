@@ -6,31 +6,176 @@ use std::collections::BTreeMap as Map;
 use std::collections::BTreeSet as Set;
 use std::path::{Path, PathBuf};
 
+use builtins;
+use builtins::PsVersion;
 use lint::Emitter;
 use lint::Lint;
-use preprocess::Parsed;
+use preprocess::{Exports, FileKind, Parsed};
 use syntax;
-use syntax::Item;
-use ConfigFile;
+use syntax::{Importee, Item, Span};
+use {ConfigFile, Edit, Fix};
 
-struct Config<'a> {
-    custom_cmdlets: Set<Item<UniCase<&'a str>>>,
+#[derive(Default)]
+pub struct Config<'a> {
+    /// Commandlets, classes and variables assumed to exist
+    custom_items: Set<Item<UniCase<&'a str>>>,
+
+    /// Extras of subdirectories with their own `shelly.toml`,
+    /// by canonical paths of the directories
+    nested_items: Vec<(PathBuf, Set<Item<UniCase<&'a str>>>)>,
+
+    /// Canonical paths of files implicitly imported into every file
+    preludes: Vec<PathBuf>,
+
+    /// Version of PowerShell the scripts are run with, if known
+    target: Option<PsVersion>,
+
+    /// Aliases not reported by `avoid-aliases`
+    allowed_aliases: Set<UniCase<String>>,
+
+    /// Whether invoked executables have to be listed in `extras.executables`
+    check_executables: bool,
+}
+
+/// Options of `avoid-aliases`, in `[lint-options.avoid-aliases]`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AliasesOptions {
+    /// Aliases which are fine to use, eg. `%` and `?`
+    pub(crate) allowed: Option<Vec<String>>,
 }
 
 impl<'a> Config<'a> {
-    fn from_config_file(config_file: &ConfigFile) -> Config {
-        let custom_cmdlets = config_file.extras.as_ref()
-            .and_then(|extras| extras.cmdlets.as_ref())
-            .map(|cmdlets|
-                cmdlets
-                    .iter()
-                    .map(|cmdlet| Item::function(UniCase::new(cmdlet.as_str())))
-                    .collect()
-            )
-            .unwrap_or_else(Set::new);
-
-        Config { custom_cmdlets }
+    /// Creates a config from the config file and commands from `builtins.lock` and builtins files.
+    pub fn from_config_file(config_file: &'a ConfigFile, commands: &'a [String], root: &Path)
+        -> Result<Config<'a>, Error>
+    {
+        let mut custom_items = extra_items(config_file)?;
+        custom_items.extend(commands.iter().map(|command| Item::function(UniCase::new(command.as_str()))));
+
+        let mut preludes = Vec::new();
+        for prelude in config_file.extras.iter().flat_map(|extras| &extras.preludes).flatten() {
+            let path = root.join(prelude);
+            let path = path.canonicalize()
+                .map_err(|_| format_err!("Prelude file not found: {}", path.display()))?;
+            preludes.push(path);
+        }
+
+        let options: AliasesOptions = config_file.lint_options(Lint::AvoidAliases)?.unwrap_or_default();
+        let allowed_aliases = options.allowed.into_iter().flatten().map(UniCase::new).collect();
+
+        let check_executables = config_file.extras.as_ref().is_some_and(|extras| extras.executables.is_some());
+
+        Ok(Config {
+            custom_items,
+            nested_items: Vec::new(),
+            preludes,
+            target: None,
+            allowed_aliases,
+            check_executables,
+        })
+    }
+
+    /// Adds items from `[extras]` of a nested config (and commands from its builtins files),
+    /// assumed to exist only in files under its directory
+    pub fn with_nested(mut self, dir: PathBuf, config_file: &'a ConfigFile, commands: &'a [String])
+        -> Result<Config<'a>, Error>
+    {
+        let mut items = extra_items(config_file)?;
+        items.extend(commands.iter().map(|command| Item::function(UniCase::new(command.as_str()))));
+        self.nested_items.push((dir, items));
+        Ok(self)
+    }
+
+    /// Sets the targeted PowerShell version. Commandlets added in it are assumed to exist
+    /// and shipped ones missing in it are not.
+    pub fn with_target(mut self, target: Option<PsVersion>) -> Config<'a> {
+        if let Some(target) = target {
+            self.custom_items.extend(builtins::added_in(target).map(|name| Item::function(UniCase::new(name))));
+        }
+        self.target = target;
+        self
+    }
+
+    /// Checks whether the item is a shipped builtin available in the targeted version
+    /// (or a built-in alias)
+    pub fn is_shipped(&self, item: &Item<UniCase<&str>>) -> bool {
+        if item.is_function() && builtins::alias_target(&item.name).is_some() {
+            return true;
+        }
+        builtins::SHIPPED.contains(item) && self.removed(item).is_none()
+    }
+
+    /// If a shipped commandlet is missing in the targeted version, returns its replacement, if any
+    fn removed(&self, item: &Item<UniCase<&str>>) -> Option<Option<&'static str>> {
+        match self.target {
+            Some(target) if item.is_function() => builtins::removed_in(target, &item.name),
+            _ => None,
+        }
     }
+
+    /// Checks whether the item is assumed to exist in a given file
+    fn is_custom_item(&self, file: &Path, item: &Item<UniCase<&str>>) -> bool {
+        self.custom_items.contains(item) || self.nested_items.iter()
+            .any(|(dir, items)| file.starts_with(dir) && items.contains(item))
+    }
+
+    /// Commandlets assumed to exist in addition to shipped builtins
+    pub fn custom_cmdlets(&self) -> impl Iterator<Item=&'a str> + '_ {
+        self.custom_items.iter().filter(|item| item.is_function()).map(|item| *item.name)
+    }
+}
+
+/// Items from `[extras]`: `cmdlets`, commands of `known_modules`, `classes` and `variables`
+fn extra_items(config_file: &ConfigFile) -> Result<Set<Item<UniCase<&str>>>, Error> {
+    let mut items = Set::new();
+    let extras = match &config_file.extras {
+        Some(extras) => extras,
+        None => return Ok(items),
+    };
+
+    for cmdlet in extras.cmdlets.iter().flatten() {
+        items.insert(Item::function(UniCase::new(cmdlet.as_str())));
+    }
+
+    for module in extras.known_modules.iter().flatten() {
+        let commands = builtins::known_module_commands(module)?;
+        items.extend(commands.map(|command| Item::function(UniCase::new(command))));
+    }
+
+    for class in extras.classes.iter().flatten() {
+        items.insert(Item::class(UniCase::new(class.as_str())));
+    }
+
+    // Executables can be invoked by their names, as functions
+    for executable in extras.executables.iter().flatten() {
+        items.insert(Item::function(UniCase::new(executable_stem(executable))));
+    }
+
+    for variable in extras.variables.iter().flatten() {
+        let name = variable.trim_start_matches('$');
+        items.insert(Item::variable(UniCase::new(name)));
+    }
+
+    Ok(items)
+}
+
+/// Name of an executable without the `.exe` extension
+fn executable_stem(name: &str) -> &str {
+    match name.len().checked_sub(4) {
+        Some(stem) if name.get(stem..).is_some_and(|extension| extension.eq_ignore_ascii_case(".exe")) => &name[..stem],
+        _ => name,
+    }
+}
+
+/// Commands of modules from `#Requires -Modules` which have bundled command lists.
+/// Other modules are ignored, they may be installed with their own commands.
+fn required_module_items(parsed: &Parsed) -> Set<Item<UniCase<&'static str>>> {
+    parsed.required_modules.iter()
+        .filter_map(|module| builtins::known_module_commands(module).ok())
+        .flatten()
+        .map(|command| Item::function(UniCase::new(command)))
+        .collect()
 }
 
 /// Functions in scope
@@ -39,9 +184,20 @@ pub struct Scope<'a> {
     /// All the functions in scope
     items: Map<Item<UniCase<&'a str>>, DefinedItem<'a>>,
 
-    /// Files directly imported by `.`
+    /// Other definitions of items imported from multiple files,
+    /// not chosen by `items`
+    ambiguous: Map<Item<UniCase<&'a str>>, Vec<DefinedItem<'a>>>,
+
+    /// Imported functions shadowed by definitions in current file
+    shadowed: Vec<(&'a syntax::Definition, DefinedItem<'a>)>,
+
+    /// Files directly imported by `.` (and preludes),
+    /// including origins of functions exported by imported modules
     direct_imports: Set<&'a Path>,
 
+    /// Imports skipped, because they would create a cycle
+    recursive_imports: Vec<&'a Path>,
+
     /// Current file
     current_file: &'a Path,
 }
@@ -80,6 +236,101 @@ impl<'a> Scope<'a> {
             None => None
         }
     }
+
+    /// Definitions of an item other than the one returned by `search`,
+    /// if it was imported from multiple files
+    pub fn alternatives(&self, item: &Item<&str>) -> Vec<DefinedItem<'a>> {
+        self.ambiguous
+            .get(&item.as_case_insensitive())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl<'a> Scope<'a> {
+    /// Brings items exported by an imported file into scope.
+    /// With `using module`, classes defined in the module are brought too.
+    ///
+    /// Returns false if the import was skipped because it's recursive.
+    fn import(
+        &mut self,
+        import: &'a Path,
+        with_classes: bool,
+        files: &'a Map<PathBuf, Parsed>,
+        config: &Config,
+        scopes: &mut Map<&'a Path, ScopeWip<'a>>,
+    ) -> Result<bool, Error> {
+        let nested = match get_scope(import, files, config, scopes)? {
+            Some(nested) => nested,
+            None => return Ok(false),
+        };
+        let exports = &files[import].exports;
+
+        self.direct_imports.insert(import);
+
+        for (item, defined) in &nested.items {
+            let is_module_class = with_classes && item.is_class() && defined.origin == import;
+            if !exports.exports(item) && !is_module_class {
+                continue;
+            }
+
+            // Functions exported from a module are
+            // directly available to the importer.
+            if let Exports::Functions(_) = exports {
+                self.direct_imports.insert(defined.origin);
+            }
+
+            self.insert_imported(*item, *defined);
+
+            for alternative in nested.ambiguous.get(item).into_iter().flatten() {
+                self.insert_alternative(*item, *alternative);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Brings definitions of a prelude into scope of a file the prelude
+    /// itself imports (possibly indirectly). The prelude's scope is still
+    /// being computed then, so only its own definitions are available.
+    fn seed_prelude(&mut self, prelude: &'a Path, files: &'a Map<PathBuf, Parsed>) {
+        let parsed = &files[prelude];
+
+        self.direct_imports.insert(prelude);
+
+        for definition in parsed.definitions.iter().filter(|definition| definition.visible_in.is_none()) {
+            let item = definition.item.as_case_insensitive();
+            if parsed.exports.exports(&item) {
+                self.insert_imported(item, DefinedItem { definition, origin: prelude });
+            }
+        }
+    }
+
+    /// Inserts an imported item, remembering the
+    /// previous definition if it came from another file
+    fn insert_imported(&mut self, item: Item<UniCase<&'a str>>, defined: DefinedItem<'a>) {
+        if let Some(previous) = self.items.insert(item, defined) {
+            let alternatives = self.ambiguous.entry(item).or_default();
+            if !alternatives.iter().any(|alternative| alternative.origin == previous.origin) {
+                alternatives.push(previous);
+            }
+            alternatives.retain(|alternative| alternative.origin != defined.origin);
+            if alternatives.is_empty() {
+                self.ambiguous.remove(&item);
+            }
+        }
+    }
+
+    /// Remembers a definition of an item which is not the chosen one
+    fn insert_alternative(&mut self, item: Item<UniCase<&'a str>>, alternative: DefinedItem<'a>) {
+        if self.items[&item].origin == alternative.origin {
+            return;
+        }
+        let alternatives = self.ambiguous.entry(item).or_default();
+        if !alternatives.iter().any(|other| other.origin == alternative.origin) {
+            alternatives.push(alternative);
+        }
+    }
 }
 
 /// State of scope computation
@@ -93,36 +344,165 @@ enum ScopeWip<'a> {
     Current,
 }
 
-pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &ConfigFile, emitter: &mut Emitter)
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[
+    Lint::UnknownFunctions,
+    Lint::IndirectImports,
+    Lint::AmbiguousUsages,
+    Lint::ShadowedImports,
+    Lint::UnusedPrivateFunctions,
+    Lint::NonexistingExports,
+    Lint::InvalidLetterCasing,
+    Lint::UnusedImports,
+    Lint::RecursiveImports,
+    Lint::IncompatibleVersion,
+    Lint::AvoidAliases,
+    Lint::UnknownExecutables,
+];
+
+pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter)
     -> Result<Map<&'a Path, Scope<'a>>, Error>
 {
-    lazy_static! {
-        static ref BUILTINS: Set<Item<UniCase<&'static str>>> =
-            include_str!("builtins.txt")
-            .split_whitespace()
-            .chain(include_str!("extras.txt").split_whitespace())
-            .map(UniCase::new)
-            .map(Item::function)
-            .collect();
-    }
+    let mut scopes = Map::new();
 
-    let config = Config::from_config_file(config);
+    let cycles = import_cycles(files, config);
+    let cycle_of: Map<&Path, usize> = cycles.iter()
+        .enumerate()
+        .flat_map(|(index, cycle)| cycle.iter().map(move |&file| (file, index)))
+        .collect();
+    let mut reported_cycles = Set::new();
 
-    let mut scopes = Map::new();
+    // Preludes are computed first, so that the files they
+    // import see them as being computed, not the other way round
+    for prelude in &config.preludes {
+        if let Some((prelude, _)) = files.get_key_value(prelude) {
+            get_scope(prelude, files, config, &mut scopes)?;
+        }
+    }
 
     for (path, parsed) in files {
-        let scope = get_scope(path, files, &mut scopes)?;
+        let scope = get_scope(path, files, config, &mut scopes)?
+            .expect("only imported scopes can be recursive");
 
         let mut already_analyzed = Set::new();
         let mut used_dependencies: Set<&Path> = Set::new();
+        let required_items = required_module_items(parsed);
+        let broken_imports = broken_imports_in_scope(path, files);
+
+        // Mocked functions are used by the mocks, as Pester checks that they exist
+        for mock in &parsed.mocks {
+            if let Some((_, defined)) = scope.search(&Item::function(&*mock.name)) {
+                used_dependencies.insert(defined.origin);
+            }
+        }
+
+        if let (Some(target), Some(required)) = (config.target, &parsed.required_version) {
+            // Invalid versions are reported by PowerShell itself
+            if let Ok(version) = required.version.parse::<PsVersion>() {
+                if version > target {
+                    required.span.in_file(parsed)
+                        .lint(Lint::IncompatibleVersion, format!("requires PowerShell {}", version))
+                        .note(format!("The target version is {}", target))
+                        .emit(emitter);
+                }
+            }
+        }
+
+        for entry in &parsed.manifest_exports {
+            let is_pattern = entry.value.contains(&['*', '?'] as &[char]);
+            if !is_pattern && scope.search(&Item::function(entry.value.as_str())).is_none() {
+                entry.span.in_file(parsed)
+                    .lint(Lint::NonexistingExports, "exported function not defined in the module")
+                    .what(entry.value.as_str())
+                    .emit(emitter);
+            }
+        }
+
+        if let (FileKind::Module, Exports::Functions(Some(_))) = (parsed.kind, &parsed.exports) {
+            report_unused_private_functions(parsed, emitter);
+        }
+
+        // Each cycle is reported once, at the first import breaking it
+        for &import in &scope.recursive_imports {
+            let cycle = cycle_of[&**path];
+            if !reported_cycles.insert(cycle) {
+                continue;
+            }
+
+            let summary = match &*cycles[cycle] {
+                [_] => "The file imports itself".to_owned(),
+                cycle => format!(
+                    "{} files form the cycle: {}",
+                    cycle.len(),
+                    cycle.iter()
+                        .map(|file| files[*file].original_path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            };
+            let mut message = parsed.imports[import].span.in_file(parsed)
+                .lint(Lint::RecursiveImports, "recursive import")
+                .note(summary);
+            for (importer, imported) in cycle_imports(import, &cycles[cycle], files) {
+                if (importer, imported) == (&**path, import) {
+                    continue;
+                }
+                let is_recursive = get_cached_scope(importer, &scopes).recursive_imports.contains(&imported);
+                let mut note = format!("...which imports {}", files[imported].original_path.display());
+                if is_recursive {
+                    note = format!("{} (also ignored)", note);
+                }
+                message = message.note_at(files[importer].imports[imported].span.in_file(&files[importer]), note);
+            }
+            message.emit(emitter);
+        }
+
+        for (definition, imported) in &scope.shadowed {
+            definition.span.in_file(parsed)
+                .lint(Lint::ShadowedImports, "function shadows an imported one")
+                .what(definition.item.name.as_str())
+                .note_at(
+                    imported.definition.span.in_file(&files[imported.origin]),
+                    format!("Imported from {}", files[imported.origin].original_path.display()),
+                )
+                .emit(emitter);
+        }
 
         for usage in &parsed.usages {
             let usage_unicase = usage.item.as_case_insensitive();
+            let search_result = scope.search(&usage.item.as_ref());
 
-            if BUILTINS.contains(&usage_unicase) {
+            // Reported on every usage, unless the name is defined in scope
+            if let Some(command) = builtins::alias_target(usage.name()) {
+                if usage.item.is_function()
+                    && !config.allowed_aliases.contains(&UniCase::new(usage.name().to_owned()))
+                    && search_result.is_none()
+                {
+                    usage.span.in_file(parsed)
+                        .lint(Lint::AvoidAliases, "alias used instead of a commandlet")
+                        .what(usage.name())
+                        .note(format!("`{}` is an alias of `{}`", usage.name(), command))
+                        .fix(Fix {
+                            description: format!("Replace with `{}`", command),
+                            edits: vec![Edit { span: usage.span, replacement: command.to_owned() }],
+                        })
+                        .emit(emitter);
+                }
+            }
+
+            // Functions defined in scope take precedence
+            // over builtins and aliases with the same names
+            if search_result.is_none() && config.is_shipped(&usage_unicase) {
+                continue;
+            }
+            if config.is_custom_item(path, &usage_unicase) || required_items.contains(&usage_unicase) {
+                continue;
+            }
+            if defined_in_enclosing_block(usage, parsed) {
                 continue;
             }
-            if config.custom_cmdlets.contains(&usage_unicase) {
+            // Mocks of functions not in scope replace them for the rest of their block
+            if mocked_before(usage, parsed) && search_result.is_none() {
                 continue;
             }
             if already_analyzed.contains(&usage_unicase) {
@@ -131,14 +511,34 @@ pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &ConfigFile, emitter
 
             already_analyzed.insert(usage_unicase);
 
-            let search_result = scope.search(&usage.item.as_ref());
             match search_result {
-                None => {
-                    // Don't produce errors for unkown classes yet,
-                    // because their usage us a big heuristic.
-                    if usage.item.is_function() {
-                        usage.span.in_file(&parsed)
-                            .lint(Lint::UnknownFunctions, "function not in scope")
+                // Don't produce errors for unkown classes yet,
+                // because their usage us a big heuristic.
+                None if usage.item.is_function() => {
+                    let mut message = usage.span.in_file(parsed)
+                        .lint(Lint::UnknownFunctions, "function not in scope")
+                        .what(usage.name());
+                    if let (Some(target), Some(replacement)) = (config.target, config.removed(&usage_unicase)) {
+                        let mut note = format!("`{}` is not available in PowerShell {}", usage.name(), target);
+                        if let Some(replacement) = replacement {
+                            note += &format!(", use `{}` instead", replacement);
+                        }
+                        message = message.note(note);
+                    }
+                    // Missing definitions are likely the result of a broken import
+                    for &(importer, import) in &broken_imports {
+                        message = message.note_at(
+                            import.span.in_file(importer),
+                            "This import was not found, it may be the root cause",
+                        );
+                    }
+                    message.emit(emitter);
+                }
+                None if usage.item.is_executable() && config.check_executables => {
+                    let stem = executable_stem(usage.name());
+                    if !config.is_custom_item(path, &Item::function(UniCase::new(stem))) {
+                        usage.span.in_file(parsed)
+                            .lint(Lint::UnknownExecutables, "executable not in `extras.executables`")
                             .what(usage.name())
                             .emit(emitter);
                     }
@@ -163,17 +563,23 @@ pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &ConfigFile, emitter
                     if through_import_bags.is_empty() {
                         used_dependencies.insert(imported_through[0]);
 
-                        usage.span.in_file(&parsed)
+                        usage.span.in_file(parsed)
                             .lint(Lint::IndirectImports, "indirectly imported")
                             .what(usage.name())
-                            .note(format!(
-                                "Indirectly imported through {}",
-                                files[imported_through[0]].original_path.display()
-                            ))
-                            .note(format!(
-                                "Consider directly importing {}",
-                                files[item.origin].original_path.display()
-                            ))
+                            .note_at(
+                                parsed.imports[imported_through[0]].span.in_file(parsed),
+                                format!(
+                                    "Indirectly imported through {}",
+                                    files[imported_through[0]].original_path.display()
+                                ),
+                            )
+                            .note_at(
+                                item.definition.span.in_file(&files[item.origin]),
+                                format!(
+                                    "Consider directly importing {}",
+                                    files[item.origin].original_path.display()
+                                ),
+                            )
                             .emit(emitter);
                     } else {
                         used_dependencies.insert(through_import_bags[0]);
@@ -185,11 +591,41 @@ pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &ConfigFile, emitter
             if let Some((_, defined)) = search_result {
                 used_dependencies.insert(defined.origin);
 
+                let alternatives = scope.alternatives(&usage.item.as_ref());
+                if !alternatives.is_empty() {
+                    let message = usage.span.in_file(parsed)
+                        .lint(Lint::AmbiguousUsages, "defined in multiple imported files")
+                        .what(usage.name())
+                        .note_at(
+                            defined.definition.span.in_file(&files[defined.origin]),
+                            format!("Resolved to this definition from {}", files[defined.origin].original_path.display()),
+                        );
+
+                    alternatives
+                        .iter()
+                        .fold(message, |message, alternative| {
+                            message.note_at(
+                                alternative.definition.span.in_file(&files[alternative.origin]),
+                                format!("Also defined in {}", files[alternative.origin].original_path.display()),
+                            )
+                        })
+                        .emit(emitter);
+                }
+
                 if usage.item != defined.definition.item {
-                    usage.span.in_file(&parsed)
+                    let mut message = usage.span.in_file(parsed)
                         .lint(Lint::InvalidLetterCasing, "function name differs between usage and definition")
                         .note("Check whether the letter casing is the same")
-                        .emit(emitter);
+                        .note_at(
+                            defined.definition.span.in_file(&files[defined.origin]),
+                            format!("Defined here as `{}`", defined.definition.item.name),
+                        );
+
+                    if let Some(fix) = rename_fix(usage.span, &defined.definition.item.name, &parsed.source) {
+                        message = message.fix(fix);
+                    }
+
+                    message.emit(emitter);
                 }
             }
         }
@@ -199,7 +635,9 @@ pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &ConfigFile, emitter
         // scope analysis to save some info.
         if !parsed.is_import_bag() {
             for (imported_file, import) in &parsed.imports {
-                if !used_dependencies.contains(&**imported_file) {
+                // Recursive imports are already reported
+                let is_recursive = scope.recursive_imports.contains(&&**imported_file);
+                if !used_dependencies.contains(&**imported_file) && !is_recursive {
                     if files[imported_file].functions_and_classes().next().is_none() {
                         // Temporarily silence unused-imports for weird "empty" files
                         // with no functions and no class definitions to avoid false positives.
@@ -208,9 +646,14 @@ pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &ConfigFile, emitter
                         continue;
                     }
 
-                    import.span.in_file(&parsed)
-                        .lint(Lint::UnusedImports, "unused import")
-                        .emit(emitter);
+                    let mut message = import.span.in_file(parsed)
+                        .lint(Lint::UnusedImports, "unused import");
+
+                    if let Some(fix) = remove_import_fix(import.span, &parsed.source) {
+                        message = message.fix(fix);
+                    }
+
+                    message.emit(emitter);
                 }
             }
         }
@@ -230,6 +673,94 @@ pub fn analyze<'a>(files: &'a Map<PathBuf, Parsed>, config: &ConfigFile, emitter
     Ok(scopes)
 }
 
+/// Reports functions of a module with `Export-ModuleMember`
+/// which are neither exported nor used in the module itself
+fn report_unused_private_functions(parsed: &Parsed, emitter: &mut Emitter) {
+    let used: Set<_> = parsed.usages.iter().map(|usage| usage.item.as_case_insensitive()).collect();
+
+    for definition in &parsed.definitions {
+        let item = definition.item.as_case_insensitive();
+        if !item.is_function() || definition.visible_in.is_some() {
+            continue;
+        }
+        if parsed.exports.exports(&item) || used.contains(&item) {
+            continue;
+        }
+
+        definition.span.in_file(parsed)
+            .lint(Lint::UnusedPrivateFunctions, "function neither exported nor used")
+            .what(definition.item.name.as_str())
+            .note("Only functions listed in `Export-ModuleMember` are visible to importers")
+            .emit(emitter);
+    }
+}
+
+/// Fix replacing a usage with the name as defined,
+/// if the span covers exactly the differently cased name
+fn rename_fix(span: Span, name: &str, source: &str) -> Option<Fix> {
+    let used = source.get(span.start.byte as usize..span.end.byte as usize)?;
+    if UniCase::new(used) != UniCase::new(name) {
+        return None;
+    }
+
+    Some(Fix {
+        description: format!("Rename to `{}`", name),
+        edits: vec![Edit { span, replacement: name.to_owned() }],
+    })
+}
+
+/// Fix removing the line of an import, if there's nothing else on it
+/// (except of a comment)
+fn remove_import_fix(span: Span, source: &str) -> Option<Fix> {
+    let line_start = source.get(..span.start.byte as usize)?.rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = source[line_start..].find(&['\r', '\n'][..]).map_or(source.len(), |end| line_start + end);
+
+    // The span may not cover the quotes
+    let quotes = &['"', '\''][..];
+    let before = source.get(line_start..span.start.byte as usize)?.trim().trim_end_matches(quotes).trim();
+    let after = source.get(span.end.byte as usize..line_end)?.trim().trim_start_matches(quotes).trim();
+
+    let only_import = before == "."
+        || before.to_lowercase().starts_with("import-module") && !before.contains(';');
+    if !only_import || !(after.is_empty() || after.starts_with('#')) {
+        return None;
+    }
+
+    // Including the newline
+    let next_line_start = source[line_end..].find('\n').map_or(source.len(), |newline| line_end + newline + 1);
+
+    let span = Span {
+        start: syntax::v2::Location { byte: line_start as u32, line: span.start.line, col: 1 },
+        end: syntax::v2::Location { byte: next_line_start as u32, line: span.start.line + 1, col: 1 },
+    };
+
+    Some(Fix {
+        description: "Remove the import".to_owned(),
+        edits: vec![Edit { span, replacement: String::new() }],
+    })
+}
+
+/// Checks whether a function is mocked with Pester's `Mock` before
+/// the usage, in a block that contains the usage
+fn mocked_before(usage: &syntax::Usage, parsed: &Parsed) -> bool {
+    usage.item.is_function() && parsed.mocks.iter().any(|mock| {
+        mock.span.start.byte < usage.span.start.byte
+            && mock.visible_in.is_none_or(|block| block.contains(usage.span))
+            && mock.name.eq_ignore_ascii_case(usage.name())
+    })
+}
+
+/// Checks whether a usage refers to a definition
+/// nested in a block that contains the usage
+fn defined_in_enclosing_block(usage: &syntax::Usage, parsed: &Parsed) -> bool {
+    let usage_unicase = usage.item.as_case_insensitive();
+
+    parsed.definitions.iter().any(|definition| {
+        definition.visible_in.is_some_and(|block| block.contains(usage.span))
+            && definition.item.as_case_insensitive() == usage_unicase
+    })
+}
+
 /// Gets a scope for a file, panics if not computed yet
 fn get_cached_scope<'a>(
     file: &Path,
@@ -242,16 +773,20 @@ fn get_cached_scope<'a>(
 }
 
 /// Computes or retrieves a Scope for a file,
-/// errors on recursive or out-of-tree imports.
+/// errors on out-of-tree imports.
 /// Caches the computed Scope in the `scopes` cache.
+///
+/// Returns `None` for a file whose scope is currently being
+/// computed, ie. when the file is imported recursively.
 fn get_scope<'a>(
     file: &'a Path,
     files: &'a Map<PathBuf, Parsed>,
+    config: &Config,
     scopes: &mut Map<&'a Path, ScopeWip<'a>>,
-) -> Result<Scope<'a>, Error> {
+) -> Result<Option<Scope<'a>>, Error> {
     match scopes.get(file) {
-        Some(ScopeWip::Current) => bail!("Recursive import of {}", file.display()),
-        Some(ScopeWip::Resolved(scope)) => return Ok(scope.clone()),
+        Some(ScopeWip::Current) => return Ok(None),
+        Some(ScopeWip::Resolved(scope)) => return Ok(Some(scope.clone())),
         _ => (),
     };
     scopes.insert(file, ScopeWip::Current);
@@ -266,26 +801,235 @@ fn get_scope<'a>(
 
     let mut scope = Scope {
         items: Map::new(),
+        ambiguous: Map::new(),
+        shadowed: Vec::new(),
         direct_imports: Set::new(),
+        recursive_imports: Vec::new(),
         current_file: file,
     };
 
-    for import in parsed_file.imports.keys() {
-        scope.direct_imports.insert(import);
-        let nested = get_scope(&import, files, scopes)?;
-        scope.items.extend(&nested.items);
+    for prelude in implicit_imports(file, config) {
+        let (prelude, _) = files.get_key_value(prelude).ok_or_else(|| {
+            format_err!("Prelude {} is not a part of analyzed files", prelude.display())
+        })?;
+        if !scope.import(prelude, false, files, config, scopes)? {
+            // The prelude imports this file
+            scope.seed_prelude(prelude, files);
+        }
     }
 
-    for definition in &parsed_file.definitions {
-        scope.items.insert(
+    // Everything brought by preludes is available directly
+    scope.direct_imports.extend(scope.items.values().map(|defined| defined.origin));
+
+    for (path, import) in &parsed_file.imports {
+        let with_classes = matches!(import.importee, Importee::UsingModule(_));
+        if !scope.import(path, with_classes, files, config, scopes)? {
+            scope.recursive_imports.push(path);
+        }
+    }
+
+    // Definitions nested in blocks are not visible to importers
+    let file_scope_definitions = parsed_file.definitions
+        .iter()
+        .filter(|definition| definition.visible_in.is_none());
+
+    for definition in file_scope_definitions {
+        let previous = scope.items.insert(
             definition.item.as_case_insensitive(),
             DefinedItem { definition, origin: file },
         );
+        if let Some(previous) = previous {
+            if previous.origin != file && definition.item.is_function() {
+                scope.shadowed.push((definition, previous));
+            }
+        }
+        // Local definitions take precedence over imported ones
+        scope.ambiguous.remove(&definition.item.as_case_insensitive());
     }
 
     scopes.insert(file, ScopeWip::Resolved(scope.clone()));
 
-    Ok(scope)
+    Ok(Some(scope))
+}
+
+/// Preludes implicitly imported by a file. Preludes themselves
+/// don't see other preludes, otherwise they would import each other.
+fn implicit_imports<'c>(file: &Path, config: &'c Config) -> &'c [PathBuf] {
+    if config.preludes.iter().any(|prelude| prelude == file) {
+        &[]
+    } else {
+        &config.preludes
+    }
+}
+
+/// Finds broken imports of a file and of the files it (possibly indirectly) imports
+fn broken_imports_in_scope<'a>(file: &'a Path, files: &'a Map<PathBuf, Parsed>)
+    -> Vec<(&'a Parsed, &'a syntax::Import)>
+{
+    let mut broken_imports = Vec::new();
+    let mut visited = Set::new();
+    let mut stack = vec![file];
+
+    while let Some(file) = stack.pop() {
+        let parsed = match files.get(file) {
+            Some(parsed) if visited.insert(file) => parsed,
+            _ => continue,
+        };
+        broken_imports.extend(parsed.broken_imports.iter().map(|import| (parsed, import)));
+        stack.extend(parsed.imports.keys().map(|import| &**import));
+    }
+
+    broken_imports
+}
+
+/// How an item used in a file gets into its scope
+pub enum Explanation<'a> {
+    /// Files through which the item is imported, starting with
+    /// the file itself, and the definition in the last of them
+    Imported(Vec<&'a Path>, &'a syntax::Definition),
+
+    /// Assumed to exist, for a given reason
+    Assumed(&'static str),
+
+    NotInScope,
+}
+
+/// Explains where an item in scope of a file comes from, for `shelly why`
+pub fn explain<'a>(
+    file: &'a Path,
+    item: &Item<&str>,
+    files: &'a Map<PathBuf, Parsed>,
+    scopes: &Map<&'a Path, Scope<'a>>,
+    config: &'a Config,
+) -> Explanation<'a> {
+    let defined = match scopes.get(file).and_then(|scope| scope.search(item)) {
+        Some((_, defined)) => defined,
+        None => {
+            let item = item.as_case_insensitive();
+            return if config.is_shipped(&item) {
+                Explanation::Assumed("it's a builtin")
+            } else if config.is_custom_item(file, &item) {
+                Explanation::Assumed("it's listed in config or in a builtins file")
+            } else if files.get(file).is_some_and(|parsed| required_module_items(parsed).contains(&item)) {
+                Explanation::Assumed("it's a command of a module from `#Requires -Modules`")
+            } else {
+                Explanation::NotInScope
+            };
+        }
+    };
+
+    // Follow the imports (or preludes) which bring the same definition
+    let mut chain = vec![file];
+    while let Some(&current) = chain.last().filter(|&&current| current != defined.origin) {
+        let next = files[current].imports.keys()
+            .map(|import| &**import)
+            .chain(config.preludes.iter().map(|prelude| &**prelude))
+            .filter(|import| !chain.contains(import))
+            .find(|import| {
+                scopes.get(import)
+                    .and_then(|scope| scope.search(item))
+                    .is_some_and(|(_, found)| found.origin == defined.origin)
+            });
+        // Shouldn't happen, but the definition should still be the last link
+        chain.push(next.unwrap_or(defined.origin));
+    }
+
+    Explanation::Imported(chain, defined.definition)
+}
+
+/// Finds cycles of imports, as strongly connected components
+/// of the import graph (with a file importing itself being a cycle too).
+/// Implicit imports of preludes are a part of the graph too.
+/// Files of each cycle are sorted.
+fn import_cycles<'a>(files: &'a Map<PathBuf, Parsed>, config: &'a Config) -> Vec<Vec<&'a Path>> {
+    /// State of Tarjan's algorithm
+    struct Search<'a> {
+        files: &'a Map<PathBuf, Parsed>,
+        config: &'a Config<'a>,
+        indices: Map<&'a Path, usize>,
+        lowlinks: Map<&'a Path, usize>,
+        stack: Vec<&'a Path>,
+        on_stack: Set<&'a Path>,
+        cycles: Vec<Vec<&'a Path>>,
+    }
+
+    impl<'a> Search<'a> {
+        fn visit(&mut self, file: &'a Path) {
+            let index = self.indices.len();
+            self.indices.insert(file, index);
+            self.lowlinks.insert(file, index);
+            self.stack.push(file);
+            self.on_stack.insert(file);
+
+            let files = self.files;
+            let imports = files[file].imports.keys()
+                .chain(implicit_imports(file, self.config))
+                .filter_map(|import| files.get_key_value(import))
+                .map(|(import, _)| &**import);
+            for import in imports {
+                if !self.indices.contains_key(import) {
+                    self.visit(import);
+                    let lowlink = self.lowlinks[file].min(self.lowlinks[import]);
+                    self.lowlinks.insert(file, lowlink);
+                } else if self.on_stack.contains(import) {
+                    let lowlink = self.lowlinks[file].min(self.indices[import]);
+                    self.lowlinks.insert(file, lowlink);
+                }
+            }
+
+            if self.lowlinks[file] == index {
+                let start = self.stack.iter().rposition(|&other| other == file).unwrap();
+                let mut component = self.stack.split_off(start);
+                for other in &component {
+                    self.on_stack.remove(other);
+                }
+                let imports_itself = self.files[file].imports.contains_key(file);
+                if component.len() > 1 || imports_itself {
+                    component.sort();
+                    self.cycles.push(component);
+                }
+            }
+        }
+    }
+
+    let mut search = Search {
+        files,
+        config,
+        indices: Map::new(),
+        lowlinks: Map::new(),
+        stack: Vec::new(),
+        on_stack: Set::new(),
+        cycles: Vec::new(),
+    };
+    for file in files.keys() {
+        if !search.indices.contains_key(&**file) {
+            search.visit(file);
+        }
+    }
+
+    search.cycles
+}
+
+/// Imports between files of a cycle, as `(importer, imported)`,
+/// in the order of visiting the cycle starting at a given file
+fn cycle_imports<'a>(start: &'a Path, cycle: &[&'a Path], files: &'a Map<PathBuf, Parsed>)
+    -> Vec<(&'a Path, &'a Path)>
+{
+    let mut imports = Vec::new();
+    let mut visited = Set::new();
+    let mut queue = ::std::collections::VecDeque::from(vec![start]);
+    visited.insert(start);
+
+    while let Some(file) = queue.pop_front() {
+        for import in files[file].imports.keys().filter(|import| cycle.contains(&&***import)) {
+            imports.push((file, &**import));
+            if visited.insert(&**import) {
+                queue.push_back(import);
+            }
+        }
+    }
+
+    imports
 }
 
 #[cfg(test)]
@@ -321,6 +1065,7 @@ mod test {
         Definition {
             span: Span::dummy(),
             item: Item::function(fun.to_owned()),
+            visible_in: None,
         }
     }
 
@@ -328,6 +1073,7 @@ mod test {
         Definition {
             span: Span::dummy(),
             item: Item::class(class.to_owned()),
+            visible_in: None,
         }
     }
 
@@ -365,7 +1111,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
 
@@ -380,13 +1126,13 @@ mod test {
         ].into_iter().collect();
 
         let mut emitter = VecEmitter::new();
-        let res = analyze(
+        analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
-        );
+        ).unwrap();
 
-        assert!(res.is_err());
+        assert_eq!(emitter.lints(), [Lint::RecursiveImports]);
     }
 
     #[test]
@@ -398,7 +1144,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
 
@@ -439,7 +1185,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
 
@@ -479,7 +1225,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
 
@@ -515,6 +1261,7 @@ mod test {
             ( "file_B".into(), Parsed {
                     imports: collect![import("file_C")],
                     definitions: vec![definition("bar")],
+                    original_path: "file_B".into(),
                     ..Parsed::default()
                 }),
             ( "file_C".into(), Parsed {
@@ -529,6 +1276,7 @@ mod test {
             ( "file_E".into(), Parsed {
                     imports: collect![import("file_B")],
                     usages: vec![usage("New-Item")],
+                    original_path: "file_E".into(),
                     ..Parsed::default()
                 }),
         ].into_iter().collect();
@@ -536,7 +1284,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
 
@@ -563,7 +1311,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
         assert_eq!(emitter.emitted_items.len(), 2);
@@ -587,6 +1335,7 @@ mod test {
                     usages: vec![usage("MyFunB")],
                     definitions: vec![definition("MyFunA")],
                     imports: collect![import("file_A")],
+                    original_path: "file_B".into(),
                     ..Parsed::default()
                 }
             ),
@@ -595,6 +1344,7 @@ mod test {
                 Parsed {
                     usages: vec![usage("MyFunB"), usage("myFunA")],
                     imports: collect![import("file_B")],
+                    original_path: "file_C".into(),
                     ..Parsed::default()
                 }
             ),
@@ -603,7 +1353,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
         let invalid_casing_lints: Vec<_> = emitter.emitted_items
@@ -636,7 +1386,7 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
         assert_eq!(emitter.emitted_items.len(), 1);
@@ -669,9 +1419,209 @@ mod test {
         let mut emitter = VecEmitter::new();
         analyze(
             &files,
-            &ConfigFile::default(),
+            &Config::default(),
+            &mut Emitter::new(&mut emitter, lint::Config::default())
+        ).unwrap();
+        assert_eq!(emitter.emitted_items.len(), 0);
+    }
+
+    #[test]
+    fn test_preludes_are_in_scope_of_every_file() {
+        let files = vec![
+            (
+                "Prelude".into(),
+                Parsed {
+                    definitions: vec![definition("Initialize-Everything")],
+                    ..Parsed::default()
+                }
+            ),
+            (
+                "A".into(),
+                Parsed {
+                    usages: vec![usage("Initialize-Everything")],
+                    ..Parsed::default()
+                }
+            ),
+        ].into_iter().collect();
+
+        let config = Config {
+            preludes: vec!["Prelude".into()],
+            ..Config::default()
+        };
+
+        let mut emitter = VecEmitter::new();
+        analyze(
+            &files,
+            &config,
             &mut Emitter::new(&mut emitter, lint::Config::default())
         ).unwrap();
         assert_eq!(emitter.emitted_items.len(), 0);
     }
+
+    #[test]
+    fn test_preludes_can_import_project_files() {
+        let files = vec![
+            (
+                "A".into(),
+                Parsed {
+                    definitions: vec![definition("Get-Helper")],
+                    usages: vec![usage("Initialize-Everything")],
+                    ..Parsed::default()
+                }
+            ),
+            (
+                "B".into(),
+                Parsed {
+                    usages: vec![usage("Get-Helper"), usage("Initialize-Everything")],
+                    ..Parsed::default()
+                }
+            ),
+            (
+                "Prelude".into(),
+                Parsed {
+                    imports: collect![import("A")],
+                    definitions: vec![definition("Initialize-Everything")],
+                    usages: vec![usage("Get-Helper")],
+                    ..Parsed::default()
+                }
+            ),
+        ].into_iter().collect();
+
+        let config = Config {
+            preludes: vec!["Prelude".into()],
+            ..Config::default()
+        };
+
+        let mut emitter = VecEmitter::new();
+        analyze(
+            &files,
+            &config,
+            &mut Emitter::new(&mut emitter, lint::Config::default())
+        ).unwrap();
+        assert_eq!(emitter.emitted_items.len(), 0);
+    }
+
+    #[test]
+    fn test_warns_about_functions_defined_in_multiple_imported_files() {
+        let files = vec![
+            (
+                "A".into(),
+                Parsed {
+                    imports: collect![import("B"), import("C")],
+                    usages: vec![usage("Get-Foo")],
+                    ..Parsed::default()
+                }
+            ),
+            (
+                "B".into(),
+                Parsed {
+                    original_path: "B".into(),
+                    definitions: vec![definition("Get-Foo")],
+                    ..Parsed::default()
+                }
+            ),
+            (
+                "C".into(),
+                Parsed {
+                    original_path: "C".into(),
+                    definitions: vec![definition("get-foo")],
+                    ..Parsed::default()
+                }
+            ),
+        ].into_iter().collect();
+
+        let mut emitter = VecEmitter::new();
+        analyze(
+            &files,
+            &Config::default(),
+            &mut Emitter::new(&mut emitter, lint::Config::default())
+        ).unwrap();
+
+        let ambiguous = emitter.only(Lint::AmbiguousUsages);
+        assert_eq!(ambiguous.len(), 1);
+        emitter.assert_contains(Lint::AmbiguousUsages, "Resolved to this definition from C");
+        emitter.assert_contains(Lint::AmbiguousUsages, "Also defined in B");
+    }
+
+    #[test]
+    fn test_same_function_imported_through_multiple_files_is_not_ambiguous() {
+        let files = vec![
+            (
+                "A".into(),
+                Parsed {
+                    imports: collect![import("B"), import("C")],
+                    usages: vec![usage("Get-Foo")],
+                    ..Parsed::default()
+                }
+            ),
+            ("B".into(), Parsed { imports: collect![import("D")], ..Parsed::default() }),
+            ("C".into(), Parsed { imports: collect![import("D")], ..Parsed::default() }),
+            ("D".into(), Parsed { definitions: vec![definition("Get-Foo")], ..Parsed::default() }),
+        ].into_iter().collect();
+
+        let mut emitter = VecEmitter::new();
+        analyze(
+            &files,
+            &Config::default(),
+            &mut Emitter::new(&mut emitter, lint::Config::default())
+        ).unwrap();
+
+        assert!(emitter.only(Lint::AmbiguousUsages).is_empty());
+    }
+
+    #[test]
+    fn test_warns_when_local_function_shadows_an_imported_one() {
+        let files = vec![
+            (
+                "A".into(),
+                Parsed {
+                    imports: collect![import("B")],
+                    usages: vec![usage("Get-Foo"), usage("Get-Bar")],
+                    definitions: vec![definition("get-foo")],
+                    ..Parsed::default()
+                }
+            ),
+            (
+                "B".into(),
+                Parsed {
+                    original_path: "B".into(),
+                    definitions: vec![definition("Get-Foo"), definition("Get-Bar")],
+                    ..Parsed::default()
+                }
+            ),
+        ].into_iter().collect();
+
+        let mut emitter = VecEmitter::new();
+        analyze(
+            &files,
+            &Config::default(),
+            &mut Emitter::new(&mut emitter, lint::Config::default())
+        ).unwrap();
+
+        assert_eq!(emitter.only(Lint::ShadowedImports).len(), 1);
+        emitter.assert_contains(Lint::ShadowedImports, "Imported from B");
+    }
+
+    #[test]
+    fn test_extra_items() {
+        let config_file: ConfigFile = concat!(
+            "[extras]\n",
+            "cmdlets = [\"Get-Foo\"]\n",
+            "classes = [\"Foo.Bar\"]\n",
+            "variables = [\"$BuildRoot\"]\n",
+        ).parse().unwrap();
+        let nested: ConfigFile = "[extras]\ncmdlets = [\"Get-Nested\"]\n".parse().unwrap();
+
+        let config = Config::from_config_file(&config_file, &[], Path::new(".")).unwrap()
+            .with_nested("sub".into(), &nested, &[]).unwrap();
+        let custom = |file: &str, item: Item<&str>| config.is_custom_item(Path::new(file), &item.as_case_insensitive());
+
+        assert!(custom("A", Item::function("get-foo")));
+        assert!(custom("A", Item::class("Foo.Bar")));
+        assert!(!custom("A", Item::function("Foo.Bar")));
+        assert!(custom("A", Item::variable("buildroot")));
+        assert!(custom("sub/A", Item::function("Get-Nested")));
+        assert!(!custom("A", Item::function("Get-Nested")));
+        assert_eq!(config.custom_cmdlets().collect::<Vec<_>>(), ["Get-Foo"]);
+    }
 }
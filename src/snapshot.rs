@@ -0,0 +1,213 @@
+//! Builtins files converted from `Get-Command` snapshots by `shelly builtins import`
+//!
+//! A snapshot is produced on a machine with the modules installed, either by
+//! `Get-Command | Select-Object Name, CommandType, Source | ConvertTo-Json`
+//! or by `Get-Command | Export-Csv`.
+
+use failure::Error;
+use serde_json::{self, Value};
+use unicase::UniCase;
+
+use std::collections::BTreeMap as Map;
+use std::fmt::Write;
+
+/// Command types which can be called by name from scripts,
+/// as numbers of `System.Management.Automation.CommandTypes`.
+/// Applications and external scripts are files found in `$PATH`, so they're skipped.
+const COMMAND_TYPES: &[(&str, u64)] = &[
+    ("Alias", 1),
+    ("Function", 2),
+    ("Filter", 4),
+    ("Cmdlet", 8),
+    ("Configuration", 256),
+];
+
+/// A row of the snapshot
+#[derive(Debug, PartialEq)]
+struct Command {
+    name: String,
+
+    /// Module providing the command, empty if unknown
+    module: String,
+}
+
+/// Converts a snapshot to a builtins file, with commands grouped by module
+pub fn import_builtins(snapshot: &str) -> Result<String, Error> {
+    let snapshot = snapshot.trim_start_matches('\u{feff}');
+    let commands = if snapshot.trim_start().starts_with(['[', '{']) {
+        parse_json(snapshot)?
+    } else {
+        parse_csv(snapshot)?
+    };
+
+    let mut by_module: Map<UniCase<&str>, Map<UniCase<&str>, &str>> = Map::new();
+    for command in &commands {
+        by_module.entry(UniCase::new(&command.module))
+            .or_default()
+            .insert(UniCase::new(&command.name), &command.name);
+    }
+
+    let mut builtins = String::from("# Generated by `shelly builtins import`\n");
+    for (module, names) in by_module {
+        let module = if module.is_empty() { "(no module)" } else { *module };
+        let _ = writeln!(builtins, "\n# {}", module);
+        for name in names.values() {
+            let _ = writeln!(builtins, "{}", name);
+        }
+    }
+
+    Ok(builtins)
+}
+
+/// Whether the command type, given as a number or a name, is callable by name
+fn is_callable(command_type: &str) -> bool {
+    COMMAND_TYPES.iter().any(|&(name, number)| {
+        command_type.eq_ignore_ascii_case(name) || command_type.parse() == Ok(number)
+    })
+}
+
+fn parse_json(snapshot: &str) -> Result<Vec<Command>, Error> {
+    let value: Value = serde_json::from_str(snapshot)
+        .map_err(|e| format_err!("Invalid JSON snapshot: {}", e))?;
+
+    // A single command is serialized without an array
+    let objects = match value {
+        Value::Array(objects) => objects,
+        object => vec![object],
+    };
+
+    let mut commands = Vec::new();
+    for object in objects {
+        let field = |key: &str| match object.get(key) {
+            Some(Value::String(value)) => Some(value.clone()),
+            Some(Value::Number(value)) => Some(value.to_string()),
+            _ => None,
+        };
+
+        let name = match field("Name") {
+            Some(name) => name,
+            None => bail!("Command without a `Name` in the JSON snapshot: {}", object),
+        };
+        if field("CommandType").is_some_and(|command_type| !is_callable(&command_type)) {
+            continue;
+        }
+
+        let module = field("Source").or_else(|| field("ModuleName")).unwrap_or_default();
+        commands.push(Command { name, module });
+    }
+
+    Ok(commands)
+}
+
+fn parse_csv(snapshot: &str) -> Result<Vec<Command>, Error> {
+    let mut rows = csv_rows(snapshot)?.into_iter()
+        // `#TYPE` line written by Windows PowerShell
+        .filter(|row| !row.first().is_some_and(|field| field.starts_with("#TYPE")));
+
+    let header = match rows.next() {
+        Some(header) => header,
+        None => bail!("Empty snapshot"),
+    };
+    let column = |name: &str| header.iter().position(|field| field.eq_ignore_ascii_case(name));
+
+    let name_column = match column("Name") {
+        Some(column) => column,
+        None => bail!("No `Name` column in the CSV snapshot"),
+    };
+    let type_column = column("CommandType");
+    let module_column = column("Source").or_else(|| column("ModuleName"));
+
+    let mut commands = Vec::new();
+    for row in rows {
+        let field = |column: Option<usize>| column.and_then(|column| row.get(column)).map(String::as_str);
+
+        let name = match field(Some(name_column)) {
+            Some(name) if !name.is_empty() => name.to_owned(),
+            _ => continue,
+        };
+        if field(type_column).is_some_and(|command_type| !is_callable(command_type)) {
+            continue;
+        }
+
+        let module = field(module_column).unwrap_or_default().to_owned();
+        commands.push(Command { name, module });
+    }
+
+    Ok(commands)
+}
+
+/// Splits CSV into rows of fields. Quoted fields can contain
+/// commas, newlines and quotes (doubled).
+fn csv_rows(csv: &str) -> Result<Vec<Vec<String>>, Error> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(::std::mem::take(&mut field)),
+            '\r' if !quoted => (),
+            '\n' if !quoted => {
+                row.push(::std::mem::take(&mut field));
+                rows.push(::std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        bail!("Unterminated quoted field in the CSV snapshot");
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+#[test]
+fn test_import_json() {
+    let snapshot = r#"[
+        {"Name": "Get-Mailbox", "CommandType": 8, "Source": "ExchangeOnlineManagement"},
+        {"Name": "git.exe", "CommandType": 32, "Source": "C:\\Program Files\\Git\\cmd\\git.exe"},
+        {"Name": "gmb", "CommandType": "Alias", "Source": ""},
+        {"Name": "Connect-ExchangeOnline", "CommandType": 2, "Source": "ExchangeOnlineManagement"}
+    ]"#;
+
+    assert_eq!(
+        import_builtins(snapshot).unwrap(),
+        "# Generated by `shelly builtins import`\n\
+         \n# (no module)\ngmb\n\
+         \n# ExchangeOnlineManagement\nConnect-ExchangeOnline\nGet-Mailbox\n",
+    );
+
+    assert!(import_builtins(r#"{"Name": "Get-Foo"}"#).unwrap().ends_with("\nGet-Foo\n"));
+    assert!(import_builtins(r#"[{"CommandType": 8}]"#).is_err());
+}
+
+#[test]
+fn test_import_csv() {
+    let snapshot = "\u{feff}#TYPE System.Management.Automation.CmdletInfo\r\n\
+        \"HelpUri\",\"Name\",\"CommandType\",\"Definition\",\"Source\"\r\n\
+        \"\",\"Get-Mailbox\",\"Cmdlet\",\"Get-Mailbox [-Identity] <String>\",\"ExchangeOnlineManagement\"\r\n\
+        \"\",\"Invoke-Thing\",\"Function\",\"param($a, \"\"b\"\")\n$a\n\",\"Tools\"\r\n\
+        \"\",\"git.exe\",\"Application\",\"\",\"\"\r\n";
+
+    assert_eq!(
+        import_builtins(snapshot).unwrap(),
+        "# Generated by `shelly builtins import`\n\
+         \n# ExchangeOnlineManagement\nGet-Mailbox\n\
+         \n# Tools\nInvoke-Thing\n",
+    );
+
+    assert!(import_builtins("\"Source\"\n\"Foo\"\n").is_err());
+    assert!(csv_rows("\"unterminated\n").is_err());
+}
@@ -1,56 +1,205 @@
+//! Checks that files run with some requirement met, eg. strict mode enabled.
+//!
+//! A requirement is met by calling one of its commandlets,
+//! either in the file itself or in one of the imported files.
+
 use std::collections::BTreeSet as Set;
 use std::collections::BTreeMap as Map;
 use std::path::{Path, PathBuf};
 
+use failure::Error;
+use unicase;
+
+use config::StrictnessScope;
 use lint::Emitter;
 use lint::Lint;
+use imports::Entrypoints;
+use ConfigFile;
 use Location;
-use preprocess::Parsed;
+use preprocess::{FileKind, Parsed};
 use scope::Scope;
 use syntax::Item;
 
-// This should be a constant, not a constant-returning
-// function, but constants are currently a little limited on stable Rust.
-fn strict_mode_pseudoitem() -> Item<&'static str> {
-    Item::pseudo("!EnablesStrictMode")
+/// A requirement checked by this pass
+struct Requirement {
+    /// Name of the pseudo-item injected by meeting the requirement
+    pseudoitem: String,
+
+    /// Commandlets meeting the requirement
+    injected_by: Vec<String>,
+
+    /// Which files should meet the requirement
+    scope: StrictnessScope,
+
+    lint: Lint,
+    message: String,
+}
+
+pub struct Config {
+    /// Strict mode and requirements from config
+    requirements: Vec<Requirement>,
+
+    /// Files required to meet the requirements. If not declared,
+    /// files not imported by any other file are.
+    entrypoints: Option<Entrypoints>,
+
+    /// Whether files neither imported nor declared as entry points
+    /// are reported by `unused-files`. Otherwise they're required
+    /// to meet the requirements, as entry points are.
+    unused_files: bool,
 }
 
-pub fn preprocess(file: &mut Parsed) {
-    // We treat setting strict mode as defining
-    // a "!EnablesStrictMode" pseudo-item.
-    for usage in &file.usages {
-        if usage.item.as_ref() == Item::function("Set-StrictMode") {
-            file.definitions.push(::syntax::Definition {
-                item: strict_mode_pseudoitem().into(),
-                span: usage.span.clone()
+impl Config {
+    pub fn from_config_file(config_file: &ConfigFile, root: &Path) -> Result<Config, Error> {
+        let strict_mode_scope = config_file.strictness
+            .as_ref()
+            .and_then(|strictness| strictness.scope)
+            .unwrap_or(StrictnessScope::Roots);
+
+        // We treat setting strict mode as defining
+        // a "!EnablesStrictMode" pseudo-item.
+        let strict_mode = Requirement {
+            pseudoitem: "!EnablesStrictMode".to_owned(),
+            injected_by: vec!["Set-StrictMode".to_owned()],
+            scope: strict_mode_scope,
+            lint: Lint::NoStrictMode,
+            message: "strict mode not enabled for this file".to_owned(),
+        };
+
+        let custom = config_file.requirements
+            .iter()
+            .flatten()
+            .map(|requirement| Requirement {
+                pseudoitem: format!("!{}", requirement.name),
+                injected_by: requirement.injected_by.clone(),
+                scope: requirement.scope.unwrap_or(StrictnessScope::Roots),
+                lint: Lint::MissingRequirements,
+                message: requirement.message.clone().unwrap_or_else(|| {
+                    format!("requirement `{}` not met for this file", requirement.name)
+                }),
             });
-            break;
+
+        Ok(Config {
+            requirements: ::std::iter::once(strict_mode).chain(custom).collect(),
+            entrypoints: Entrypoints::from_config_file(config_file, root)?,
+            unused_files: false,
+        })
+    }
+
+    /// Sets whether `unused-files` is checked
+    pub fn with_unused_files(mut self, unused_files: bool) -> Config {
+        self.unused_files = unused_files;
+        self
+    }
+}
+
+pub fn preprocess(file: &mut Parsed, config: &Config) {
+    for requirement in &config.requirements {
+        let usage = file.usages.iter().find(|usage| {
+            usage.item.is_function()
+                && requirement.injected_by.iter().any(|cmdlet| unicase::eq(usage.name(), &**cmdlet))
+        });
+
+        if let Some(usage) = usage {
+            let definition = ::syntax::Definition {
+                item: Item::pseudo(requirement.pseudoitem.clone()),
+                span: usage.span,
+                visible_in: None,
+            };
+            file.definitions.push(definition);
         }
     }
 }
 
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[Lint::NoStrictMode, Lint::MissingRequirements];
+
 pub fn analyze<'a>(
     files: &'a Map<PathBuf, Parsed>,
     scopes: &Map<&'a Path, Scope<'a>>,
+    config: &Config,
     emitter: &mut Emitter,
 ) {
-    let mut importees: Set<&Path> = Set::new();
+    // Files importing a given one
+    let mut importers: Map<&Path, Vec<&Path>> = Map::new();
 
-    for parsed in files.values() {
+    for (path, parsed) in files {
         for importee in parsed.imports.keys() {
-            importees.insert(importee);
+            importers.entry(importee).or_default().push(path);
+        }
+    }
+
+    let is_root = |file: &Path| match &config.entrypoints {
+        Some(entrypoints) => {
+            entrypoints.contains(&files[file].original_path)
+                || (!config.unused_files && !importers.contains_key(file))
+        }
+        None => !importers.contains_key(file),
+    };
+
+    for requirement in &config.requirements {
+        let meets = |file: &Path| scopes[file].search(&Item::pseudo(&*requirement.pseudoitem)).is_some();
+
+        for &file in scopes.keys() {
+            // Manifests contain no code
+            if files[file].kind == FileKind::Manifest || meets(file) {
+                continue;
+            }
+
+            let message = Location::whole_file(&files[file])
+                .lint(requirement.lint, requirement.message.clone())
+                .note(format!("Call `{}` to fix", requirement.injected_by.join("` or `")));
+
+            if is_root(file) {
+                message.emit(emitter);
+                continue;
+            }
+
+            // Files neither imported nor declared as entry points
+            // (when not roots) are reported by `unused-files` instead
+            if requirement.scope == StrictnessScope::Roots || !importers.contains_key(file) {
+                continue;
+            }
+
+            // An imported file is fine if all the roots that
+            // (possibly indirectly) import it meet the requirement
+            let unmet_root = find_roots(file, &importers, &is_root)
+                .into_iter()
+                .find(|&root| !meets(root));
+
+            if let Some(root) = unmet_root {
+                message
+                    .note_at(
+                        Location::whole_file(&files[root]),
+                        "Imported (possibly indirectly) by this file, which doesn't meet the requirement",
+                    )
+                    .emit(emitter);
+            }
         }
     }
+}
 
-    let all_files: Set<&Path> = scopes.keys().cloned().collect();
+/// Finds roots (entry points or files not imported by any other file)
+/// which (possibly indirectly) import a given one.
+fn find_roots<'a>(
+    file: &'a Path,
+    importers: &Map<&'a Path, Vec<&'a Path>>,
+    is_root: &dyn Fn(&Path) -> bool,
+) -> Set<&'a Path> {
+    let mut roots = Set::new();
+    let mut visited = Set::new();
+    let mut stack = vec![file];
 
-    let root_files: Set<&Path> = all_files.difference(&importees).cloned().collect();
+    while let Some(file) = stack.pop() {
+        if !visited.insert(file) {
+            continue;
+        }
 
-    for &file in &root_files {
-        if scopes[file].search(&strict_mode_pseudoitem()).is_none() {
-            Location::whole_file(&files[file])
-                .lint(Lint::NoStrictMode, "strict mode not enabled for this file")
-                .emit(emitter);
+        if is_root(file) {
+            roots.insert(file);
         }
+        stack.extend(importers.get(file).into_iter().flatten());
     }
+
+    roots
 }
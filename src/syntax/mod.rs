@@ -1,11 +1,12 @@
+use std::collections::BTreeMap as Map;
+use std::collections::BTreeSet as Set;
 use std::path::PathBuf;
 
 use regex::Regex;
 use unicase::{self, UniCase};
 
-mod v2;
+pub mod v2;
 pub use self::v2::{Span, FileStr};
-pub use self::v2::Error;
 pub use self::v2::Result;
 use self::v2::TokenTree as TT;
 
@@ -16,9 +17,35 @@ pub struct File {
     pub definitions: Vec<Definition>,
     pub usages: Vec<Usage>,
     pub testcases: Vec<Testcase>,
+    pub describes: Vec<Describe>,
+
+    /// Scriptblocks of Pester's `BeforeAll` and `BeforeEach`
+    pub setup_blocks: Vec<Span>,
+
+    /// Functions mocked with Pester's `Mock`
+    pub mocks: Vec<Mock>,
+
+    /// Accesses of `$env:` variables, in source order
+    pub env_variables: Vec<EnvVariable>,
+
+    /// Spans of `#` and `<# #>` comments
+    pub comments: Vec<Span>,
+
+    /// Names of modules from `#Requires -Modules`
+    pub required_modules: Vec<String>,
+
+    /// Version from the `#Requires -Version` statement
+    pub required_version: Option<RequiredVersion>,
+
+    /// Names (possibly with wildcards) of functions and aliases
+    /// exported by `Export-ModuleMember`, `None` if it's not called
+    pub exported_functions: Option<Vec<String>>,
+
+    /// Syntax errors the parser recovered from
+    pub errors: Vec<v2::Error>,
 }
 
-/// A `.` import
+/// A `.` import or `Import-Module` statement
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Import {
     pub span: Span,
@@ -34,6 +61,19 @@ pub enum Importee {
     /// Points to system under test, namely `$here/$sut`
     HereSut,
 
+    /// `$env:BUILD_ROOT/...`, relative to a directory given by an environment variable
+    EnvRelative(String, PathBuf),
+
+    /// `Import-Module Name`, module given by name rather than path
+    Module(String),
+
+    /// `Import-Module $PSScriptRoot/...`, module given by a path relative to the script
+    ModulePath(PathBuf),
+
+    /// `using module ./Classes.psm1`, bringing also classes of the module into scope.
+    /// The module is given by a path relative to the script or by name.
+    UsingModule(String),
+
     Unrecognized(String),
 }
 
@@ -49,6 +89,12 @@ pub enum ItemKind {
     Function,
     Class,
 
+    /// Variable, without the `$`. Only known from config for now.
+    Variable,
+
+    /// External program invoked with its extension, eg. `git.exe`
+    Executable,
+
     /// Pseudoitems are items that are propaged similarly to normal
     /// definitions, but they're created by some part of analysis.
     /// Eg. we have "uses strict mode" pseudoitem, that gets injected
@@ -79,12 +125,21 @@ impl<S: AsRef<str>> Item<S> {
         Item { name, kind: ItemKind::Class, }
     }
 
+    pub fn variable(name: S) -> Self {
+        Item { name, kind: ItemKind::Variable, }
+    }
+
+    pub fn executable(name: S) -> Self {
+        Item { name, kind: ItemKind::Executable, }
+    }
+
     pub fn pseudo(name: S) -> Self {
         Item { name, kind: ItemKind::Pseudoitem, }
     }
 
     pub fn is_function(&self) -> bool { self.kind == ItemKind::Function }
     pub fn is_class(&self) -> bool { self.kind == ItemKind::Class }
+    pub fn is_executable(&self) -> bool { self.kind == ItemKind::Executable }
 
 }
 
@@ -102,6 +157,10 @@ impl<'a> From<Item<&'a str>> for Item<String> {
 pub struct Definition {
     pub span: Span,
     pub item: Item<String>,
+
+    /// Block the definition is visible in,
+    /// `None` for definitions in the file scope
+    pub visible_in: Option<Span>,
 }
 
 /// Function/commandlet call / usage of a class
@@ -120,101 +179,222 @@ impl Usage {
 pub struct Testcase {
     pub span: Span,
     pub name: String,
+
+    /// `-TestCases` (or `-ForEach`) parameter
+    pub test_cases: Option<TestCases>,
+}
+
+/// Test cases of a templated testcase, eg. `It "handles <Value>" -TestCases @(...)`
+#[derive(Debug, Eq, PartialEq)]
+pub enum TestCases {
+    /// An array of hashtables, with (lowercased) keys present in all of them
+    Literal(Set<String>),
+
+    /// Test cases given in some other way, eg. by a variable
+    Dynamic,
+}
+
+/// `Describe` block
+#[derive(Debug)]
+pub struct Describe {
+    pub span: Span,
+    pub name: String,
+}
+
+/// Function mocked with Pester's `Mock`
+#[derive(Debug)]
+pub struct Mock {
+    /// Span of the name of the mocked function
+    pub span: Span,
+    pub name: String,
+
+    /// Block the mock is active in, as in `Definition::visible_in`
+    pub visible_in: Option<Span>,
+}
+
+/// A `#Requires -Version` statement
+#[derive(Debug, Clone)]
+pub struct RequiredVersion {
+    /// Span of the version number
+    pub span: Span,
+    pub version: String,
+}
+
+/// An access of an environment variable, eg. `$env:BUILD_ID`
+#[derive(Debug)]
+pub struct EnvVariable {
+    pub span: Span,
+    pub name: String,
+    pub access: EnvAccess,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EnvAccess {
+    Read,
+
+    /// Assignment, eg. `$env:Foo = 1`
+    Write,
+
+    /// Usage in a condition of `if` or `while`, or `Test-Path env:Foo`
+    Check,
 }
 
 /// Parses a source file.
 ///
+/// Syntax errors don't stop the parsing, instead
+/// they're collected in `File::errors`.
+///
 /// Note: Assumes BOM (byte order mark) is stripped.
-pub fn parse(source: &str, debug: bool) -> Result<File> {
+pub fn parse(source: &str, debug: bool) -> File {
     lazy_static! {
         // TODO rewrite import parsing from regexes to token streams
         static ref IMPORT: Regex = Regex::new(
             r"(?ix) ^ \s* \. \s+ (.*?) \s* (\#.*)? $"
         ).unwrap();
 
+        static ref USING_MODULE: Regex = Regex::new(
+            r#"(?ix) ^ \s* using \s+ module \s+ ["']? ( [^"'\s;\#]+ )"#
+        ).unwrap();
+
+        /// `Import-Module` of a path relative to the script, eg. `$PSScriptRoot\Helpers.psm1`
+        static ref IMPORT_MODULE_RELATIVE: Regex = Regex::new(
+            r#"(?ix) ^ \s* Import-Module \s+ (?: -Name \s+ )? ["']? ( \$ PSScriptRoot [^"'\s;]* )"#
+        ).unwrap();
+
         static ref IMPORT_RELATIVE: Regex = Regex::new(
             r"(?ix) ^ \$ PSScriptRoot (.*?) $"
         ).unwrap();
 
+        static ref IMPORT_ENV: Regex = Regex::new(
+            r#"(?ix) ^ ["]? \$ env: (\w+) ( [^"$]*? ) ["]? $"#
+        ).unwrap();
+
         static ref IMPORT_HERESUT: Regex = Regex::new(
             r#"(?ix) ^ ["]? \$ here [/\\] \$ sut ["]? $"#
         ).unwrap();
     }
 
-    let token_tree_stream = v2::parse(source, debug)?;
+    let (token_tree_stream, errors, comments) = v2::parse_with_comments(source, debug);
 
     let mut definitions = Vec::new();
     let mut usages = Vec::new();
     let mut imports = Vec::new();
+    let mut module_imports = Vec::new();
     let mut testcases = Vec::new();
+    let mut describes = Vec::new();
+    let mut setup_blocks = Vec::new();
+    let mut mocks = Vec::new();
+    let mut exported_functions: Option<Vec<String>> = None;
 
-    // Gather function definitions and usages
-    v2::traverse_streams(&token_tree_stream, |stream, _| {
-        let mut is_function_definition = false;
-        let mut iter = stream.iter();
-        while let Some(tt) = iter.next() {
-            match *tt {
-                TT::Cmdlet { span, ident } => {
-                    let name = ident.cut_from(source).to_owned();
-
-                    if is_function_definition {
-                        definitions.push(Definition { span, item: Item::function(name) });
-                    } else {
-                        if !v2::ident_is_keyword(&name) && !name.ends_with(".exe") {
-                            usages.push(Usage { span, item: Item::function(name) });
-                        }
-                    }
-                }
-                _ => {}
-            }
-
-            is_function_definition = match *tt {
-                TT::FunctionKeyword { .. } => true,
-                _                                     => false,
-            };
-        }
-    });
+    gather_functions(&token_tree_stream, None, source, &mut definitions, &mut usages, &mut mocks);
 
-    // Gather class definitions and usages, and testcases
+    // Gather class definitions and usages, testcases, describe and setup blocks
     v2::traverse_streams(&token_tree_stream, |stream, delim| {
-        match (stream, delim) {
-            // TODO: stop representing class names as "fields".
-            (&[TT::Field { span, ident }], Some(v2::Delimiter::Bracket)) => {
-                // This is just a heuristic – not every [<word in brackets>] is necessarily
-                // a class name. But every usage of a class name should be of such form
-                let name = ident.cut_from(source).to_owned();
-                usages.push(Usage { span, item: Item::class(name) });
-            }
-            _ => {}
+        // TODO: stop representing class names as "fields".
+        if let (&[TT::Field { span, ident }], Some(v2::Delimiter::Bracket)) = (stream, delim) {
+            // This is just a heuristic – not every [<word in brackets>] is necessarily
+            // a class name. But every usage of a class name should be of such form
+            let name = ident.cut_from(source).to_owned();
+            usages.push(Usage { span, item: Item::class(name) });
         }
 
-        for window in stream.windows(2) {
-            match window {
-                &[TT::ClassKeyword { .. }, TT::Field { span, ident }] => {
+        for (i, window) in stream.windows(2).enumerate() {
+            match *window {
+                [TT::ClassKeyword { .. }, TT::Field { span, ident }] => {
                     let name = ident.cut_from(source).to_owned();
-                    definitions.push(Definition { span, item: Item::class(name) });
+                    definitions.push(Definition { span, item: Item::class(name), visible_in: None });
                 }
 
-                &[TT::Cmdlet { ident, .. }, TT::String { span, .. }]
+                [TT::Cmdlet { ident, .. }, TT::String { span, .. }]
                 if unicase::eq(ident.cut_from(source), "It") => {
                     // TODO More precise analysis of string content
                     // (consider escape characters, splicing, etc.)
-                    let test_name = FileStr::from(span).cut_from(source).trim_matches('"');
+                    let test_name = FileStr::from(span).cut_from(source).trim_matches(&['"', '\''] as &[char]);
                     testcases.push(Testcase {
-                        span: span,
+                        span,
                         name: test_name.to_owned(),
+                        test_cases: parse_test_cases(&stream[i + 2..], source),
+                    });
+                }
+
+                // `%` and `?` aliases, which are not words
+                [TT::Symbol { symbol: '|', .. }, TT::Symbol { symbol: '%', span }] |
+                [TT::Symbol { symbol: '|', .. }, TT::Symbol { symbol: '?', span }] => {
+                    let name = FileStr::from(span).cut_from(source).to_owned();
+                    usages.push(Usage { span, item: Item::function(name) });
+                }
+
+                [TT::Cmdlet { ident, .. }, TT::String { span, .. }]
+                if unicase::eq(ident.cut_from(source), "Describe") => {
+                    let name = FileStr::from(span).cut_from(source).trim_matches(&['"', '\''] as &[char]);
+                    describes.push(Describe {
+                        span,
+                        name: name.to_owned(),
                     });
                 }
 
+                [TT::Cmdlet { ident, .. }, TT::Group { span, delimiter: v2::Delimiter::Brace, prefix: None, .. }]
+                if ["BeforeAll", "BeforeEach"].iter().any(|block| unicase::eq(ident.cut_from(source), block)) => {
+                    setup_blocks.push(span);
+                }
+
                 _ => {}
             }
         }
+
+        for (i, tt) in stream.iter().enumerate() {
+            definitions.extend(parse_function_drive_assignment(&stream[i..], source));
+
+            if let TT::Cmdlet { ident, .. } = *tt {
+                let cmdlet = ident.cut_from(source);
+                if unicase::eq(cmdlet, "Import-Module") {
+                    module_imports.extend(parse_import_module(&stream[i + 1..], source));
+                }
+                if unicase::eq(cmdlet, "Export-ModuleMember") {
+                    exported_functions.get_or_insert_with(Vec::new)
+                        .extend(parse_export_module_member(&stream[i + 1..], source));
+                }
+                if unicase::eq(cmdlet, "New-Object") {
+                    usages.extend(parse_new_object(&stream[i + 1..], source));
+                }
+                if unicase::eq(cmdlet, "Set-Item") || unicase::eq(cmdlet, "New-Item") {
+                    definitions.extend(parse_function_drive_item(&stream[i + 1..], source));
+                }
+                if ["Set-Alias", "New-Alias", "sal", "nal"].iter().any(|alias| unicase::eq(cmdlet, alias)) {
+                    if let Some((definition, usage)) = parse_alias(&stream[i + 1..], source) {
+                        definitions.push(definition);
+                        usages.push(usage);
+                    }
+                }
+            }
+        }
     });
 
+    let mut computed_imports = gather_computed_imports(&token_tree_stream, source);
+
     for (line, line_no) in source.lines().zip(1..) {
 
         let get_span = |fragment: &str| Span::from_fragment(line_no, fragment, source);
 
+        if let Some(captures) = USING_MODULE.captures(line) {
+            let module = captures.get(1).unwrap().as_str();
+            imports.push(Import {
+                span: get_span(module),
+                importee: Importee::UsingModule(module.replace(r"\", "/")),
+            });
+        }
+
+        if let Some(captures) = IMPORT_MODULE_RELATIVE.captures(line) {
+            let path = captures.get(1).unwrap().as_str();
+            if let Some(relative) = IMPORT_RELATIVE.captures(path) {
+                let relative = relative[1].replace(r"\", "/");
+                imports.push(Import {
+                    span: get_span(path),
+                    importee: Importee::ModulePath(relative.trim_matches('/').into()),
+                });
+            }
+        }
+
         if let Some(captures) = IMPORT.captures(line) {
             let importee_string = &captures[1];
 
@@ -225,6 +405,11 @@ pub fn parse(source: &str, debug: bool) -> Result<File> {
                 Importee::Relative(relative.into())
             } else if IMPORT_HERESUT.is_match(importee_string) {
                 Importee::HereSut
+            } else if let Some(captures) = IMPORT_ENV.captures(importee_string) {
+                let relative = captures[2].replace(r"\", "/");
+                Importee::EnvRelative(captures[1].to_owned(), relative.trim_matches('/').into())
+            } else if let Some(importee) = computed_imports.remove(&line_no) {
+                importee
             } else {
                 Importee::Unrecognized(importee_string.to_owned())
             };
@@ -236,14 +421,656 @@ pub fn parse(source: &str, debug: bool) -> Result<File> {
         }
     }
 
-    Ok(File {
+    imports.extend(module_imports);
+
+    let env_variables = gather_env_variables(&token_tree_stream, source);
+    let required_modules = parse_required_modules(&comments, source);
+    let required_version = parse_required_version(&comments, source);
+
+    File {
         definitions,
         usages,
         imports,
         testcases,
+        describes,
+        setup_blocks,
+        mocks,
+        env_variables,
+        comments,
+        required_modules,
+        required_version,
+        exported_functions,
+        errors,
+    }
+}
+
+/// Gathers function definitions, usages and mocks.
+///
+/// Functions defined inside another function's body or inside
+/// a Pester `Describe`, `Context` or `It` block are visible only
+/// in that block. Functions defined in `BeforeAll` and similar
+/// blocks are visible in the block enclosing them. Same for mocks.
+/// Named blocks (`begin`, `process`, `end`) share the scope
+/// of their function, so they're visible in the whole function body.
+fn gather_functions(
+    stream: &[TT],
+    visible_in: Option<Span>,
+    source: &str,
+    definitions: &mut Vec<Definition>,
+    usages: &mut Vec<Usage>,
+    mocks: &mut Vec<Mock>,
+) {
+    let mut is_function_definition = false;
+
+    // Between function's name and its body
+    let mut in_function_header = false;
+
+    // Between eg. `Describe` and its scriptblock
+    let mut in_pester_header = false;
+
+    for (i, tt) in stream.iter().enumerate() {
+        match *tt {
+            TT::Cmdlet { span, ident } => {
+                let name = ident.cut_from(source).to_owned();
+
+                if !is_function_definition && unicase::eq(&*name, "Mock") {
+                    if let Some((span, mocked)) = parse_mock(&stream[i + 1..], source) {
+                        mocks.push(Mock { span, name: mocked.to_owned(), visible_in });
+                    }
+                }
+
+                if is_function_definition {
+                    definitions.push(Definition { span, item: Item::function(name), visible_in });
+                    in_function_header = true;
+                } else if is_executable_name(&name) {
+                    usages.push(Usage { span, item: Item::executable(name) });
+                } else if !v2::ident_is_keyword(&name) {
+                    in_pester_header |= ["Describe", "Context", "It"].iter().any(|block| unicase::eq(&*name, block));
+                    usages.push(Usage { span, item: Item::function(name) });
+                }
+            }
+            TT::Group { span, ref interior, delimiter, prefix } => {
+                let is_body = (in_function_header || in_pester_header)
+                    && delimiter == v2::Delimiter::Brace
+                    && prefix.is_none();
+                let visible_in = if is_body { Some(span) } else { visible_in };
+                gather_functions(interior, visible_in, source, definitions, usages, mocks);
+                if is_body {
+                    in_function_header = false;
+                    in_pester_header = false;
+                }
+            }
+            TT::String { ref subtrees, .. } => {
+                gather_functions(subtrees, visible_in, source, definitions, usages, mocks);
+            }
+            TT::NamedBlock { ref interior, .. } => {
+                gather_functions(interior, visible_in, source, definitions, usages, mocks);
+            }
+            TT::Symbol { symbol: ';', .. } => {
+                in_pester_header = false;
+            }
+            _ => {}
+        }
+
+        is_function_definition = matches!(*tt, TT::FunctionKeyword { .. });
+    }
+}
+
+/// Gathers dot-imports of paths computed from the script's location,
+/// eg. `. (Join-Path $PSScriptRoot lib\Utils.ps1)`, by line of the `.`
+fn gather_computed_imports(stream: &[TT], source: &str) -> Map<u32, Importee> {
+    let mut imports = Map::new();
+
+    v2::traverse_streams(stream, |stream, _| {
+        for (i, tt) in stream.iter().enumerate() {
+            // The `.` symbol is represented as `=`
+            let span = match *tt {
+                TT::Symbol { symbol: '=', span } if FileStr::from(span).cut_from(source) == "." => span,
+                _ => continue,
+            };
+
+            let starts_statement = i == 0 || matches!(stream[i - 1], TT::Symbol { symbol: ';', .. });
+            if !starts_statement {
+                continue;
+            }
+
+            if let Some(importee) = parse_computed_importee(&stream[i + 1..], source) {
+                imports.insert(span.start.line, importee);
+            }
+        }
+    });
+
+    imports
+}
+
+/// Recognizes a dot-imported path built with `Join-Path`
+/// or relative to `$PSCommandPath`
+fn parse_computed_importee(args: &[TT], source: &str) -> Option<Importee> {
+    let relative = match args {
+        // `. (Join-Path $PSScriptRoot lib\Utils.ps1)`
+        [TT::Group { ref interior, delimiter: v2::Delimiter::Parenthesis, prefix: None, .. }, ..] => {
+            join_path(interior, source)?
+        }
+
+        // `. "$(Split-Path $PSCommandPath)\lib\Utils.ps1"`
+        [TT::String { span, ref subtrees }, ..] => match **subtrees {
+            [TT::Group { span: group_span, ref interior, prefix: Some('$'), .. }] if is_script_dir(interior, source) => {
+                let rest = &source[group_span.end.byte as usize .. span.end.byte as usize];
+                let rest = rest.trim_end_matches('"');
+                if rest.contains('$') {
+                    return None;
+                }
+                rest.to_owned()
+            }
+            _ => return None,
+        },
+
+        // `. $PSCommandPath.Replace('.Tests.ps1', '.ps1')`
+        [ref variable, TT::Symbol { symbol: '=', .. }, TT::Field { ident, .. }, TT::Group { ref interior, .. }, ..]
+        if is_variable(variable, "PSCommandPath", source) && unicase::eq(ident.cut_from(source), "Replace") => {
+            let (_, pattern) = literal_argument(interior.first()?, source)?;
+            return match pattern.to_lowercase().contains(".tests") {
+                true => Some(Importee::HereSut),
+                false => None,
+            };
+        }
+
+        _ => return None,
+    };
+
+    Some(Importee::Relative(relative.replace(r"\", "/").trim_matches('/').into()))
+}
+
+/// Evaluates `Join-Path` relative to the script's directory,
+/// given tokens inside the parentheses
+fn join_path(interior: &[TT], source: &str) -> Option<String> {
+    let args = match interior {
+        [TT::Cmdlet { ident, .. }, args @ ..] if unicase::eq(ident.cut_from(source), "Join-Path") => args,
+        _ => return None,
+    };
+
+    let mut base = None;
+    let mut children = Vec::new();
+    let mut positional = Vec::new();
+
+    let mut i = 0;
+    while let Some(tt) = args.get(i) {
+        match *tt {
+            TT::Flag { ident, .. } => {
+                let flag = ident.cut_from(source);
+                if unicase::eq(flag, "Path") {
+                    base = args.get(i + 1);
+                } else if unicase::eq(flag, "ChildPath") || unicase::eq(flag, "AdditionalChildPath") {
+                    children.extend(args.get(i + 1));
+                } else {
+                    // A switch, eg. `-Resolve`
+                    i += 1;
+                    continue;
+                }
+                i += 2;
+            }
+            TT::Symbol { symbol: ',', .. } => i += 1,
+            ref tt => {
+                positional.push(tt);
+                i += 1;
+            }
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let base = base.or_else(|| positional.next())?;
+    children.extend(positional);
+
+    let mut path = match *base {
+        TT::Group { ref interior, delimiter: v2::Delimiter::Parenthesis, .. } if is_script_dir(interior, source) => {
+            String::new()
+        }
+        TT::Group { ref interior, delimiter: v2::Delimiter::Parenthesis, .. } => join_path(interior, source)?,
+        ref base if is_script_dir(::std::slice::from_ref(base), source) => String::new(),
+        _ => return None,
+    };
+
+    if children.is_empty() {
+        return None;
+    }
+    for child in children {
+        let (_, child) = literal_argument(child, source)?;
+        path += "/";
+        path += child;
+    }
+
+    Some(path)
+}
+
+/// Whether the tokens evaluate to the script's directory,
+/// ie. `$PSScriptRoot` or `Split-Path [-Parent] $PSCommandPath`
+fn is_script_dir(tts: &[TT], source: &str) -> bool {
+    match tts {
+        [variable] => is_variable(variable, "PSScriptRoot", source),
+        [TT::Cmdlet { ident, .. }, args @ ..] if unicase::eq(ident.cut_from(source), "Split-Path") => {
+            let mut args = args.iter().filter(|tt| match **tt {
+                TT::Flag { ident, .. } => !unicase::eq(ident.cut_from(source), "Parent")
+                    && !unicase::eq(ident.cut_from(source), "Path"),
+                _ => true,
+            });
+            matches!((args.next(), args.next()), (Some(variable), None) if is_variable(variable, "PSCommandPath", source))
+        }
+        _ => false,
+    }
+}
+
+/// Whether the token is an unqualified variable with a given name
+fn is_variable(tt: &TT, name: &str, source: &str) -> bool {
+    match *tt {
+        TT::Variable { ident, qualifier: None, .. } => unicase::eq(ident.cut_from(source), name),
+        _ => false,
+    }
+}
+
+/// Gathers accesses of environment variables
+fn gather_env_variables(stream: &[TT], source: &str) -> Vec<EnvVariable> {
+    let is_env = |qualifier: FileStr| unicase::eq(qualifier.cut_from(source), "env");
+
+    // Starts of variables used in conditions
+    let mut in_conditions = Set::new();
+    let mut env_variables = Vec::new();
+
+    v2::traverse_streams(stream, |stream, _| {
+        for (i, tt) in stream.iter().enumerate() {
+            let cmdlet = match *tt {
+                TT::Cmdlet { ident, .. } => ident.cut_from(source),
+                _ => continue,
+            };
+
+            let is_condition = ["if", "elseif", "while"].iter().any(|keyword| unicase::eq(cmdlet, keyword));
+
+            match stream.get(i + 1) {
+                Some(TT::Group { interior, delimiter: v2::Delimiter::Parenthesis, .. }) if is_condition => {
+                    v2::traverse_streams(interior, |stream, _| {
+                        for tt in stream {
+                            if let TT::Variable { span, qualifier: Some(qualifier), .. } = *tt {
+                                if is_env(qualifier) {
+                                    in_conditions.insert(span.start.byte);
+                                }
+                            }
+                        }
+                    });
+                }
+                _ if unicase::eq(cmdlet, "Test-Path") => {
+                    let args = match stream[i + 1..] {
+                        [TT::Flag { ident, .. }, ref rest @ ..] if unicase::eq(ident.cut_from(source), "Path") => rest,
+                        ref args => args,
+                    };
+                    let path = args.first().and_then(|arg| literal_argument(arg, source));
+                    if let Some((span, path)) = path {
+                        if path.len() > 4 && unicase::eq(&path[..4], "env:") {
+                            let name = path[4..].trim_start_matches(&['\\', '/'] as &[char]);
+                            env_variables.push(EnvVariable { span, name: name.to_owned(), access: EnvAccess::Check });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    v2::traverse_streams(stream, |stream, _| {
+        for (i, tt) in stream.iter().enumerate() {
+            let (span, ident) = match *tt {
+                TT::Variable { span, ident, qualifier: Some(qualifier) } if is_env(qualifier) => (span, ident),
+                _ => continue,
+            };
+
+            let access = if in_conditions.contains(&span.start.byte) {
+                EnvAccess::Check
+            } else if let Some(TT::Symbol { symbol: '=', .. }) = stream.get(i + 1) {
+                EnvAccess::Write
+            } else {
+                EnvAccess::Read
+            };
+
+            env_variables.push(EnvVariable { span, name: ident.cut_from(source).to_owned(), access });
+        }
+    });
+
+    env_variables.sort_by_key(|variable| variable.span.start.byte);
+    env_variables
+}
+
+/// Finds the class name instantiated by `New-Object`
+fn parse_new_object(args: &[TT], source: &str) -> Option<Usage> {
+    let mut i = 0;
+
+    let type_name = loop {
+        match *args.get(i)? {
+            TT::Flag { ident, .. } => {
+                let flag = ident.cut_from(source);
+                if unicase::eq(flag, "TypeName") {
+                    break args.get(i + 1)?;
+                } else if unicase::eq(flag, "ComObject") {
+                    return None;
+                } else if unicase::eq(flag, "Strict") {
+                    i += 1;
+                } else {
+                    // Skip the flag's value, possibly a comma-separated list
+                    i += 2;
+                    while let Some(TT::Symbol { symbol: ',', .. }) = args.get(i) {
+                        i += 2;
+                    }
+                }
+            }
+            ref tt => break tt,
+        }
+    };
+
+    match *type_name {
+        TT::Word { span } => {
+            let name = FileStr::from(span).cut_from(source).to_owned();
+            Some(Usage { span, item: Item::class(name) })
+        }
+        _ => None,
+    }
+}
+
+/// Finds `-TestCases` among arguments of `It`
+fn parse_test_cases(args: &[TT], source: &str) -> Option<TestCases> {
+    let args = args.split(|tt| matches!(tt, TT::Symbol { symbol: ';', .. })).next()?;
+
+    let position = args.iter().position(|tt| match *tt {
+        TT::Flag { ident, .. } => {
+            let flag = ident.cut_from(source);
+            unicase::eq(flag, "TestCases") || unicase::eq(flag, "ForEach")
+        }
+        _ => false,
+    })?;
+
+    let cases = match args.get(position + 1) {
+        Some(TT::Group { interior, delimiter: v2::Delimiter::Parenthesis, prefix: Some('@'), .. }) => interior,
+        _ => return Some(TestCases::Dynamic),
+    };
+
+    let mut common_keys: Option<Set<String>> = None;
+
+    for case in cases.iter() {
+        let hashtable = match case {
+            TT::Group { interior, delimiter: v2::Delimiter::Brace, prefix: Some('@'), .. } => interior,
+            // Commas and newlines
+            TT::Symbol { .. } => continue,
+            _ => return Some(TestCases::Dynamic),
+        };
+
+        let keys: Set<String> = hashtable
+            .split(|tt| matches!(tt, TT::Symbol { symbol: ';', .. }))
+            .filter_map(|statement| match *statement {
+                [TT::Field { ident, .. }, TT::Symbol { symbol: '=', .. }, ..] => {
+                    Some(ident.cut_from(source).to_lowercase())
+                }
+                [TT::String { span, .. }, TT::Symbol { symbol: '=', .. }, ..] => {
+                    let key = FileStr::from(span).cut_from(source).trim_matches(&['"', '\''] as &[char]);
+                    Some(key.to_lowercase())
+                }
+                _ => None,
+            })
+            .collect();
+
+        common_keys = Some(match common_keys {
+            Some(common) => common.intersection(&keys).cloned().collect(),
+            None => keys,
+        });
+    }
+
+    Some(TestCases::Literal(common_keys.unwrap_or_default()))
+}
+
+/// Parses arguments of `Import-Module`.
+///
+/// Recognizes only modules given by a plain name.
+fn parse_import_module(args: &[TT], source: &str) -> Option<Import> {
+    let args = match args {
+        [TT::Flag { ident, .. }, rest @ ..] if unicase::eq(ident.cut_from(source), "Name") => rest,
+        _ => args,
+    };
+
+    let (span, name) = literal_argument(args.first()?, source)?;
+
+    let is_path = name.contains(&['/', '\\'] as &[char])
+        || ["psm1", "psd1", "ps1", "dll"].iter().any(|ext| name.to_lowercase().ends_with(&format!(".{}", ext)));
+
+    if is_path {
+        return None;
+    }
+
+    Some(Import { span, importee: Importee::Module(name.to_owned()) })
+}
+
+/// Parameters of `Mock` taking a value
+const MOCK_VALUE_PARAMETERS: &[&str] = &[
+    "ModuleName",
+    "MockWith",
+    "ParameterFilter",
+    "RemoveParameterType",
+    "RemoveParameterValidation",
+];
+
+/// Finds the name of the function mocked by `Mock`, given either
+/// as `-CommandName` or as the first positional argument
+fn parse_mock<'a>(args: &[TT], source: &'a str) -> Option<(Span, &'a str)> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            TT::Flag { ident, .. } if unicase::eq(ident.cut_from(source), "CommandName") => {
+                return literal_argument(args.next()?, source);
+            }
+            TT::Flag { ident, .. } => {
+                let flag = ident.cut_from(source);
+                if MOCK_VALUE_PARAMETERS.iter().any(|parameter| unicase::eq(flag, *parameter)) {
+                    args.next();
+                }
+            }
+            TT::Symbol { symbol: ';', .. } | TT::Symbol { symbol: '|', .. } => return None,
+            ref arg => if let Some(name) = literal_argument(arg, source) {
+                return Some(name);
+            }
+        }
+    }
+    None
+}
+
+/// Finds names given to `-Function` and `-Alias` of `Export-ModuleMember`.
+///
+/// Names which can't be determined statically, eg. from
+/// a variable, are returned as `*` wildcard.
+fn parse_export_module_member(args: &[TT], source: &str) -> Vec<String> {
+    fn gather(tt: &TT, source: &str, names: &mut Vec<String>) {
+        match *tt {
+            // Commas are glued to words, as in `Get-Foo,Get-Bar`
+            TT::Word { span } => {
+                let words = FileStr::from(span).cut_from(source).split(',');
+                names.extend(words.filter(|word| !word.is_empty()).map(str::to_owned));
+            }
+            TT::Group { ref interior, delimiter: v2::Delimiter::Parenthesis, .. } => {
+                for tt in interior.iter() {
+                    gather(tt, source, names);
+                }
+            }
+            TT::Symbol { symbol: ',', .. } => {}
+            ref tt => match literal_argument(tt, source) {
+                Some((_, name)) => names.push(name.to_owned()),
+                None if !names.iter().any(|name| name == "*") => names.push("*".to_owned()),
+                None => {}
+            }
+        }
+    }
+
+    let args = args.split(|tt| matches!(tt, TT::Symbol { symbol: ';', .. })).next().unwrap_or_default();
+
+    // Positional arguments are bound to `-Function`
+    let mut exports_functions = true;
+    let mut names = Vec::new();
+    for tt in args {
+        match *tt {
+            TT::Flag { ident, .. } => {
+                let flag = ident.cut_from(source);
+                exports_functions = unicase::eq(flag, "Function") || unicase::eq(flag, "Alias");
+            }
+            ref tt if exports_functions => gather(tt, source, &mut names),
+            _ => {}
+        }
+    }
+
+    names
+}
+
+/// Finds the alias name and its target among arguments of `Set-Alias`/`New-Alias`.
+///
+/// An alias is represented as a definition of a function calling its target.
+fn parse_alias(args: &[TT], source: &str) -> Option<(Definition, Usage)> {
+    let args = args.split(|tt| matches!(tt, TT::Symbol { symbol: ';', .. })).next()?;
+
+    let mut name = None;
+    let mut value = None;
+    let mut positional = Vec::new();
+
+    let mut i = 0;
+    while let Some(tt) = args.get(i) {
+        match *tt {
+            TT::Flag { ident, .. } => {
+                let flag = ident.cut_from(source);
+                if unicase::eq(flag, "Name") {
+                    name = args.get(i + 1);
+                } else if unicase::eq(flag, "Value") {
+                    value = args.get(i + 1);
+                } else if !["Scope", "Option", "Description"].iter().any(|param| unicase::eq(flag, param)) {
+                    // A switch
+                    i += 1;
+                    continue;
+                }
+                i += 2;
+            }
+            ref tt => {
+                positional.push(tt);
+                i += 1;
+            }
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let name = name.or_else(|| positional.next())?;
+    let value = value.or_else(|| positional.next())?;
+
+    let (span, name) = literal_argument(name, source)?;
+    let (target_span, target) = literal_argument(value, source)?;
+
+    let target_item = if is_executable_name(target) {
+        Item::executable(target.to_owned())
+    } else {
+        Item::function(target.to_owned())
+    };
+
+    Some((
+        Definition { span, item: Item::function(name.to_owned()), visible_in: None },
+        Usage { span: target_span, item: target_item },
+    ))
+}
+
+/// Whether a command name is a name of a program, eg. `git.exe`
+fn is_executable_name(name: &str) -> bool {
+    name.len() > 4 && name.get(name.len() - 4..).is_some_and(|extension| extension.eq_ignore_ascii_case(".exe"))
+}
+
+/// Finds a function defined by assigning to the `function:` drive,
+/// eg. `$function:Invoke-Thing = {}` or `${function:Invoke-Thing} = {}`
+fn parse_function_drive_assignment(stream: &[TT], source: &str) -> Option<Definition> {
+    match *stream {
+        [TT::Variable { span, ident, qualifier: Some(qualifier) }, TT::Symbol { symbol: '=', .. }, ..]
+        if unicase::eq(qualifier.cut_from(source), "function") => {
+            let name = ident.cut_from(source).to_owned();
+            Some(Definition { span, item: Item::function(name), visible_in: None })
+        }
+        _ => None,
+    }
+}
+
+/// Finds a function defined by `Set-Item function:Invoke-Thing {}`
+fn parse_function_drive_item(args: &[TT], source: &str) -> Option<Definition> {
+    let args = match args {
+        [TT::Flag { ident, .. }, rest @ ..] if unicase::eq(ident.cut_from(source), "Path") => rest,
+        _ => args,
+    };
+
+    let (span, path) = literal_argument(args.first()?, source)?;
+
+    let (drive, name) = path.split_at(path.find(':')?);
+    if !unicase::eq(drive, "function") {
+        return None;
+    }
+
+    // Skip the colon and a scope modifier, eg. `function:global:Foo`
+    let name = name.rsplit(':').next()?;
+
+    Some(Definition { span, item: Item::function(name.to_owned()), visible_in: None })
+}
+
+/// Finds modules in `#Requires -Modules` comments, given by name,
+/// eg. `#Requires -Modules Az.Accounts, 'Pester'`, or by a hashtable,
+/// eg. `#Requires -Modules @{ ModuleName = 'Az'; ModuleVersion = '9.0' }`
+fn parse_required_modules(comments: &[Span], source: &str) -> Vec<String> {
+    lazy_static! {
+        static ref REQUIRES_MODULES: Regex = Regex::new(
+            r"(?ix) ^ \# requires \b .*? -modules \s+ ( (?: @\{ [^}]* \} | [^\s,]+ ) (?: \s* , \s* (?: @\{ [^}]* \} | [^\s,]+ ) )* )"
+        ).unwrap();
+
+        static ref MODULE: Regex = Regex::new(
+            r"(?ix) @\{ [^}]* \b modulename \s* = \s* ['\x22]? ( [^'\x22;}\s]+ ) [^}]* \} | ( [^\s,@]+ )"
+        ).unwrap();
+    }
+
+    let mut modules = Vec::new();
+    for comment in comments {
+        let text = FileStr::from(*comment).cut_from(source);
+        let captures = match REQUIRES_MODULES.captures(text) {
+            Some(captures) => captures,
+            None => continue,
+        };
+
+        for module in MODULE.captures_iter(&captures[1]) {
+            if let Some(name) = module.get(1).or_else(|| module.get(2)) {
+                modules.push(name.as_str().trim_matches(&['"', '\''] as &[char]).to_owned());
+            }
+        }
+    }
+    modules
+}
+
+/// Finds the version in `#Requires -Version 7.2`
+fn parse_required_version(comments: &[Span], source: &str) -> Option<RequiredVersion> {
+    lazy_static! {
+        static ref REQUIRES_VERSION: Regex = Regex::new(
+            r"(?ix) ^ \# requires \b .*? -version \s+ ( [0-9]+ (?: \. [0-9]+ )* )"
+        ).unwrap();
+    }
+
+    comments.iter().find_map(|comment| {
+        let text = FileStr::from(*comment).cut_from(source);
+        let version = REQUIRES_VERSION.captures(text)?.get(1)?.as_str();
+        Some(RequiredVersion {
+            span: Span::from_fragment(comment.start.line, version, source),
+            version: version.to_owned(),
+        })
     })
 }
 
+/// Gets a bare word or a string without interpolation
+fn literal_argument<'a>(tt: &TT, source: &'a str) -> Option<(Span, &'a str)> {
+    match *tt {
+        TT::Word { span } => Some((span, FileStr::from(span).cut_from(source))),
+        TT::String { span, ref subtrees } if subtrees.is_empty() => {
+            Some((span, FileStr::from(span).cut_from(source).trim_matches(&['"', '\''] as &[char])))
+        }
+        _ => None,
+    }
+}
+
 #[test]
 fn test_basics() {
     let source = r#"
@@ -270,7 +1097,7 @@ fn test_basics() {
         [Boat] $Foo = 5
     "#;
 
-    let parsed = parse(source, false).unwrap();
+    let parsed = parse(source, false);
 
     assert_eq!(parsed.imports[0].importee, Importee::HereSut);
     assert_eq!(parsed.imports[1].importee, Importee::HereSut);
@@ -290,10 +1117,160 @@ fn test_basics() {
     assert_eq!(parsed.usages[5].item.as_ref(), Item::class("Boat"));
 
     assert_eq!(parsed.testcases[0].name, "works");
+    assert_eq!(parsed.describes[0].name, "something");
+}
+
+#[test]
+fn test_class_usages() {
+    let source = r#"
+        $A = New-Object Car
+        $B = New-Object -ArgumentList 1, 2 -TypeName Boat
+        $C = New-Object -ComObject Excel.Application
+        $D = [Plane]::new()
+        [Train]::Create()
+    "#;
+
+    let parsed = parse(source, false);
+
+    let classes: Vec<_> = parsed.usages
+        .iter()
+        .filter(|usage| usage.item.is_class())
+        .map(|usage| &*usage.item.name)
+        .collect();
+
+    assert_eq!(classes, ["Car", "Boat", "Plane", "Train"]);
+}
+
+#[test]
+fn test_test_cases() {
+    let source = r#"
+        Describe "templates" {
+            It "handles <Value>" -TestCases @(
+                @{ Value = 1; Other = 2 }
+                @{ 'value' = 3 }
+            ) {}
+            It "handles <Value>" -TestCases $Cases {}
+            It "works" {}
+        }
+    "#;
+
+    let parsed = parse(source, false);
+    let test_cases: Vec<_> = parsed.testcases.iter().map(|testcase| testcase.test_cases.as_ref()).collect();
+
+    assert_eq!(test_cases, [
+        Some(&TestCases::Literal(vec!["value".to_owned()].into_iter().collect())),
+        Some(&TestCases::Dynamic),
+        None,
+    ]);
+}
+
+#[test]
+fn test_import_module() {
+    let source = r#"
+        Import-Module Utils
+        Import-Module -Name "Az.Accounts"
+        Import-Module $PSScriptRoot/Foo.psm1
+        Import-Module -Name "$PSScriptRoot\Lib\Baz.psm1" -Force
+        Import-Module ./Bar.psd1
+    "#;
+
+    let parsed = parse(source, false);
+
+    let importees: Vec<_> = parsed.imports.into_iter().map(|import| import.importee).collect();
+    assert_eq!(importees, [
+        Importee::ModulePath("Foo.psm1".into()),
+        Importee::ModulePath("Lib/Baz.psm1".into()),
+        Importee::Module("Utils".into()),
+        Importee::Module("Az.Accounts".into()),
+    ]);
+}
+
+#[test]
+fn test_using_module() {
+    let source = concat!(
+        "using module .\\Classes.psm1\n",
+        "using module Utils\n",
+        "enum Color {\n",
+        "    Red\n",
+        "}\n",
+    );
+
+    let parsed = parse(source, false);
+
+    let importees: Vec<_> = parsed.imports.into_iter().map(|import| import.importee).collect();
+    assert_eq!(importees, [
+        Importee::UsingModule("./Classes.psm1".into()),
+        Importee::UsingModule("Utils".into()),
+    ]);
+
+    assert_eq!(parsed.definitions.len(), 1);
+    assert!(parsed.definitions[0].item.is_class());
+    assert!(parsed.usages.is_empty(), "{:?}", parsed.usages);
+}
+
+#[test]
+fn test_export_module_member() {
+    let source = concat!(
+        "function Get-Foo {}\n",
+        "Export-ModuleMember -Function Get-Foo, 'Get-Bar' -Variable Config -Alias gf\n",
+        "Export-ModuleMember @('Get-Baz', \"Set-*\")\n",
+        "Export-ModuleMember -Function $Public.BaseName\n",
+    );
+
+    let parsed = parse(source, false);
+    assert_eq!(parsed.exported_functions.unwrap(), ["Get-Foo", "Get-Bar", "gf", "Get-Baz", "Set-*", "*"]);
+
+    assert_eq!(parse("function Get-Foo {}\n", false).exported_functions, None);
+}
+
+#[test]
+fn test_env_imports() {
+    let source = concat!(
+        ". $env:BUILD_ROOT\\common.ps1\n",
+        ". \"$Env:Tools/lib/A.ps1\"\n",
+        ". \"$env:Tools\\$Name.ps1\"\n",
+    );
+
+    let parsed = parse(source, false);
+
+    let importees: Vec<_> = parsed.imports.into_iter().map(|import| import.importee).collect();
+    assert_eq!(importees, [
+        Importee::EnvRelative("BUILD_ROOT".into(), "common.ps1".into()),
+        Importee::EnvRelative("Tools".into(), "lib/A.ps1".into()),
+        Importee::Unrecognized("\"$env:Tools\\$Name.ps1\"".into()),
+    ]);
+}
+
+#[test]
+fn test_computed_imports() {
+    let source = concat!(
+        ". (Join-Path $PSScriptRoot \"lib\\Utils.ps1\")\n",
+        ". (Join-Path -Path (Split-Path -Parent $PSCommandPath) -ChildPath lib\\A.ps1)\n",
+        ". (Join-Path (Join-Path $PSScriptRoot lib) B.ps1)\n",
+        ". \"$(Split-Path $PSCommandPath)\\C.ps1\"\n",
+        ". $PSCommandPath.Replace('.Tests.ps1', '.ps1')\n",
+        "function Foo {\n",
+        "    . (Join-Path $PSScriptRoot D.ps1)\n",
+        "}\n",
+        ". (Join-Path $Env:Root E.ps1)\n",
+        ". \"$(Split-Path $PSCommandPath)\\$Name.ps1\"\n",
+    );
+
+    let parsed = parse(source, false);
+
+    let importees: Vec<_> = parsed.imports.into_iter().map(|import| import.importee).collect();
+    assert_eq!(importees, [
+        Importee::Relative("lib/Utils.ps1".into()),
+        Importee::Relative("lib/A.ps1".into()),
+        Importee::Relative("lib/B.ps1".into()),
+        Importee::Relative("C.ps1".into()),
+        Importee::HereSut,
+        Importee::Relative("D.ps1".into()),
+        Importee::Unrecognized("(Join-Path $Env:Root E.ps1)".into()),
+        Importee::Unrecognized("\"$(Split-Path $PSCommandPath)\\$Name.ps1\"".into()),
+    ]);
 }
 
-// This test should stop to pass
-// when the parser will be implemented correctly.
 #[test]
 fn test_nested() {
     let source = r#"
@@ -303,7 +1280,7 @@ fn test_nested() {
         }
     "#;
 
-    let parsed = parse(source, false).unwrap();
+    let parsed = parse(source, false);
 
     let mut funs: Vec<_> = parsed.definitions
         .iter()
@@ -313,4 +1290,275 @@ fn test_nested() {
     funs.sort();
 
     assert_eq!(funs, ["Foo", "Nested"]);
+
+    let foo = &parsed.definitions[0];
+    let nested = &parsed.definitions[1];
+    assert_eq!(foo.visible_in, None);
+
+    let body = nested.visible_in.expect("nested function visible in the whole file");
+    assert!(body.contains(nested.span));
+    assert!(!body.contains(foo.span));
+}
+
+#[test]
+fn test_filters() {
+    let source = r#"
+        filter Get-Matching { if ($_ -match "foo") { $_ } }
+        Get-Items | Get-Matching
+    "#;
+
+    let parsed = parse(source, false);
+
+    assert_eq!(parsed.definitions[0].item.as_ref(), Item::function("Get-Matching"));
+    assert!(parsed.usages.iter().all(|usage| usage.name() != "filter"));
+}
+
+#[test]
+fn test_aliases() {
+    let source = r#"
+        Set-Alias Deploy Invoke-Deployment
+        New-Alias -Value Get-Stuff -Name stuff -Scope Global
+        Set-Alias -Force np notepad.exe
+        sal ship Invoke-Deployment
+    "#;
+
+    let parsed = parse(source, false);
+
+    let defined: Vec<_> = parsed.definitions.iter().map(|def| &*def.item.name).collect();
+    assert_eq!(defined, ["Deploy", "stuff", "np", "ship"]);
+
+    let functions: Vec<_> = parsed.usages
+        .iter()
+        .filter(|usage| usage.item.is_function())
+        .map(|usage| usage.name())
+        .collect();
+    assert_eq!(functions, [
+        "Set-Alias", "New-Alias", "Set-Alias", "sal", "Invoke-Deployment", "Get-Stuff", "Invoke-Deployment",
+    ]);
+}
+
+#[test]
+fn test_variable_qualifiers() {
+    let source = r#"
+        $script:Config = $global:State
+        Write-Host "$env:PATH and ${Env:Program Files(x86)}" $Local
+    "#;
+
+    let (tts, errors) = v2::parse(source, false);
+    assert!(errors.is_empty());
+
+    let mut variables = Vec::new();
+    v2::traverse_streams(&tts, |stream, _| {
+        for tt in stream {
+            if let TT::Variable { ident, qualifier, .. } = *tt {
+                variables.push((qualifier.map(|qualifier| qualifier.cut_from(source)), ident.cut_from(source)));
+            }
+        }
+    });
+
+    assert_eq!(variables, [
+        (Some("script"), "Config"),
+        (Some("global"), "State"),
+        (None, "Local"),
+        (Some("env"), "PATH"),
+        (Some("Env"), "Program Files(x86)"),
+    ]);
+}
+
+#[test]
+fn test_env_variables() {
+    let source = r#"
+        if (-not $env:BUILD_ID) { throw "no build" }
+        $env:Mode = "release"
+        if (Test-Path env:\ARTIFACTS_DIR) {}
+        Write-Host "Building $env:BUILD_ID in $env:WORKSPACE"
+    "#;
+
+    let parsed = parse(source, false);
+
+    let accesses: Vec<_> = parsed.env_variables
+        .iter()
+        .map(|variable| (&*variable.name, variable.access))
+        .collect();
+
+    assert_eq!(accesses, [
+        ("BUILD_ID", EnvAccess::Check),
+        ("Mode", EnvAccess::Write),
+        ("ARTIFACTS_DIR", EnvAccess::Check),
+        ("BUILD_ID", EnvAccess::Read),
+        ("WORKSPACE", EnvAccess::Read),
+    ]);
+}
+
+#[test]
+fn test_required_modules() {
+    let source = r#"
+        #Requires -Version 5.1 -Modules Az.Accounts, 'Pester'
+        #requires -modules @{ ModuleName = "ActiveDirectory"; ModuleVersion = "1.0" }
+        # Requires -Modules NotARequirement
+        Get-Foo
+    "#;
+
+    let parsed = parse(source, false);
+    assert_eq!(parsed.required_modules, ["Az.Accounts", "Pester", "ActiveDirectory"]);
+
+    let version = parsed.required_version.unwrap();
+    assert_eq!(version.version, "5.1");
+    assert_eq!(version.span.start.line, 2);
+    assert_eq!(&source[version.span.start.byte as usize..version.span.end.byte as usize], "5.1");
+}
+
+#[test]
+fn test_function_drive() {
+    let source = r#"
+        $function:Invoke-Thing = { Get-Thing }
+        ${function:Invoke-Braced} = {}
+        Set-Item function:Invoke-Other { 1 }
+        New-Item -Path function:global:Invoke-Global -Value {}
+        $Copy = $function:Invoke-Thing
+    "#;
+
+    let parsed = parse(source, false);
+
+    let defined: Vec<_> = parsed.definitions.iter().map(|def| &*def.item.name).collect();
+    assert_eq!(defined, ["Invoke-Thing", "Invoke-Braced", "Invoke-Other", "Invoke-Global"]);
+}
+
+#[test]
+fn test_pester_blocks() {
+    let source = r#"
+        BeforeAll {
+            function Get-Global {}
+        }
+
+        Describe "things" {
+            BeforeAll {
+                function Get-Shared {}
+            }
+
+            It "works" {
+                function Get-Local {}
+            }
+        }
+    "#;
+
+    let parsed = parse(source, false);
+
+    let visible_in = |name: &str| {
+        parsed.definitions
+            .iter()
+            .find(|def| def.item.name == name)
+            .unwrap()
+            .visible_in
+            .map(|span| span.start.line)
+    };
+
+    assert_eq!(visible_in("Get-Global"), None);
+    assert_eq!(visible_in("Get-Shared"), Some(6));
+    assert_eq!(visible_in("Get-Local"), Some(11));
+}
+
+#[test]
+fn test_mocks() {
+    let source = r#"
+        Mock Get-Global { 1 }
+
+        Describe "things" {
+            BeforeAll {
+                Mock -ModuleName Tools Get-Shared -MockWith { 2 }
+                Mock -CommandName "Get-Named" -Verifiable
+            }
+
+            It "works" {
+                Mock -ParameterFilter { $Name -eq 'x' } Get-Local
+            }
+        }
+    "#;
+
+    let parsed = parse(source, false);
+
+    let mocks: Vec<_> = parsed.mocks
+        .iter()
+        .map(|mock| (&*mock.name, mock.visible_in.map(|span| span.start.line)))
+        .collect();
+    assert_eq!(mocks, [
+        ("Get-Global", None),
+        ("Get-Shared", Some(4)),
+        ("Get-Named", Some(4)),
+        ("Get-Local", Some(10)),
+    ]);
+}
+
+#[test]
+fn test_named_blocks() {
+    let source = r#"
+        function Get-Items {
+            [CmdletBinding()]
+            param([string] $Path)
+            begin { Open-Items } process { Read-Item $_ } end {
+                Close-Items
+            }
+        }
+    "#;
+
+    let (tts, errors) = v2::parse(source, false);
+    assert!(errors.is_empty());
+
+    let body = match tts[..] {
+        [_, TT::FunctionKeyword { .. }, TT::Cmdlet { .. }, TT::Group { ref interior, .. }, ..] => interior,
+        _ => panic!("function not parsed"),
+    };
+    let named_blocks = body.iter().filter(|tt| matches!(tt, TT::NamedBlock { .. })).count();
+    assert_eq!(named_blocks, 3);
+
+    let parsed = parse(source, false);
+    let functions: Vec<_> = parsed.usages
+        .iter()
+        .filter(|usage| usage.item.is_function())
+        .map(|usage| usage.name())
+        .collect();
+    assert_eq!(functions, ["Open-Items", "Read-Item", "Close-Items"]);
+}
+
+#[test]
+fn test_functions_in_named_blocks() {
+    let source = r#"
+        function Get-Items {
+            begin { function Format-Item { $args } }
+            process { function Test-Item {} Format-Item $_ }
+        }
+    "#;
+
+    let parsed = parse(source, false);
+
+    let outer = &parsed.definitions[0];
+    assert_eq!(outer.item.name, "Get-Items");
+    assert_eq!(outer.visible_in, None);
+
+    let body = parsed.definitions[1].visible_in.expect("function in begin block visible in the whole file");
+    assert!(!body.contains(outer.span));
+    assert!(body.start.byte < source.find("begin").unwrap() as u32);
+
+    let nested = &parsed.definitions[2];
+    assert_eq!(nested.item.name, "Test-Item");
+    assert_eq!(nested.visible_in, Some(body));
+}
+
+#[test]
+fn test_recovery() {
+    let source = r#"
+        function Foo {
+            Write-Host ( ]
+        }
+
+        function Bar { Get-Bar }
+    "#;
+
+    let parsed = parse(source, false);
+
+    assert_eq!(parsed.errors.len(), 1);
+
+    let defined: Vec<_> = parsed.definitions.iter().map(|def| &*def.item.name).collect();
+    assert_eq!(defined, ["Foo", "Bar"]);
+    assert!(parsed.usages.iter().any(|usage| usage.name() == "Get-Bar"));
 }
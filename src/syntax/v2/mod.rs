@@ -29,15 +29,6 @@ pub use self::stage2::FileStr;
 
 mod stream;
 
-impl Location {
-    fn error<T>(self, msg: impl Into<String>) -> Result<T> {
-        Err(Error {
-            what:   msg.into(),
-            where_: self,
-        })
-    }
-}
-
 #[derive(Debug)]
 pub struct Error {
     pub what: String,
@@ -45,32 +36,41 @@ pub struct Error {
 }
 pub type Result<T> = ::std::result::Result<T, Error>;
 
-pub fn parse(source: &str, debug: bool) -> Result<stage2::TokenStream> {
+/// Parses a source file, recovering from syntax errors.
+///
+/// Returns token trees along with all encountered errors.
+pub fn parse(source: &str, debug: bool) -> (stage2::TokenStream, Vec<Error>) {
+    let (tts, errors, _) = parse_with_comments(source, debug);
+    (tts, errors)
+}
+
+/// Like `parse`, but also returns spans of comments.
+pub fn parse_with_comments(source: &str, debug: bool) -> (stage2::TokenStream, Vec<Error>, Vec<Span>) {
 
     if debug { print!("Stage1... "); }
 
-    match stage1::parse(&source) {
-        Err(e)   => {
-            if debug { println!("[failed]"); }
-            Err(e)
-        }
-        Ok(tts1) => {
-            if debug { println!("[OK] ({} tts)", tts1.len()); }
-            if debug { print!("Stage2... "); }
+    let (tts1, mut errors, comments) = stage1::parse_with_comments(source);
 
-            match stage2::TT::from_stage1(tts1, &source) {
-                Err(e)   => {
-                    if debug { println!("[failed] ({:?})", e); }
-                    Err(e)
-                }
-                Ok(tts2) => {
-                    if debug { println!("[OK] ({} tts)", tts2.len()); }
-                    if debug { stage2::pretty::color_print(&source, &tts2); }
-                    Ok(tts2)
-                }
-            }
-        }
+    if debug { println!("[{}] ({} tts)", status(&errors), tts1.len()); }
+    if debug { print!("Stage2... "); }
+
+    let stage1_errors = errors.len();
+    let tts2 = stage2::TT::from_stage1(tts1, source, &mut errors);
+
+    if debug { println!("[{}] ({} tts)", status(&errors[stage1_errors..]), tts2.len()); }
+    if debug {
+        for e in &errors { println!("{:?}", e); }
+        #[cfg(feature = "pretty")]
+        stage2::pretty::color_print(source, &tts2);
+        #[cfg(not(feature = "pretty"))]
+        println!("{:#?}", tts2);
     }
+
+    (tts2, errors, comments)
+}
+
+fn status(errors: &[Error]) -> &'static str {
+    if errors.is_empty() { "OK" } else { "recovered" }
 }
 
 pub fn ident_is_keyword(ident: &str) -> bool {
@@ -93,8 +93,10 @@ pub fn ident_is_keyword(ident: &str) -> bool {
         | "break"
         | "return"
         | "function"
+        | "filter"
         | "in"
-        | "param" => true,
+        | "param"
+        | "using" => true,
         | _       => false
     }
 }
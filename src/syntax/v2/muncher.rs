@@ -49,12 +49,13 @@ impl Location {
     }
 }
 
-fn find_line<'source>(byte_offset: usize, source: &'source str) -> &'source str {
-    let mut start = byte_offset as usize;
+fn find_line(byte_offset: usize, source: &str) -> &str {
+    let mut start = byte_offset;
     loop {
         if start == 0 {
             break;
-        } if source.as_bytes().get(start) == Some(&b'\n') {
+        }
+        if source.as_bytes().get(start) == Some(&b'\n') {
             start += 1;
             break;
         }
@@ -97,7 +98,8 @@ impl<'source> Muncher<'source> {
                 new_location.col = 1;
             }
             _    => {
-                new_location.col += 1;
+                // Saturating, as minified files may have absurdly long lines
+                new_location.col = new_location.col.saturating_add(1);
             }
         };
 
@@ -143,6 +145,11 @@ impl Span {
         }
     }
 
+    /// Checks whether the other span lies within this one
+    pub fn contains(self, other: Span) -> bool {
+        self.start.byte <= other.start.byte && other.end.byte <= self.end.byte
+    }
+
     /// Creates a span of a fragment `&str` in a `whole`
     ///
     /// The fragment has to be a subslice of the whole.
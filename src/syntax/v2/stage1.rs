@@ -1,10 +1,34 @@
 use syntax::v2::{Span, Location};
 use syntax::v2::Muncher;
+use syntax::v2::Error;
+#[cfg(test)]
 use syntax::v2::Result;
 use syntax::v2::stream::Dummy;
 
 /// Parses a source file into list of token trees, stripping comments.
+///
+/// Fails on the first syntax error.
+#[cfg(test)]
 pub fn parse(source: &str) -> Result<TokenStream> {
+    let (tts, errors) = parse_recovering(source);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None        => Ok(tts),
+    }
+}
+
+/// Parses a source file into list of token trees, stripping comments.
+///
+/// Recovers from syntax errors (eg. by closing unclosed groups
+/// or skipping stray delimiters) and returns all of them.
+#[cfg(test)]
+pub fn parse_recovering(source: &str) -> (TokenStream, Vec<Error>) {
+    let (tts, errors, _) = Parser::parse(source);
+    (tts, errors)
+}
+
+/// Like `parse_recovering`, but also returns spans of the stripped comments.
+pub fn parse_with_comments(source: &str) -> (TokenStream, Vec<Error>, Vec<Span>) {
     Parser::parse(source)
 }
 
@@ -100,58 +124,79 @@ impl Dummy for TokenTree {
 
 struct Parser<'source> {
     muncher: Muncher<'source>,
+
+    /// Delimiters of currently open groups
+    open_groups: Vec<Delimiter>,
+
+    errors: Vec<Error>,
+
+    /// Spans of skipped comments
+    comments: Vec<Span>,
 }
 
 impl<'syntax> Parser<'syntax> {
-    fn parse(source: &str) -> Result<TokenStream> {
+    fn parse(source: &str) -> (TokenStream, Vec<Error>, Vec<Span>) {
         let mut parser = Parser {
-            muncher: Muncher::new(source)
+            muncher: Muncher::new(source),
+            open_groups: Vec::new(),
+            errors: Vec::new(),
+            comments: Vec::new(),
         };
 
-        let tts = parser.parse_tts()?;
+        let mut tts = Vec::new();
+
+        loop {
+            tts.extend(parser.parse_tts());
 
-        match parser.consume_char() {
-            None                      => Ok(tts),
-            Some((delimiter, sp_bad)) => {
-                sp_bad.start.error(format!("Unexpected closing `{}`", delimiter))
+            match parser.consume_char() {
+                None                      => break,
+                Some((delimiter, sp_bad)) => {
+                    // Skip the stray delimiter and carry on
+                    parser.report(sp_bad.start, format!("Unexpected closing `{}`", delimiter));
+                }
             }
         }
+
+        compute_spacing(&mut tts);
+
+        (tts.into_boxed_slice(), parser.errors, parser.comments)
+    }
+
+    fn report(&mut self, where_: Location, what: impl Into<String>) {
+        self.errors.push(Error { what: what.into(), where_ });
     }
 
     /// Parses all it can up to the nearest closing delimiter
     /// or the end of file.
-    fn parse_tts(&mut self) -> Result<TokenStream> {
+    fn parse_tts(&mut self) -> Vec<TokenTree> {
         let mut tts = Vec::new();
 
-        while let Some(tt) = self.parse_tt()? {
+        while let Some(tt) = self.parse_tt() {
             tts.push(tt);
         }
 
         compute_spacing(&mut tts);
 
-        Ok(tts.into_boxed_slice())
+        tts
     }
 
     // Note: this function doesn't handle the "spacing"
     // parameter of words and symbols. It should be handled
     // in postprocessing in parse_tts.
-    fn parse_tt(&mut self) -> Result<Option<TokenTree>> {
+    fn parse_tt(&mut self) -> Option<TokenTree> {
         loop {
-            let c = match self.peek_char() {
-                Some(c) => c,
-                None    => return Ok(None),
-            };
+            let c = self.peek_char()?;
 
             let tt = match c {
-                '(' | '{' | '['        => self.parse_group()?,
-                ')' | '}' | ']'        => return Ok(None),
+                '(' | '{' | '['        => self.parse_group(),
+                ')' | '}' | ']'        => return None,
                 '\n'                   => self.parse_symbol(),
                 '#'                    => { self.skip_to_newline(); continue }
-                '@'                    => self.parse_at()?,
-                '\'' | '\"'            => self.parse_string(None)?,
+                '@'                    => self.parse_at(),
+                '\'' | '\"'            => self.parse_string(None),
                 '<'                    => {
                     if self.muncher.peek_2nd_char() == Some('#') {
-                        self.skip_long_comment()?;
+                        self.skip_long_comment();
                         continue;
                     } else {
                         self.parse_symbol()
@@ -163,7 +208,7 @@ impl<'syntax> Parser<'syntax> {
                 _                      => self.parse_symbol(),
             };
 
-            return Ok(Some(tt))
+            return Some(tt)
         }
     }
 
@@ -201,69 +246,98 @@ impl<'syntax> Parser<'syntax> {
     }
 
     // Assuming first char is correct.
-    fn parse_group(&mut self) -> Result<TokenTree> {
+    fn parse_group(&mut self) -> TokenTree {
         let (opening, sp_start) = self.consume_char().unwrap();
         let delimiter = Delimiter::from_opening(opening);
-
-        let tts = self.parse_tts()?;
-
         let expected = delimiter.closing();
 
-        match self.consume_char() {
-            Some((closing, sp_end)) if closing == expected => {
-                Ok(TT::Group {
-                    interior: tts,
-                    delimiter,
-                    span: sp_start.to(sp_end),
-                })
+        self.open_groups.push(delimiter);
+        let tts = self.parse_tts();
+        self.open_groups.pop();
+
+        let sp_end = match self.peek_char() {
+            Some(closing) if closing == expected => {
+                self.consume_char().unwrap().1
             }
-            Some((invalid, sp_bad)) => {
-                sp_bad.start
-                    .error(format!("Expected `{}`, but found `{}`", expected, invalid))
+            Some(invalid) => {
+                let sp_bad = self.current_location();
+                self.report(sp_bad, format!("Expected `{}`, but found `{}`", expected, invalid));
+
+                let closes_outer_group = self.open_groups
+                    .iter()
+                    .any(|outer| outer.closing() == invalid);
+
+                if closes_outer_group {
+                    // Treat the current group as closed, so the
+                    // invalid delimiter can close an outer one.
+                    sp_bad.to_span()
+                } else {
+                    // Probably a typo, let it close the current group.
+                    self.consume_char().unwrap().1
+                }
             }
             None => {
-                self.muncher.current_location()
-                    .error(format!("Expected `{}`, but found end of file", expected))
+                let eof = self.current_location();
+                self.report(eof, format!("Expected `{}`, but found end of file", expected));
+                eof.to_span()
             }
+        };
+
+        TT::Group {
+            interior: tts.into_boxed_slice(),
+            delimiter,
+            span: sp_start.to(sp_end),
         }
     }
 
     fn skip_to_newline(&mut self) {
-        while self.peek_char() != Some('\n') {
+        let start = self.current_location();
+
+        while let Some(c) = self.peek_char() {
+            if c == '\n' {
+                break;
+            }
             self.consume_char();
         }
+
+        let end = self.current_location();
+        self.comments.push(Span { start, end });
     }
 
-    fn skip_long_comment(&mut self) -> Result<()> {
+    fn skip_long_comment(&mut self) {
+        let start = self.current_location();
+
         self.consume_char();
         self.consume_char();
 
         while let Some((c, _)) = self.consume_char() {
-            if c == '#' {
-                if let Some(('>', _)) = self.consume_char() {
-                    return Ok(())
-                }
+            if c == '#' && self.peek_char() == Some('>') {
+                self.consume_char();
+                let end = self.current_location();
+                self.comments.push(Span { start, end });
+                return
             }
         }
 
-        self.current_location().error("Unclosed long comment")
+        let eof = self.current_location();
+        self.comments.push(Span { start, end: eof });
+        self.report(eof, "Unclosed long comment")
     }
 
     // Assuming it's a '@'
-    fn parse_at(&mut self) -> Result<TokenTree> {
+    fn parse_at(&mut self) -> TokenTree {
         let consumed = self.consume_char().unwrap();
         let (symbol, span) = consumed;
         assert!(symbol == '@');
 
-        let tt = match self.peek_char() {
-            Some('\"') | Some('\'') => self.parse_string(Some(consumed))?,
+        match self.peek_char() {
+            Some('\"') | Some('\'') => self.parse_string(Some(consumed)),
             _                       => TT::Symbol { symbol, span, spacing: Spacing::Alone },
-        };
-        Ok(tt)
+        }
     }
 
     // Assuming it's a string
-    fn parse_string(&mut self, preceding_symbol: Option<(char, Span)>) -> Result<TokenTree> {
+    fn parse_string(&mut self, preceding_symbol: Option<(char, Span)>) -> TokenTree {
         use self::StringQuotes::*;
         use self::StringHereness::*;
 
@@ -274,8 +348,7 @@ impl<'syntax> Parser<'syntax> {
 
         let quotes = match self.consume_char() {
             Some(('\'', _)) => Single,
-            Some(('\"', _)) => Double,
-            _          => panic!("ICE: parse_string"),
+            _          => Double,
         };
 
         let mut subtrees = Vec::new();
@@ -283,7 +356,10 @@ impl<'syntax> Parser<'syntax> {
         loop {
             let (c, c_span) = match self.consume_char() {
                 Some(consumed) => consumed,
-                None    => return start.error("Unclosed string"),
+                None    => {
+                    self.report(start, "Unclosed string");
+                    break;
+                }
             };
 
             match (c, quotes, hereness) {
@@ -296,16 +372,26 @@ impl<'syntax> Parser<'syntax> {
                     else                           { break; }
                 }
                 ('\"', Double, HereString) |
-                ('\'', Single, HereString) => {
-                    if c_span.start.col == 1 && self.peek_char() == Some('@') {
-                        self.consume_char();
-                        break;
-                    }
+                ('\'', Single, HereString)
+                if c_span.start.col == 1 && self.peek_char() == Some('@') => {
+                    self.consume_char();
+                    break;
                 }
                 ('$',  Double, _)          => {
                     match self.peek_char() {
-                        Some('(') | Some('{')        => subtrees.push(self.parse_group()?),
-                        Some(w) if can_start_word(w) => subtrees.push(self.parse_word()),
+                        Some('(') | Some('{')        => subtrees.push(self.parse_group()),
+                        Some(w) if can_start_word(w) => {
+                            subtrees.push(self.parse_word());
+
+                            // Scope or drive qualified variable, eg. `$env:PATH`
+                            let qualified = self.peek_char() == Some(':')
+                                && self.muncher.peek_2nd_char().map(can_start_word).unwrap_or(false);
+                            if qualified {
+                                let (symbol, span) = self.consume_char().unwrap();
+                                subtrees.push(TT::Symbol { symbol, span, spacing: Spacing::Alone });
+                                subtrees.push(self.parse_word());
+                            }
+                        }
                         _                            => (),
                     }
                 }
@@ -313,10 +399,12 @@ impl<'syntax> Parser<'syntax> {
             }
         }
 
-        Ok(TT::String {
+        compute_spacing(&mut subtrees);
+
+        TT::String {
             subtrees: subtrees.into_boxed_slice(),
             span: Span { start, end: self.current_location() },
-        })
+        }
     }
 
     fn consume_char(&mut self) -> Option<(char, Span)> { self.muncher.next_char() }
@@ -425,5 +513,27 @@ fn comments() {
         "foo # nieprawda\nbar" => TT::Word{..}, TT::Symbol{..}, TT::Word{..} => true
         "foo <# # > #> bar" => TT::Word{..}, TT::Word{..} => true
         "# komentarz\n" => TT::Symbol { symbol: '\n', .. } => true
+        "foo # no newline at the end" => TT::Word{..} => true
     );
 }
+
+#[test]
+fn recovery() {
+    let (tts, errors) = parse_recovering("{ ( foo } bar");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(tts.len(), 2);
+
+    let (tts, errors) = parse_recovering(") foo ] bar");
+    assert_eq!(errors.len(), 2);
+    assert_eq!(tts.len(), 2);
+
+    let (tts, errors) = parse_recovering("foo { bar \"baz");
+    assert_eq!(errors.len(), 2);
+    assert_eq!(tts.len(), 2);
+}
+
+#[test]
+fn long_lines() {
+    let line = "a".repeat(100_000);
+    assert!(parse(&line).is_ok());
+}
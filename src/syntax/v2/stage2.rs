@@ -1,11 +1,14 @@
-use syntax::v2::Span;
-use syntax::v2::Result;
+use syntax::v2::{Span, Location};
+use syntax::v2::Error;
 
 use syntax::v2::stage1::TokenTree as TT1;
-use syntax::v2::stage1::Spacing::{Alone, Joined};
+use syntax::v2::stage1::Spacing::Joined;
 
 use syntax::v2::stream::Stream;
 
+// Not all the spans are used by the analysis yet,
+// but they're useful when debugging the parser.
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum TokenTree {
     // Perhaps make the four variants below
@@ -13,8 +16,10 @@ pub enum TokenTree {
 
     /// `$Variable`
     ///
-    /// `Using:Variable` are represented as separate tts.
-    Variable { span: Span, ident: FileStr },
+    /// A scope or drive qualifier is kept separately, eg.
+    /// `$script:Config` has `script` as a qualifier and `Config` as an ident.
+    /// Also a `${braced variable}`.
+    Variable { span: Span, ident: FileStr, qualifier: Option<FileStr> },
 
     /// `-Flag`
     Flag { span: Span, ident: FileStr },
@@ -27,7 +32,7 @@ pub enum TokenTree {
     /// Also (it's a stretch), a word inside []-brackets.
     Field { span: Span, ident: FileStr },
 
-    /// The `function` keyword.
+    /// The `function` keyword (or `filter`, which defines a function too).
     FunctionKeyword { span: Span },
 
     /// The `class` keyword.
@@ -36,6 +41,10 @@ pub enum TokenTree {
     /// The `return` keyword.
     ReturnKeyword { span: Span },
 
+    /// A `begin`, `process`, `end` or `dynamicparam` block
+    /// of an advanced function, along with its body.
+    NamedBlock { span: Span, keyword: Span, interior: TokenStream },
+
     /// The `in` keyword. The rest of keywords
     /// are parsed as commandlets.
     InKeyword { span: Span },
@@ -97,8 +106,10 @@ impl From<Span> for FileStr {
 }
 
 impl TT {
-    pub fn from_stage1(tt1: Box<[TT1]>, source: &str) -> Result<TokenStream> {
-        transform(tt1, Mode::Function, Delimiter::Brace, source)
+    /// Converts stage1 token trees, recovering from errors
+    /// by skipping the offending token trees.
+    pub fn from_stage1(tt1: Box<[TT1]>, source: &str, errors: &mut Vec<Error>) -> TokenStream {
+        transform(tt1, Mode::Function, Delimiter::Brace, source, errors)
     }
 }
 
@@ -123,7 +134,13 @@ enum Mode {
 }
 
 
-fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_source: &str) -> Result<TokenStream> {
+fn transform(
+    input: Box<[TT1]>,
+    start_mode: Mode,
+    delimiter: Delimiter,
+    whole_source: &str,
+    errors: &mut Vec<Error>,
+) -> TokenStream {
     let mut current_mode = start_mode;
 
     let mut stream = Stream::new(input);
@@ -139,7 +156,7 @@ fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_so
             (TT1::Symbol { symbol: '`', spacing: Joined, span }, _) => {
                 match stream.peek() {
                     Some(TT1::Symbol { symbol: '\n', .. }) => { stream.consume(); }
-                    _ => { return span.start.error("Unknown escape") }
+                    _ => report(errors, span.start, "Unknown escape"),
                 }
             }
 
@@ -159,7 +176,7 @@ fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_so
                 }
 
                 // Are there more of these magic symbols?
-                output.push(TT::Symbol { symbol: symbol, span })
+                output.push(TT::Symbol { symbol, span })
             }
 
             // ________________
@@ -193,11 +210,23 @@ fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_so
 
                 let ident = FileStr::from(span);
 
+                let followed_by_block = matches!(stream.peek(), Some(TT1::Group { delimiter: Delimiter::Brace, .. }));
+
+                if followed_by_block && is_block_keyword(ident.cut_from(whole_source)) {
+                    if let Some(TT1::Group { span: body_span, interior, .. }) = stream.consume() {
+                        let interior = transform(interior, Mode::Function, Delimiter::Brace, whole_source, errors);
+                        output.push(TT::NamedBlock { span: span.to(body_span), keyword: span, interior });
+                    }
+                    // Stay in function mode, so the next block is recognized too
+                    continue;
+                }
+
                 match ident.cut_from(whole_source) {
-                    "function" | "Function" => {
+                    "function" | "Function" | "filter" | "Filter" => {
                         output.push(TT::FunctionKeyword { span });
                     }
-                    "class" | "Class" => {
+                    // Enums are represented as classes
+                    "class" | "Class" | "enum" | "Enum" => {
                         output.push(TT::ClassKeyword { span });
                         class_keyword_encountered = true;
                         // field, because we use Field as class names anyway
@@ -250,7 +279,12 @@ fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_so
             (TT1::Symbol { symbol: '$', span, spacing: Joined }, _) => {
                 match stream.peek() {
                     Some(&TT1::Word { .. }) => {
-                        parse_variable_name(Some(span), &mut stream, &mut output);
+                        parse_variable_name(Some(span), &mut stream, &mut output, whole_source);
+                        current_mode = Mode::Argument;
+                    }
+                    Some(&TT1::Group { span: group_span, delimiter: Delimiter::Brace, .. }) => {
+                        stream.consume();
+                        output.push(braced_variable(span.to(group_span), group_span, whole_source));
                         current_mode = Mode::Argument;
                     }
                     _ => {
@@ -330,7 +364,7 @@ fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_so
                     _                                    => (span, Mode::Function, None),
                 };
 
-                let interior = transform(interior, mode, delimiter, whole_source)?;
+                let interior = transform(interior, mode, delimiter, whole_source, errors);
 
                 output.push(TT::Group { span, interior, delimiter, prefix });
                 class_keyword_encountered = false;
@@ -340,33 +374,26 @@ fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_so
 
             (TT1::String { span, subtrees }, _) => {
                 let mut new_subtrees = Vec::with_capacity(subtrees.len());
-                for subtree in subtrees.into_vec().into_iter() {
-                    let pushee = match subtree {
-                        TT1::Group { span, delimiter: Delimiter::Brace, interior } => {
-                            let mut stream = Stream::new(interior);
-                            let mut new_interior = Vec::new();
-                            parse_variable_name(None, &mut stream, &mut new_interior);
-                            if let Some(_) = stream.peek() {
-                                return span.start.error("Variable name expected in {}-block");
-                            }
-                            // TODO this is wrapped into group only to support
-                            // multi-token ${Using:Foo} syntax in strings.
-                            TT::Group {
-                                span,
-                                interior: new_interior.into_boxed_slice(),
-                                delimiter: Delimiter::Parenthesis,
-                                prefix: None
-                            }
+                let mut subtrees = Stream::new(subtrees);
+                while let Some(subtree) = subtrees.peek() {
+                    if let TT1::Word { .. } = subtree {
+                        parse_variable_name(None, &mut subtrees, &mut new_subtrees, whole_source);
+                        continue;
+                    }
+
+                    let pushee = match subtrees.consume().unwrap() {
+                        TT1::Group { span, delimiter: Delimiter::Brace, .. } => {
+                            braced_variable(span, span, whole_source)
                         }
                         TT1::Group { span, delimiter: Delimiter::Parenthesis, interior } => {
-                            let interior = transform(interior, Mode::Function, Delimiter::Parenthesis, whole_source)?;
+                            let interior = transform(
+                                interior, Mode::Function, Delimiter::Parenthesis, whole_source, errors
+                            );
                             TT::Group { span, interior, delimiter, prefix: Some('$') }
                         }
-                        TT1::Word { span, .. } => {
-                            TT::Variable { span, ident: span.into() }
-                        }
                         other_tt => {
-                            return other_tt.span().start.error("ICE: Weird subtree in string");
+                            report(errors, other_tt.span().start, "ICE: Weird subtree in string");
+                            continue;
                         }
                     };
                     new_subtrees.push(pushee);
@@ -392,30 +419,86 @@ fn transform(input: Box<[TT1]>, start_mode: Mode, delimiter: Delimiter, whole_so
         }
     }
 
-    Ok(output.into_boxed_slice())
+    output.into_boxed_slice()
 }
 
-/// Parses a single variable name or `Using:Variable`
-/// into a stream of tts. TODO: Better representation of `Using` variables.
-fn parse_variable_name(mut dollar_span: Option<Span>, stream: &mut Stream<TT1>, output: &mut Vec<TT>) {
-    while let Some(&TT1::Word { span, spacing }) = stream.peek() {
-        stream.consume();
+/// Checks for keywords starting named blocks of advanced functions
+fn is_block_keyword(ident: &str) -> bool {
+    match &*ident.to_lowercase() {
+        | "begin"
+        | "process"
+        | "end"
+        | "dynamicparam" => true,
+        | _              => false,
+    }
+}
 
-        let span_with_dollar = dollar_span.map(|s| s.to(span)).unwrap_or(span);
-        dollar_span = None;
-        output.push(TT::Variable { span: span_with_dollar, ident: span.into() });
+fn report(errors: &mut Vec<Error>, where_: Location, what: &str) {
+    errors.push(Error { what: what.into(), where_ });
+}
 
-        if spacing == Alone {
-            break
-        }
+/// Parses a single variable name, possibly qualified
+/// with a scope or a drive, eg. `$script:Foo` or `$env:PATH`.
+fn parse_variable_name(dollar_span: Option<Span>, stream: &mut Stream<TT1>, output: &mut Vec<TT>, whole_source: &str) {
+    let (mut span, mut spacing) = match stream.peek() {
+        Some(&TT1::Word { span, spacing }) => (span, spacing),
+        _ => return,
+    };
+    stream.consume();
 
-        if let Some(&TT1::Symbol { symbol: ':', span, spacing: Joined }) = stream.peek() {
+    let mut ident = FileStr::from(span);
+    let mut qualifier = None;
+
+    if spacing == Joined {
+        if let [TT1::Symbol { symbol: ':', spacing: Joined, .. }, TT1::Word { span: name_span, spacing: name_spacing }, ..] = stream[..] {
+            stream.consume();
             stream.consume();
-            output.push(TT::Symbol { symbol: ':', span });
-        } else {
-            break
+
+            qualifier = Some(ident);
+            ident = name_span.into();
+            span = span.to(name_span);
+            spacing = name_spacing;
         }
     }
+
+    // Function names contain dashes, eg. `$function:Invoke-Thing`
+    if qualifier.is_some_and(|qualifier| qualifier.cut_from(whole_source).eq_ignore_ascii_case("function")) {
+        while spacing == Joined {
+            match stream[..] {
+                [TT1::Symbol { symbol: '-', spacing: Joined, .. }, TT1::Word { span: next_span, spacing: next_spacing }, ..] => {
+                    stream.consume();
+                    stream.consume();
+                    span = span.to(next_span);
+                    spacing = next_spacing;
+                }
+                _ => break,
+            }
+        }
+        ident.end = span.end.byte;
+    }
+
+    let span = dollar_span.map(|s| s.to(span)).unwrap_or(span);
+    output.push(TT::Variable { span, ident, qualifier });
+}
+
+/// Creates a variable from `${}`-braces,
+/// which can contain any characters in the name.
+fn braced_variable(span: Span, braces: Span, whole_source: &str) -> TT {
+    let start = braces.start.byte + 1;
+    let end = (braces.end.byte - 1).max(start);
+    let inside = FileStr { start, end };
+
+    match inside.cut_from(whole_source).find(':') {
+        Some(colon) => {
+            let colon = start + colon as u32;
+            TT::Variable {
+                span,
+                ident: FileStr { start: colon + 1, end },
+                qualifier: Some(FileStr { start, end: colon }),
+            }
+        }
+        None => TT::Variable { span, ident: inside, qualifier: None },
+    }
 }
 
 pub fn traverse_streams(stream: &[TT], mut fun: impl FnMut(&[TT], Option<Delimiter>)) {
@@ -432,11 +515,13 @@ fn traverse_streams_(
         match tt {
             TT::Group { interior, delimiter, .. } => traverse_streams_(interior, Some(*delimiter), fun),
             TT::String { subtrees, .. }           => traverse_streams_(subtrees, None, fun),
+            TT::NamedBlock { interior, .. }       => traverse_streams_(interior, Some(Delimiter::Brace), fun),
             _                                     => ()
         }
     }
 }
 
+#[cfg(feature = "pretty")]
 pub mod pretty {
     use super::*;
     use yansi::Color;
@@ -451,10 +536,10 @@ pub mod pretty {
 
     fn print_colored(source: &str, word: FileStr, replacee: Option<&str>, color: Color, done: &mut usize) {
         let end = word.start as usize;
-        // if end < *done {
-        //     print!(" << OHUI {:?} -> {:?} >> ", done, word);
-        //     return;
-        // }
+        if end < *done || word.end as usize > source.len() {
+            // Overlapping or invalid spans, nothing sensible to print
+            return;
+        }
         print!(
             "{}{}",
             &source[*done .. end],
@@ -472,13 +557,14 @@ pub mod pretty {
                 TT::Field    { ident, .. } => (Color::Red, *ident),
 
                 &TT::Symbol { symbol: ';', span, .. } => {
-                    if source.as_bytes()[span.start.byte as usize] == b'\n' {
+                    if source.as_bytes().get(span.start.byte as usize) == Some(&b'\n') {
                         print_colored(source, span.into(), Some(";\n"), Color::Red, done);
                     }
                     continue;
                 }
 
                 TT::String { subtrees: interior, .. } |
+                TT::NamedBlock { interior, .. }       |
                 TT::Group { interior, .. }            => {
                     color_print_impl(source, interior, done);
                     continue;
@@ -18,10 +18,7 @@ impl<T: Dummy> Stream<T> {
     }
 
     pub fn consume(&mut self) -> Option<T> {
-        let slot = match self.storage.get_mut(self.pos) {
-            Some(slot) => slot,
-            None => return None,
-        };
+        let slot = self.storage.get_mut(self.pos)?;
 
         let val = mem::replace(slot, T::dummy());
         self.pos += 1;
@@ -0,0 +1,49 @@
+//! Utilities for testing lints on PowerShell sources
+//!
+//! Available with the `testing` feature.
+
+use failure::Error;
+pub use tempdir::TempDir;
+
+use std::fs;
+use std::path::Path;
+
+use {run, Emitter, Summary, VecEmitter};
+
+/// Contents of a single script to analyze
+pub struct Contents<'x>(pub &'x str);
+
+/// Creates a temporary directory with given files.
+///
+/// The directory is removed when the returned `TempDir` is dropped.
+pub fn setup_dir(files: &[(&str, &str)]) -> Result<TempDir, Error> {
+    let dir = TempDir::new("shelly")?;
+    for &(path, contents) in files {
+        let path = dir.path().join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    Ok(dir)
+}
+
+/// Runs the analysis on a directory and returns emitted messages.
+pub fn test_dir(dir: impl AsRef<Path>) -> VecEmitter {
+    let mut emitter = VecEmitter::new();
+    run(dir, Default::default(), &mut emitter).expect("run failed");
+    emitter
+}
+
+/// Runs the analysis on a single file in a temporary directory.
+pub fn run_on_file(Contents(data): Contents, emitter: &mut dyn Emitter) -> Result<Summary, Error> {
+    let dir = setup_dir(&[("File.ps1", data)])?;
+    run(dir.path(), Default::default(), emitter)
+}
+
+/// Runs the analysis on a single file and returns emitted messages.
+pub fn test_file(file: Contents) -> VecEmitter {
+    let mut emitter = VecEmitter::new();
+    run_on_file(file, &mut emitter).expect("run failed");
+    emitter
+}
@@ -1,15 +1,102 @@
 use std::collections::BTreeMap as Map;
 use std::path::PathBuf;
 
+use failure::{Error, ResultExt};
+use regex::Regex;
+
 use lint::Lint;
 use lint::Emitter;
 use preprocess::Parsed;
-use syntax::Item;
+use syntax::{Describe, Item, TestCases};
+use ConfigFile;
+
+/// Characters reported by `invalid-testname-characters`, when not configured.
+/// They're invalid in file names on Windows.
+pub(crate) const DEFAULT_INVALID_CHARACTERS: &str = "\"><|:*?\\/";
+
+/// Testname conventions
+pub struct Config {
+    /// Pattern that every testname should match
+    pattern: Option<Regex>,
+
+    /// Characters not allowed in testnames
+    invalid_chars: Vec<char>,
+}
+
+/// Options of `invalid-testname-characters`, in `[lint-options.invalid-testname-characters]`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct InvalidCharactersOptions {
+    /// Characters not allowed in testnames
+    pub(crate) characters: Option<String>,
+}
+
+impl Config {
+    pub fn from_config_file(config_file: &ConfigFile) -> Result<Config, Error> {
+        let options: InvalidCharactersOptions = config_file.lint_options(Lint::InvalidTestnameCharacters)?
+            .unwrap_or_default();
+        let invalid_chars = options.characters.as_deref().unwrap_or(DEFAULT_INVALID_CHARACTERS).chars().collect();
+
+        let pattern = match config_file.testnames.as_ref().and_then(|testnames| testnames.pattern.as_ref()) {
+            Some(pattern) => Some(
+                Regex::new(pattern).with_context(|_| format!("Invalid testname pattern: {}", pattern))?
+            ),
+            None => None,
+        };
+
+        Ok(Config { pattern, invalid_chars })
+    }
+}
 
-pub fn analyze(files: &Map<PathBuf, Parsed>, emitter: &mut Emitter) {
-    let invalid_chars: &[char] = &['"', '>', '<', '|', ':', '*', '?', '\\', '/'];
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[
+    Lint::InvalidTestnameCharacters,
+    Lint::ReservedTestnames,
+    Lint::InvalidTestnames,
+    Lint::DuplicateDescribes,
+    Lint::UnknownTestcasePlaceholders,
+];
+
+/// Maximal length of a file name on most file systems
+const MAX_FILE_NAME_LEN: usize = 255;
+
+pub fn analyze(files: &Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter) {
+    lazy_static! {
+        /// `<Name>` placeholder in a templated testname
+        static ref PLACEHOLDER: Regex = Regex::new(r"<([^<>]*)>").unwrap();
+    }
 
     for file in files.values() {
+        for testcase in &file.testcases {
+            let keys = match &testcase.test_cases {
+                Some(TestCases::Literal(keys)) => keys,
+                _ => continue,
+            };
+
+            for placeholder in PLACEHOLDER.captures_iter(&testcase.name) {
+                // `<_>` is the whole test case, `<Key.Property>` is a property of a value
+                let key = placeholder[1].split('.').next().unwrap_or("").to_lowercase();
+                if key != "_" && !keys.contains(&key) {
+                    testcase.span.in_file(file)
+                        .lint(Lint::UnknownTestcasePlaceholders, "placeholder not defined in test cases")
+                        .what(&placeholder[1])
+                        .note(format!("`{}` is not a key in every hashtable in `-TestCases`", &placeholder[1]))
+                        .emit(emitter);
+                }
+            }
+        }
+
+        if let Some(pattern) = &config.pattern {
+            for testcase in &file.testcases {
+                if !pattern.is_match(&testcase.name) {
+                    testcase.span.in_file(file)
+                        .lint(Lint::InvalidTestnames, "testname doesn't follow the naming convention")
+                        .note(format!("Testnames should match `{}`", pattern.as_str()))
+                        .emit(emitter);
+                }
+            }
+        }
+
         let uses_pester_logger = file.usages.iter()
             .any(|usage| usage.item.as_ref() == Item::function("Initialize-PesterLogger"));
 
@@ -18,12 +105,84 @@ pub fn analyze(files: &Map<PathBuf, Parsed>, emitter: &mut Emitter) {
         }
 
         for testcase in &file.testcases {
-            if testcase.name.contains(invalid_chars) {
-                testcase.span.in_file(&file)
+            // Placeholders are replaced by Pester before the name is used
+            let name = match testcase.test_cases {
+                Some(_) => PLACEHOLDER.replace_all(&testcase.name, "x"),
+                None => testcase.name.as_str().into(),
+            };
+
+            if name.contains(&*config.invalid_chars) {
+                testcase.span.in_file(file)
                     .lint(Lint::InvalidTestnameCharacters, "testname contains invalid characters")
-                    .note(format!("These characters are invalid in a file name: {:?}", invalid_chars))
+                    .note(format!("Testnames can't contain these characters: {:?}", config.invalid_chars))
+                    .emit(emitter);
+            }
+
+            if let Some(problem) = reserved_name_problem(&name) {
+                testcase.span.in_file(file)
+                    .lint(Lint::ReservedTestnames, "testname is not a valid Windows file name")
+                    .note(problem)
+                    .emit(emitter);
+            }
+        }
+    }
+
+    check_duplicate_describes(files, emitter);
+}
+
+/// Reports `Describe` block names used in more than one file
+fn check_duplicate_describes(files: &Map<PathBuf, Parsed>, emitter: &mut Emitter) {
+    // First occurrence of each name
+    let mut first: Map<&str, (&Parsed, &Describe)> = Map::new();
+
+    for file in files.values() {
+        for describe in &file.describes {
+            let &mut (first_file, first_describe) = first.entry(&describe.name).or_insert((file, describe));
+
+            if !::std::ptr::eq(first_file, file) {
+                describe.span.in_file(file)
+                    .lint(Lint::DuplicateDescribes, "Describe block name used in multiple files")
+                    .what(describe.name.clone())
+                    .note_at(first_describe.span.in_file(first_file), "Also used here")
                     .emit(emitter);
             }
         }
     }
 }
+
+/// Checks whether a name can't be used as a file name on Windows
+/// for reasons other than invalid characters.
+fn reserved_name_problem(name: &str) -> Option<String> {
+    const DEVICE_NAMES: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL",
+        "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+        "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    // Device names are reserved also with an extension, eg. `nul.txt`
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if let Some(device) = DEVICE_NAMES.iter().find(|device| device.eq_ignore_ascii_case(stem)) {
+        return Some(format!("`{}` is a reserved device name", device));
+    }
+
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some("File names can't end with a dot or a space".to_owned());
+    }
+
+    if name.len() > MAX_FILE_NAME_LEN {
+        return Some(format!("File names can't be longer than {} bytes", MAX_FILE_NAME_LEN));
+    }
+
+    None
+}
+
+#[test]
+fn test_reserved_name_problem() {
+    assert!(reserved_name_problem("works fine").is_none());
+    assert!(reserved_name_problem("Connects").is_none());
+    assert!(reserved_name_problem("con").is_some());
+    assert!(reserved_name_problem("NUL.log").is_some());
+    assert!(reserved_name_problem("COM1 ").is_some());
+    assert!(reserved_name_problem("ends with a dot.").is_some());
+    assert!(reserved_name_problem(&"x".repeat(300)).is_some());
+}
@@ -0,0 +1,57 @@
+//! Wall time measurements of analysis phases, printed with `--timings`
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Number of the slowest files to report
+const TOP_FILES: usize = 10;
+
+#[derive(Default)]
+pub struct Timings {
+    enabled: bool,
+    phases: Vec<(&'static str, Duration)>,
+    files: Vec<(PathBuf, Duration)>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Timings {
+        Timings { enabled, ..Timings::default() }
+    }
+
+    /// Records a phase started at `start`
+    pub fn phase(&mut self, name: &'static str, start: Instant) {
+        if self.enabled {
+            self.phases.push((name, start.elapsed()));
+        }
+    }
+
+    /// Records parsing of a single file started at `start`
+    pub fn file(&mut self, path: &Path, start: Instant) {
+        if self.enabled {
+            self.files.push((path.to_owned(), start.elapsed()));
+        }
+    }
+
+    /// Prints the timings on stderr, if enabled
+    pub fn report(mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        eprintln!("Timings:");
+        for (name, duration) in &self.phases {
+            eprintln!("{:>20}: {}", name, format_duration(*duration));
+        }
+
+        self.files.sort_by_key(|&(_, duration)| ::std::cmp::Reverse(duration));
+
+        eprintln!("Slowest files to parse:");
+        for (path, duration) in self.files.iter().take(TOP_FILES) {
+            eprintln!("{:>20}: {}", format_duration(*duration), path.display());
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
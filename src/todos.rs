@@ -0,0 +1,72 @@
+//! Reports `TODO`-like comments, so they can be collected into reports
+
+use std::collections::BTreeMap as Map;
+use std::path::PathBuf;
+
+use failure::{Error, ResultExt};
+use regex::Regex;
+
+use lint::Emitter;
+use lint::Lint;
+use preprocess::Parsed;
+use syntax::Span;
+use ConfigFile;
+
+/// Markers of reported comments, when `[todos] markers` is not set
+pub(crate) const DEFAULT_MARKERS: &[&str] = &["TODO", "FIXME", "HACK"];
+
+pub struct Config {
+    /// Matches any of the markers
+    markers: Regex,
+}
+
+impl Config {
+    pub fn from_config_file(config_file: &ConfigFile) -> Result<Config, Error> {
+        let markers = config_file.todos
+            .as_ref()
+            .and_then(|todos| todos.markers.clone())
+            .unwrap_or_else(|| DEFAULT_MARKERS.iter().map(|&marker| marker.to_owned()).collect());
+
+        let alternatives: Vec<_> = markers.iter().map(|marker| regex::escape(marker)).collect();
+        let markers = Regex::new(&format!(r"\b(?:{})\b", alternatives.join("|")))
+            .with_context(|_| format!("Invalid comment markers: {:?}", markers))?;
+
+        Ok(Config { markers })
+    }
+}
+
+/// Lints emitted by this pass
+pub const LINTS: &[Lint] = &[Lint::TodoComments];
+
+pub fn analyze(files: &Map<PathBuf, Parsed>, config: &Config, emitter: &mut Emitter) {
+    for file in files.values() {
+        let source = &*file.source;
+
+        for comment in &file.comments {
+            let start = comment.start.byte as usize;
+            let text = &source[start..comment.end.byte as usize];
+
+            let marker = match config.markers.find(text) {
+                Some(marker) => marker,
+                None => continue,
+            };
+
+            // The rest of the line, without the end of a `<# #>` comment
+            let rest = &text[marker.start()..];
+            let rest = rest.lines().next().unwrap_or(rest);
+            let rest = rest.trim_end().trim_end_matches("#>").trim_end();
+
+            let line = comment.start.line + text[..marker.start()].matches('\n').count() as u32;
+            let span = Span::from_fragment(line, rest, source);
+
+            let description = rest[marker.as_str().len()..].trim_start_matches(':').trim();
+
+            let message = span.in_file(file)
+                .lint(Lint::TodoComments, format!("{} comment", marker.as_str()));
+
+            let message = if description.is_empty() { message } else { message.note(description) };
+
+            message.emit(emitter);
+        }
+    }
+}
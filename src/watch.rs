@@ -0,0 +1,168 @@
+//! Choosing files to re-analyze on changes, used by `shelly watch`
+
+use std::collections::BTreeMap as Map;
+use std::collections::BTreeSet as Set;
+use std::path::{Path, PathBuf};
+
+use preprocess::FileKind;
+use {baseline, builtins, EmittedItem, Emitter, Location, CONFIG_FILE_NAMES};
+
+/// Which files import which, by canonical paths
+#[derive(Debug, Default)]
+pub struct ImportGraph {
+    imports: Map<PathBuf, Vec<PathBuf>>,
+
+    /// Files reported on, as opposed to ones parsed only as dependencies
+    /// (eg. excluded ones), which are not analyzed on their changes
+    analyzed: Set<PathBuf>,
+}
+
+/// Files to analyze after a change
+#[derive(Debug, PartialEq)]
+pub enum Rerun {
+    /// A config or other file affecting every file has changed
+    Everything,
+
+    /// Changed files and files importing them, which still exist,
+    /// except the ones previously parsed only as dependencies
+    Files(Vec<PathBuf>),
+}
+
+impl ImportGraph {
+    /// Emitter recording imports of parsed files
+    /// and passing everything else to `inner`.
+    pub fn recorder<'a>(&'a mut self, inner: &'a mut dyn Emitter) -> Recorder<'a> {
+        Recorder { inner, graph: self }
+    }
+
+    /// Given files and all the files importing them, directly or not
+    pub fn importers(&self, files: &[PathBuf]) -> Set<PathBuf> {
+        let mut found: Set<PathBuf> = files.iter().cloned().collect();
+        loop {
+            let new: Vec<PathBuf> = self.imports.iter()
+                .filter(|(importer, imports)| !found.contains(*importer) && imports.iter().any(|import| found.contains(import)))
+                .map(|(importer, _)| importer.clone())
+                .collect();
+            if new.is_empty() {
+                return found;
+            }
+            found.extend(new);
+        }
+    }
+
+    /// Decides what to analyze after the paths have changed.
+    ///
+    /// `config` is the config given outside of the analyzed directory, if any.
+    /// Changes of files other than configs and PowerShell files are ignored,
+    /// so `None` is returned if there's nothing to analyze.
+    pub fn rerun(&self, changed: &[PathBuf], config: Option<&Path>) -> Option<Rerun> {
+        if changed.iter().any(|path| affects_all_files(path) || Some(&**path) == config) {
+            return Some(Rerun::Everything);
+        }
+
+        let changed: Vec<PathBuf> = changed.iter()
+            .filter(|path| FileKind::from_path(path).is_some())
+            .cloned()
+            .collect();
+        let files: Vec<PathBuf> = self.importers(&changed)
+            .into_iter()
+            .filter(|path| path.exists())
+            .filter(|path| !self.imports.contains_key(path) || self.analyzed.contains(path))
+            .collect();
+
+        if files.is_empty() {
+            None
+        } else {
+            Some(Rerun::Files(files))
+        }
+    }
+}
+
+/// Checks whether it's a config or other file affecting analysis of every file
+fn affects_all_files(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => CONFIG_FILE_NAMES.contains(&name) || [builtins::LOCKFILE, baseline::FILE].contains(&name),
+        None => false,
+    }
+}
+
+/// Emitter created by `ImportGraph::recorder`
+pub struct Recorder<'a> {
+    inner: &'a mut dyn Emitter,
+    graph: &'a mut ImportGraph,
+}
+
+impl<'a> Emitter for Recorder<'a> {
+    fn emit(&mut self, item: EmittedItem) {
+        self.inner.emit(item);
+    }
+
+    fn analyzed_file(&mut self, path: &Path) {
+        if let Ok(path) = path.canonicalize() {
+            self.graph.analyzed.insert(path);
+        }
+        self.inner.analyzed_file(path);
+    }
+
+    fn parsed_imports(&mut self, path: &Path, imports: &[&Path]) {
+        self.graph.imports.insert(path.to_owned(), imports.iter().map(|import| import.to_path_buf()).collect());
+        self.inner.parsed_imports(path, imports);
+    }
+
+    fn parsed_import(&mut self, path: &Path, import: &Path, location: &Location) {
+        self.inner.parsed_import(path, import, location);
+    }
+}
+
+#[test]
+fn test_rerun() {
+    use std::fs;
+
+    let dir = ::testing::setup_dir(&[
+        ("A.ps1", ". $PSScriptRoot/B.ps1\nGet-C\n"),
+        ("B.ps1", ". $PSScriptRoot/C.ps1\n"),
+        ("C.ps1", "function Get-C { }\n"),
+        ("D.ps1", ". $PSScriptRoot/C.ps1\n"),
+        ("Other.ps1", "Get-Other\n"),
+    ]).unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    let path = |name: &str| root.join(name);
+
+    let mut graph = ImportGraph::default();
+    ::run(&root, Default::default(), &mut graph.recorder(&mut ::VecEmitter::new())).unwrap();
+
+    let importers: Vec<_> = graph.importers(&[path("C.ps1")]).into_iter().collect();
+    assert_eq!(importers, [path("A.ps1"), path("B.ps1"), path("C.ps1"), path("D.ps1")]);
+
+    assert_eq!(graph.rerun(&[path("B.ps1"), path("notes.txt")], None), Some(Rerun::Files(vec![path("A.ps1"), path("B.ps1")])));
+    assert_eq!(graph.rerun(&[path("notes.txt")], None), None);
+    assert_eq!(graph.rerun(&[path("Other.ps1"), path("shelly.toml")], None), Some(Rerun::Everything));
+    assert_eq!(graph.rerun(&[path("builtins.lock")], None), Some(Rerun::Everything));
+
+    let outside_config = Path::new("/etc/shelly/ci.toml");
+    assert_eq!(graph.rerun(&[outside_config.to_owned()], Some(outside_config)), Some(Rerun::Everything));
+
+    // Importers of a deleted file are still analyzed
+    fs::remove_file(path("B.ps1")).unwrap();
+    assert_eq!(graph.rerun(&[path("B.ps1")], None), Some(Rerun::Files(vec![path("A.ps1")])));
+}
+
+#[test]
+fn test_rerun_skips_excluded_files() {
+    let dir = ::testing::setup_dir(&[
+        ("shelly.toml", "[files]\nexclude = [\"vendor/**\"]\n"),
+        ("Main.ps1", ". $PSScriptRoot/vendor/Lib.ps1\nGet-Lib\n"),
+        ("vendor/Lib.ps1", "function Get-Lib { Get-Missing }\n"),
+    ]).unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    let path = |name: &str| root.join(name);
+
+    let mut graph = ImportGraph::default();
+    ::run(&root, Default::default(), &mut graph.recorder(&mut ::VecEmitter::new())).unwrap();
+
+    assert_eq!(graph.rerun(&[path("vendor/Lib.ps1")], None), Some(Rerun::Files(vec![path("Main.ps1")])));
+
+    // New files are analyzed
+    ::std::fs::write(path("New.ps1"), "Get-New\n").unwrap();
+    assert_eq!(graph.rerun(&[path("New.ps1")], None), Some(Rerun::Files(vec![path("New.ps1")])));
+}
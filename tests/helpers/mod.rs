@@ -1,27 +1,21 @@
-use tempdir::TempDir;
-use failure::Error;
-use shelly::{self, Emitter, VecEmitter, EmittedItem};
+use shelly::VecEmitter;
 
-use std::fs;
-use std::path::Path;
+use std::env;
+use std::path::{Path, PathBuf};
 
-pub fn test_dir(dir: impl AsRef<Path>) -> Vec<EmittedItem> {
-    let mut emitter = VecEmitter::new();
-    let root_path = Path::new("tests").join(dir);
-    shelly::run(&root_path, Default::default(), &mut emitter).expect("run failed");
-    emitter.emitted_items
-}
-
-pub struct Contents<'x>(pub &'x str);
+pub use shelly::testing::{setup_dir, test_file, Contents};
 
-pub fn run_on_file(Contents(data): Contents, emitter: &mut Emitter) -> Result<(), Error> {
-    let dir = TempDir::new("shelly")?;
-    fs::write(dir.path().join("File.ps1"), data)?;
-    shelly::run(dir.path(), Default::default(), emitter)
+/// Runs the analysis on a directory relative to `tests/`
+pub fn test_dir(dir: impl AsRef<Path>) -> VecEmitter {
+    shelly::testing::test_dir(Path::new("tests").join(dir))
 }
 
-pub fn test_file(file: Contents) -> Vec<EmittedItem> {
-    let mut emitter = VecEmitter::new();
-    run_on_file(file, &mut emitter).expect("run failed");
-    emitter.emitted_items
+/// Path to the compiled `shelly` binary
+pub fn shelly_binary() -> PathBuf {
+    let test_binary_path = env::current_exe().unwrap();
+    let mut target_dir = test_binary_path.parent().unwrap().to_owned();
+    if target_dir.ends_with("deps") {
+        target_dir.pop();
+    }
+    target_dir.join("shelly")
 }
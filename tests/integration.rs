@@ -1,13 +1,16 @@
 extern crate shelly;
 extern crate failure;
-extern crate tempdir;
 
 mod helpers;
 
 use shelly::lint::Lint;
 use shelly::MessageKind;
 
+use std::path::Path;
+
 use helpers::{
+    setup_dir,
+    shelly_binary,
     test_dir,
     test_file,
     Contents
@@ -16,7 +19,7 @@ use helpers::{
 #[test]
 fn something_works() {
     let errors = test_dir("testcases/case1");
-    let lints: Vec<_> = errors.into_iter().map(|error| error.lint).collect();
+    let lints = errors.lints();
     assert!(lints.contains(&Lint::UnknownFunctions));
     assert!(lints.contains(&Lint::NoStrictMode));
 }
@@ -24,10 +27,10 @@ fn something_works() {
 #[test]
 fn loads_a_config() {
     let errors = test_dir("testcases/with_config");
-    let lints: Vec<_> = errors.iter().map(|error| error.lint).collect();
+    let lints = errors.lints();
 
     assert_eq!(errors.len(), 2);
-    assert!(errors.iter().all(|err| err.kind == MessageKind::Error));
+    assert!(errors.emitted_items.iter().all(|err| err.kind == MessageKind::Error));
 
     // Warn by default, overrided to deny
     assert!(lints.contains(&Lint::UnknownFunctions));
@@ -38,36 +41,298 @@ fn loads_a_config() {
     assert!(! lints.contains(&Lint::NoStrictMode));
 }
 
+#[test]
+fn checks_file_names() {
+    let errors = test_dir("testcases/filenames");
+
+    assert_eq!(errors.lints(), [Lint::InvalidFileNames; 2]);
+    assert_eq!(errors.in_file("thing_test.ps1").len(), 2);
+}
+
+#[test]
+fn custom_patterns_produce_definitions() {
+    let errors = test_dir("testcases/patterns");
+
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    errors.assert_contains(Lint::UnknownFunctions, "not in scope");
+}
+
+#[test]
+fn modules_export_functions_listed_in_manifest() {
+    let errors = test_dir("testcases/modules");
+
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    let in_main = errors.in_file("Main.ps1");
+    assert_eq!(in_main.len(), 1);
+    assert_eq!(in_main[0].location.span.unwrap().start.line, 7);
+}
+
+#[test]
+fn merges_builtins_lock() {
+    let errors = test_dir("testcases/builtins_lock");
+
+    assert_eq!(errors.lints(), [Lint::BuiltinsConflicts; 2]);
+    assert_eq!(errors.in_file("builtins.lock").len(), 2);
+}
+
+#[test]
+fn reports_deprecated_functions() {
+    let errors = test_dir("testcases/deprecated");
+
+    let deprecated = errors.only(Lint::DeprecatedFunctions);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(deprecated.len(), 2);
+
+    errors.assert_contains(Lint::DeprecatedFunctions, "Get-CimInstance");
+    assert!(deprecated[0].fix.is_none());
+
+    let fix = deprecated[1].fix.as_ref().unwrap();
+    assert_eq!(fix.edits.len(), 1);
+    assert_eq!(fix.edits[0].replacement, "Write-Log");
+}
+
+#[test]
+fn reports_banned_functions() {
+    let dir = setup_dir(&[
+        ("shelly.toml", concat!(
+            "[banned]\n",
+            "Invoke-LegacyDownload = \"Invoke-RestMethod\"\n",
+            "Invoke-Expression = \"\"\n",
+            "[deprecated]\n",
+            "Invoke-Expression = \"Avoid it\"\n",
+        )),
+        ("Build.ps1", "Set-StrictMode -Version Latest\nfunction Invoke-LegacyDownload {}\nInvoke-LegacyDownload\nInvoke-Expression 'x'\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let banned = errors.only(Lint::BannedFunctions);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(banned.len(), 2);
+
+    assert_eq!(banned[0].notes[0].message, "Use `Invoke-RestMethod` instead");
+    assert_eq!(banned[0].fix.as_ref().unwrap().edits[0].replacement, "Invoke-RestMethod");
+    assert!(banned[1].notes.iter().all(|note| !note.message.contains("Avoid it")));
+    assert!(banned[1].fix.is_none());
+    assert_eq!(banned[1].kind, shelly::MessageKind::Error);
+}
+
+#[test]
+fn fixes_can_be_applied_from_json_results() {
+    use std::fs;
+    use std::process::Command;
+
+    let read = |file| fs::read_to_string(Path::new("tests/testcases/deprecated").join(file)).unwrap();
+    let dir = setup_dir(&[
+        ("shelly.toml", &read("shelly.toml")),
+        ("Get-Info.ps1", &read("Get-Info.ps1")),
+    ]).unwrap();
+
+    let output = Command::new(shelly_binary())
+        .args(["analyze", "--format", "json", "--with-fixes"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    fs::write(dir.path().join("results.json"), &output.stdout).unwrap();
+
+    let output = Command::new(shelly_binary())
+        .args(["apply-fixes", "results.json"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let fixed = fs::read_to_string(dir.path().join("Get-Info.ps1")).unwrap();
+    assert!(fixed.contains("\nWrite-Log \"done\""));
+    assert!(fixed.contains("\nGet-WmiObject"));
+}
+
+#[test]
+fn nested_functions_are_not_visible_to_importers() {
+    let dir = setup_dir(&[
+        ("Foo.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "function Get-Foo {\n",
+            "    function Get-Helper {}\n",
+            "    Get-Helper\n",
+            "}\n",
+        )),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Foo.ps1\n",
+            "Get-Foo\n",
+            "Get-Helper\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert_eq!(errors.in_file("Main.ps1").len(), 1);
+}
+
+#[test]
+fn unsorted_imports_can_be_fixed() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\nunsorted-imports = \"warn\"\n"),
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Bar.ps1", "function Get-Bar {}\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Foo.ps1\n",
+            "function Get-Main { Get-Foo }\n",
+            ". $PSScriptRoot/Bar.ps1 # bar\n",
+            "Get-Bar\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnsortedImports]);
+    errors.assert_contains(Lint::UnsortedImports, "not grouped");
+
+    let fix = errors.emitted_items[0].fix.as_ref().unwrap();
+    assert_eq!(fix.edits[0].replacement, concat!(
+        ". $PSScriptRoot/Bar.ps1 # bar\n",
+        ". $PSScriptRoot/Foo.ps1\n",
+        "\n",
+        "function Get-Main { Get-Foo }",
+    ));
+}
+
+#[test]
+fn reports_test_files_imported_by_non_test_code() {
+    let dir = setup_dir(&[
+        ("Get-Foo.ps1", "Set-StrictMode -Version Latest\nfunction Get-Foo { Get-Helper }\n"),
+        ("Get-Foo.Tests.ps1", concat!(
+            ". $PSScriptRoot/Get-Foo.ps1\n",
+            "function Get-Helper {}\n",
+            "Describe 'Get-Foo' { It 'works' { Get-Foo } }\n",
+        )),
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Get-Foo.Tests.ps1\nGet-Helper\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let imported_tests = errors.only(Lint::ImportedTests);
+    assert_eq!(imported_tests.len(), 1);
+    assert!(imported_tests[0].location.file.ends_with("Main.ps1"));
+}
+
+#[test]
+fn strict_mode_can_be_required_in_imported_files() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[strictness]\nscope = \"all\"\n"),
+        ("A.ps1", "function Get-A {}\n"),
+        ("B.ps1", "function Get-B {}\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/A.ps1\n. $PSScriptRoot/B.ps1\nGet-A\nGet-B\n"),
+        ("Other.ps1", ". $PSScriptRoot/A.ps1\nGet-A\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::NoStrictMode; 2]);
+
+    let in_a = errors.in_file("A.ps1");
+    assert_eq!(in_a.len(), 1);
+    let root = in_a[0].notes.iter().find_map(|note| note.location.as_ref()).unwrap();
+    assert!(root.file.ends_with("Other.ps1"));
+
+    assert_eq!(errors.in_file("Other.ps1").len(), 1);
+}
+
+#[test]
+fn checks_custom_requirements() {
+    let dir = setup_dir(&[
+        ("shelly.toml", concat!(
+            "[[requirements]]\n",
+            "name = \"logging\"\n",
+            "injected_by = [\"Initialize-Logging\"]\n",
+            "message = \"logging not initialized\"\n",
+        )),
+        ("Logging.ps1", "function Initialize-Logging {}\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Logging.ps1\nInitialize-Logging\n"),
+        ("Other.ps1", "Set-StrictMode -Version Latest\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::MissingRequirements]);
+    errors.assert_contains(Lint::MissingRequirements, "logging not initialized");
+    assert_eq!(errors.in_file("Other.ps1").len(), 1);
+}
+
+#[test]
+fn checks_testname_conventions() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[testnames]\npattern = '^[A-Z].* (should|when) .*'\n"),
+        ("Get-Foo.Tests.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "Describe 'Get-Foo' {\n",
+            "    It 'Returns foo when asked' {}\n",
+            "    It 'works' {}\n",
+            "}\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let invalid = errors.only(Lint::InvalidTestnames);
+    assert_eq!(invalid.len(), 1);
+    assert_eq!(invalid[0].location.span.unwrap().start.line, 4);
+}
+
+#[test]
+fn reports_duplicate_describe_names() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\nduplicate-describes = \"warn\"\n"),
+        ("A.Tests.ps1", "Set-StrictMode -Version Latest\nDescribe 'Utils' {}\nDescribe 'A' {}\n"),
+        ("B.Tests.ps1", "Set-StrictMode -Version Latest\nDescribe \"Utils\" {}\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::DuplicateDescribes]);
+
+    let duplicate = &errors.emitted_items[0];
+    assert!(duplicate.location.file.ends_with("B.Tests.ps1"));
+    let first = duplicate.notes.iter().find_map(|note| note.location.as_ref()).unwrap();
+    assert!(first.file.ends_with("A.Tests.ps1"));
+}
+
 #[test]
 fn it_can_be_tested_on_string() {
     let errors = test_file(Contents(r#"
         Write-Poem -About "shelly"
     "#));
-    let lints: Vec<_> = errors.into_iter().map(|error| error.lint).collect();
+    let lints = errors.lints();
     assert!(lints.contains(&Lint::UnknownFunctions));
     assert!(lints.contains(&Lint::NoStrictMode));
 }
 
 #[test]
 fn it_can_be_used_as_a_binary() {
-    use std::env;
     use std::process::Command;
 
-    let test_binary_path = env::current_exe().unwrap();
-    let mut target_dir = test_binary_path.parent().unwrap().to_owned();
-    if target_dir.ends_with("deps") {
-        target_dir.pop();
-    }
-    let shelly_path = target_dir.join("shelly");
-
-    let output = Command::new(shelly_path)
+    let output = Command::new(shelly_binary())
         .current_dir("tests/testcases/case1")
         .output()
         .expect("can't run shelly");
 
-    assert!(output.status.success());
+    // There are errors, so the analysis fails
+    assert_eq!(output.status.code(), Some(1));
     let output_string = ::std::str::from_utf8(&output.stdout).unwrap();
     assert!(output_string.contains("not in scope"));
+    assert!(output_string.contains("error[unknown-functions]"));
+}
+
+#[test]
+fn prints_timings_of_phases() {
+    use std::process::Command;
+
+    let output = Command::new(shelly_binary())
+        .args(["analyze", "--timings"])
+        .current_dir("tests/testcases/case1")
+        .output()
+        .unwrap();
+
+    let timings = ::std::str::from_utf8(&output.stderr).unwrap();
+    assert!(timings.contains("Timings:\n"));
+    assert!(timings.contains("parsing: "));
+    assert!(timings.contains("Slowest files to parse:\n"));
 }
 
 #[test]
@@ -84,8 +349,59 @@ fn test_invalid_characters() {
         }
     "#));
 
-    let lints: Vec<_> = errors.into_iter().map(|error| error.lint).collect();
-    assert!(lints.contains(&Lint::InvalidTestnameCharacters));
+    assert!(errors.lints().contains(&Lint::InvalidTestnameCharacters));
+}
+
+#[test]
+fn invalid_testname_characters_can_be_configured() {
+    let test = concat!(
+        "Describe 'A thing' {\n",
+        "    BeforeEach { Initialize-PesterLogger -Dir $Dir }\n",
+        "    It 'handles a: b' { }\n",
+        "    It 'handles a & b' { }\n",
+        "}\n",
+    );
+    let config = "[lint-options.invalid-testname-characters]\ncharacters = '&'\n";
+    let dir = setup_dir(&[("shelly.toml", config), ("Thing.Tests.ps1", test)]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let lines: Vec<_> = errors.only(Lint::InvalidTestnameCharacters).iter()
+        .map(|item| item.location.span.unwrap().start.line)
+        .collect();
+    assert_eq!(lines, [4]);
+
+    for config in &[
+        "[lint-options.invalid-testname-characters]\nchars = '&'\n",
+        "[lint-options.unused-imports]\n",
+        "[lint-options.no-such-lint]\n",
+    ] {
+        let dir = setup_dir(&[("shelly.toml", *config), ("Thing.Tests.ps1", test)]).unwrap();
+        let mut errors = shelly::VecEmitter::new();
+        assert!(shelly::run(dir.path(), Default::default(), &mut errors).is_err(), "{}", config);
+    }
+}
+
+#[test]
+fn test_templated_testnames() {
+    let errors = test_file(Contents(r#"
+        Set-StrictMode -Version Latest
+
+        Describe "A thing" {
+            BeforeEach {
+                Initialize-PesterLogger -Dir $Dir
+            }
+
+            It "handles <Value> and <Missing>" -TestCases @(
+                @{ Value = 1 }
+            ) {
+                Write-Host "bar"
+            }
+        }
+    "#));
+
+    assert!(errors.only(Lint::InvalidTestnameCharacters).is_empty());
+    assert_eq!(errors.only(Lint::UnknownTestcasePlaceholders).len(), 1);
+    errors.assert_contains(Lint::UnknownTestcasePlaceholders, "`Missing`");
 }
 
 #[test]
@@ -128,3 +444,1312 @@ fn test_complains_if_function_usage_has_different_cases_than_declaration() {
     "#));
     assert_eq!(errors.len(), 1);
 }
+
+#[test]
+fn test_analysis_continues_after_syntax_error() {
+    let errors = test_file(Contents(r#"
+        Set-StrictMode -Version Latest
+
+        function Get-Broken {
+            Write-Host ( ]
+        }
+
+        Write-Foo
+    "#));
+
+    assert_eq!(errors.lints(), [Lint::SyntaxErrors, Lint::UnknownFunctions]);
+}
+
+#[test]
+fn reports_files_with_unknown_encoding_and_continues() {
+    use std::fs;
+
+    let dir = setup_dir(&[
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Utf16.ps1\nGet-Foo\nGet-Missing\n"),
+    ]).unwrap();
+
+    let utf16: Vec<u8> = "\u{feff}function Get-Foo { }\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    fs::write(dir.path().join("Utf16.ps1"), utf16).unwrap();
+    fs::write(dir.path().join("Latin1.ps1"), b"Write-Host 'Za\xbf\xf3\xb3\xe6'\n").unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::SyntaxErrors, Lint::UnknownFunctions]);
+    assert_eq!(errors.in_file("Latin1.ps1").len(), 1);
+    errors.assert_contains(Lint::UnknownFunctions, "Get-Missing");
+}
+
+#[test]
+fn test_functions_defined_in_pester_blocks_are_scoped() {
+    let errors = test_file(Contents(r#"
+        Set-StrictMode -Version Latest
+
+        Describe "things" {
+            BeforeAll {
+                function Get-Shared {}
+            }
+
+            It "defines" {
+                function Get-Local {}
+                Get-Local
+                Get-Shared
+            }
+
+            It "uses" {
+                Get-Local
+            }
+        }
+    "#));
+
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    errors.assert_contains(Lint::UnknownFunctions, "not in scope");
+}
+
+#[test]
+fn aliases_resolve_to_their_targets() {
+    let dir = setup_dir(&[
+        ("Deployment.ps1", "function Invoke-Deployment {}\n"),
+        ("Aliases.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Deployment.ps1\n",
+            "Set-Alias Deploy Invoke-Deployment\n",
+        )),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Aliases.ps1\n",
+            "Deploy\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(errors.only(Lint::UnknownFunctions).is_empty());
+    assert!(errors.only(Lint::UnusedImports).is_empty());
+}
+
+#[test]
+fn alias_definitions_are_imported_like_functions() {
+    let dir = setup_dir(&[
+        ("Aliases.ps1", "Set-StrictMode -Version Latest\nNew-Alias -Name ll -Value Get-ChildItem\nsal ship Get-Date\n"),
+        ("Uses.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Aliases.ps1\nship\n"),
+        ("Unused.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Aliases.ps1\nGet-Date\n"),
+        ("Indirect.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Uses.ps1\nll\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(errors.only(Lint::UnknownFunctions).is_empty());
+    assert!(errors.in_file("Uses.ps1").is_empty());
+    assert_eq!(errors.in_file("Unused.ps1")[0].lint, Lint::UnusedImports);
+    assert_eq!(errors.in_file("Indirect.ps1")[0].lint, Lint::IndirectImports);
+}
+
+#[test]
+fn test_functions_defined_through_function_drive() {
+    let errors = test_file(Contents(r#"
+        Set-StrictMode -Version Latest
+
+        ${function:Invoke-Dispatch} = { param($Command) & $Command }
+        Set-Item function:Invoke-Other { Invoke-Dispatch Get-Date }
+
+        Invoke-Other
+    "#));
+
+    assert!(errors.is_empty(), "{:#?}", errors.emitted_items);
+}
+
+#[test]
+fn checks_reads_of_env_variables() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "required_env = [\"BUILD_ID\"]\n"),
+        ("Build.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "Write-Host $env:BUILD_ID $env:Path\n",
+            "if ($env:BRANCH) { Write-Host $env:BRANCH }\n",
+            "$env:MODE = \"release\"\n",
+            "Write-Host $env:MODE $env:ARTIFACTS_DIR $env:ARTIFACTS_DIR\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UncheckedEnvVariables]);
+    errors.assert_contains(Lint::UncheckedEnvVariables, "may not be set");
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 5);
+}
+
+#[test]
+fn reports_todo_comments() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\ntodo-comments = \"warn\"\n\n[todos]\nmarkers = [\"TODO\", \"XXX\"]\n"),
+        ("Build.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "# TODO: handle retries\n",
+            "Write-Host \"TODO not in a comment\"\n",
+            "<#\n",
+            "  Long comment. XXX\n",
+            "#>\n",
+            "# FIXME is not a marker here\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::TodoComments, Lint::TodoComments]);
+    errors.assert_contains(Lint::TodoComments, "handle retries");
+
+    let lines: Vec<_> = errors.emitted_items
+        .iter()
+        .map(|item| item.location.span.unwrap().start.line)
+        .collect();
+    assert_eq!(lines, [2, 5]);
+}
+
+#[test]
+fn reports_long_lines() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\nlong-lines = \"warn\"\n\n[lint-options.long-lines]\nmax = 30\n"),
+        ("Build.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "Write-Host \"This line is longer than thirty characters\"\n",
+            "$help = @\"\n",
+            "A here-string body longer than thirty characters\n",
+            "\"@\n",
+            "# See https://example.com/a/very/long/url/to/some/docs\n",
+            "Write-Host 'żółć żółć żółć'\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::LongLines]);
+    errors.assert_contains(Lint::LongLines, "line too long");
+
+    let span = errors.emitted_items[0].location.span.unwrap();
+    assert_eq!((span.start.line, span.start.col), (2, 31));
+
+    // The deprecated top-level key still works
+    std::fs::write(dir.path().join("shelly.toml"), "max_line_length = 30\n\n[levels]\nlong-lines = \"warn\"\n").unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::LongLines]);
+}
+
+#[test]
+fn shows_where_config_values_come_from() {
+    use shelly::Source;
+
+    let dir = setup_dir(&[
+        ("shelly.toml", "max_line_length = 100\n\n[levels]\nunused-imports = \"allow\"\n"),
+    ]).unwrap();
+
+    let run_opt = shelly::RunOpt {
+        lint_overrides: ::std::iter::once((Lint::UnknownFunctions, shelly::lint::Level::Warn)).collect(),
+        ..Default::default()
+    };
+
+    let values = shelly::effective_config(dir.path(), &run_opt).unwrap();
+    let find = |key: &str| {
+        let value = values.iter().find(|value| value.key == key).unwrap();
+        (&*value.value, value.source)
+    };
+
+    assert_eq!(find("levels.unused-imports"), ("allow", Source::ConfigFile));
+    assert_eq!(find("levels.unknown-functions"), ("warn", Source::CommandLine));
+    assert_eq!(find("levels.no-strict-mode"), ("warn", Source::Default));
+    assert_eq!(find("lint-options.long-lines.max"), ("100", Source::ConfigFile));
+    assert_eq!(find("max_per_lint"), ("unlimited", Source::Default));
+}
+
+#[test]
+fn traverses_files_in_sorted_order() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[traversal]\nskip_hidden = true\nmax_depth = 2\n"),
+        ("b/Z.ps1", "{"),
+        ("a.ps1", "{"),
+        ("B.ps1", "{"),
+        (".cache/Hidden.ps1", "{"),
+        ("a/b/c/Deep.ps1", "{"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let files: Vec<_> = errors.only(Lint::SyntaxErrors)
+        .iter()
+        .map(|item| item.location.file.strip_prefix(dir.path()).unwrap().to_owned())
+        .collect();
+
+    assert_eq!(files, [Path::new("B.ps1"), Path::new("a.ps1"), Path::new("b/Z.ps1")]);
+}
+
+#[test]
+fn skips_messages_from_baseline() {
+    let dir = setup_dir(&[
+        (shelly::baseline::FILE, r#"[
+            { "file": "Build.ps1", "lint": "unknown-functions", "message": "function not in scope", "line": "Old-Function" }
+        ]"#),
+        ("Build.ps1", "Set-StrictMode -Version Latest\n\nOld-Function\nNew-Function\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 4);
+}
+
+#[test]
+fn json_emitter_collects_messages() {
+    let dir = setup_dir(&[
+        ("Build.ps1", "Set-StrictMode -Version Latest\nGet-Foo\n"),
+    ]).unwrap();
+
+    let mut emitter = shelly::json::JsonEmitter::new(false);
+    shelly::run(dir.path(), Default::default(), &mut emitter).unwrap();
+
+    assert_eq!(emitter.messages.len(), 1);
+    let message = &emitter.messages[0];
+    assert_eq!(message.lint, "unknown-functions");
+    assert_eq!(message.kind, "error");
+    assert_eq!(message.category, "correctness");
+    assert!(message.file.ends_with("Build.ps1"));
+    assert_eq!(message.span.as_ref().unwrap().start.line, 2);
+}
+
+#[test]
+fn junit_report_has_a_testcase_per_file() {
+    let dir = setup_dir(&[
+        ("Clean.ps1", "Set-StrictMode -Version Latest\n"),
+        ("Broken.ps1", "Set-StrictMode -Version Latest\nGet-Foo <bar>\n"),
+        ("Worse.ps1", "Set-StrictMode -Version Latest\nGet-Foo\nGet-Bar\n"),
+    ]).unwrap();
+
+    let mut emitter = shelly::junit::JunitEmitter::new();
+    shelly::run(dir.path(), Default::default(), &mut emitter).unwrap();
+    let report = emitter.report();
+
+    assert!(report.contains("tests=\"3\" failures=\"2\""));
+    assert!(report.contains("Clean.ps1\"/>"));
+    assert!(report.contains("<failure message=\"1 error\""));
+    assert!(report.contains("<failure message=\"2 errors\""));
+    assert!(report.contains("error[unknown-functions] function not in scope"));
+}
+
+#[test]
+fn analyzes_only_selected_files() {
+    let dir = setup_dir(&[
+        ("Foo.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "function Get-Foo { Get-Missing }\n",
+        )),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Foo.ps1\n",
+            "Get-Foo\n",
+            "Get-Bar\n",
+        )),
+        ("Other.ps1", "Set-StrictMode -Version Latest\nGet-Other\n"),
+    ]).unwrap();
+
+    let run_opt = shelly::RunOpt {
+        files: vec![dir.path().join("Main.ps1")],
+        ..Default::default()
+    };
+
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+
+    // Get-Foo is resolved through the import, but Foo.ps1 itself isn't reported on
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert_eq!(errors.in_file("Main.ps1").len(), 1);
+}
+
+#[test]
+fn analyzes_selected_files_with_preludes() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[extras]\npreludes = [\"Profile.ps1\"]\n"),
+        ("Profile.ps1", "function Get-Profile { Get-Missing }\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\nGet-Profile\nGet-Bar\n"),
+    ]).unwrap();
+
+    let run_opt = shelly::RunOpt {
+        files: vec![dir.path().join("Main.ps1")],
+        ..Default::default()
+    };
+
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+
+    // The prelude is parsed as a dependency, so it's not reported on
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    errors.assert_contains(Lint::UnknownFunctions, "Get-Bar");
+}
+
+#[test]
+fn preludes_can_dot_source_project_files() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[extras]\npreludes = [\"Profile.ps1\"]\n"),
+        ("Profile.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Helpers.ps1\n",
+            "function Write-Log {}\n",
+            "Get-Helper\n",
+        )),
+        ("Helpers.ps1", "Set-StrictMode -Version Latest\nfunction Get-Helper { Write-Log }\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\nGet-Helper\nWrite-Log\nGet-Missing\n"),
+    ]).unwrap();
+
+    let errors = test_dir(dir.path());
+
+    // Helpers.ps1 sees functions of the prelude dot-sourcing it,
+    // without that being a recursive import
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    errors.assert_contains(Lint::UnknownFunctions, "Get-Missing");
+}
+
+#[test]
+fn skips_excluded_files() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[files]\nexclude = [\"**/vendor/**\"]\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/lib/vendor/Lib.ps1\n",
+            "Get-Lib\n",
+        )),
+        ("lib/vendor/Lib.ps1", "function Get-Lib { Get-Missing }\n"),
+        ("Legacy/Old.ps1", "Get-Old\n"),
+    ]).unwrap();
+
+    let run_opt = shelly::RunOpt {
+        exclude: vec!["Legacy/*".to_owned()],
+        ..Default::default()
+    };
+
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+
+    // Lib.ps1 is still parsed to resolve Get-Lib
+    assert!(errors.is_empty(), "{:?}", errors.lints());
+}
+
+#[test]
+fn nested_configs_apply_to_their_directories() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[extras]\ncmdlets = [\"Get-Root\"]\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\nGet-Root\nGet-Component\n"),
+        ("component/shelly.toml", concat!(
+            "[levels]\n",
+            "unknown-functions = \"warn\"\n",
+            "[extras]\n",
+            "cmdlets = [\"Get-Component\"]\n",
+            "[files]\n",
+            "exclude = [\"vendor/**\"]\n",
+        )),
+        ("component/Comp.ps1", "Set-StrictMode -Version Latest\nGet-Root\nGet-Component\nGet-Missing\n"),
+        ("component/vendor/Lib.ps1", "Get-Vendor\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions, Lint::UnknownFunctions]);
+
+    let main = errors.in_file("Main.ps1");
+    assert_eq!(main.len(), 1);
+    assert_eq!(main[0].kind, shelly::MessageKind::Error);
+
+    let component = errors.in_file("component/Comp.ps1");
+    assert_eq!(component.len(), 1);
+    assert_eq!(component[0].kind, shelly::MessageKind::Warning);
+    assert_eq!(component[0].location.span.unwrap().start.line, 4);
+}
+
+#[test]
+fn loads_bundled_modules() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[extras]\nmodules = [\"Az.Accounts\", \"ActiveDirectory\"]\n"),
+        ("Deploy.ps1", "Set-StrictMode -Version Latest\nConnect-AzAccount\nGet-ADUser foo\nGet-AzVM\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 4);
+
+    let dir = setup_dir(&[("shelly.toml", "[extras]\nmodules = [\"Az.Frobnicator\"]\n")]).unwrap();
+    let mut errors = shelly::VecEmitter::new();
+    assert!(shelly::run(dir.path(), Default::default(), &mut errors).is_err());
+}
+
+#[test]
+fn requires_modules_bring_bundled_commands_into_scope() {
+    let dir = setup_dir(&[
+        ("Deploy.ps1", "#Requires -Modules Az.Accounts, InternalTools\nSet-StrictMode -Version Latest\nConnect-AzAccount\nGet-AzVM\n"),
+        ("Other.ps1", "Set-StrictMode -Version Latest\nConnect-AzAccount\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions, Lint::UnknownFunctions]);
+    assert_eq!(errors.in_file("Deploy.ps1")[0].location.span.unwrap().start.line, 4);
+    assert_eq!(errors.in_file("Other.ps1").len(), 1);
+}
+
+#[test]
+fn checks_executables_against_allow_list() {
+    let script = ("Build.ps1", "Set-StrictMode -Version Latest\ngit status\nGit.exe log\nrobocopy.exe a b\n");
+
+    let dir = setup_dir(&[script]).unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 2);
+
+    let dir = setup_dir(&[script, ("shelly.toml", "[extras]\nexecutables = [\"git.exe\"]\n")]).unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownExecutables]);
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 4);
+    errors.assert_contains(Lint::UnknownExecutables, "not in `extras.executables`");
+}
+
+#[test]
+fn loads_builtins_files() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[extras]\nbuiltins_files = [\"cmdlets/exchange.txt\"]\n"),
+        ("cmdlets/exchange.txt", "# Exchange Online\nGet-Mailbox\nSet-Mailbox # used by Sync.ps1\n"),
+        ("Sync.ps1", "Set-StrictMode -Version Latest\nGet-Mailbox | Set-Mailbox\nGet-Missing\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    errors.assert_contains(Lint::UnknownFunctions, "not in scope");
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 3);
+
+    let dir = setup_dir(&[("shelly.toml", "[extras]\nbuiltins_files = [\"missing.txt\"]\n")]).unwrap();
+    let mut errors = shelly::VecEmitter::new();
+    assert!(shelly::run(dir.path(), Default::default(), &mut errors).is_err());
+}
+
+#[test]
+fn checks_builtins_of_target_version() {
+    let script = ("Stats.ps1", "Set-StrictMode -Version Latest\nGet-WmiObject Win32_OperatingSystem\nGet-Uptime\n");
+
+    let dir = setup_dir(&[script]).unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 3);
+
+    let dir = setup_dir(&[script, ("shelly.toml", "target = \"7.2\"\n")]).unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    let item = &errors.emitted_items[0];
+    assert_eq!(item.location.span.unwrap().start.line, 2);
+    assert_eq!(
+        item.notes[0].message,
+        "`Get-WmiObject` is not available in PowerShell 7.2, use `Get-CimInstance` instead",
+    );
+
+    let run_opt = shelly::RunOpt { ps_version: Some("5.1".to_owned()), ..Default::default() };
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert_eq!(errors.emitted_items[0].location.span.unwrap().start.line, 3);
+
+    let dir = setup_dir(&[script, ("shelly.toml", "target = \"seven\"\n")]).unwrap();
+    let mut errors = shelly::VecEmitter::new();
+    assert!(shelly::run(dir.path(), Default::default(), &mut errors).is_err());
+}
+
+#[test]
+fn reports_requirements_newer_than_target_version() {
+    let files = [
+        ("New.ps1", "#Requires -Version 7.2\nSet-StrictMode -Version Latest\n"),
+        ("Old.ps1", "#Requires -Version 5.1\nSet-StrictMode -Version Latest\n"),
+    ];
+
+    let dir = setup_dir(&files).unwrap();
+    assert!(shelly::testing::test_dir(dir.path()).is_empty());
+
+    let dir = setup_dir(&files).unwrap();
+    let run_opt = shelly::RunOpt { ps_version: Some("5.1".to_owned()), ..Default::default() };
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+    assert_eq!(errors.lints(), [Lint::IncompatibleVersion]);
+    assert!(errors.emitted_items[0].location.file.ends_with("New.ps1"));
+    errors.assert_contains(Lint::IncompatibleVersion, "requires PowerShell 7.2");
+    errors.assert_contains(Lint::IncompatibleVersion, "The target version is 5.1");
+}
+
+#[test]
+fn resolves_and_reports_aliases() {
+    let script = (
+        "Build.ps1",
+        "Set-StrictMode -Version Latest\nfunction ll { ls }\ngci | % { $_ } | ? { $_ } | select -First 1\nll\n",
+    );
+
+    let dir = setup_dir(&[script]).unwrap();
+    assert!(shelly::testing::test_dir(dir.path()).is_empty());
+
+    let config = ("shelly.toml", "[levels]\navoid-aliases = \"warn\"\n[lint-options.avoid-aliases]\nallowed = [\"%\"]\n");
+    let dir = setup_dir(&[script, config]).unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    let mut aliases: Vec<_> = errors.only(Lint::AvoidAliases).iter()
+        .map(|item| item.fix.as_ref().unwrap().edits[0].replacement.as_str())
+        .collect();
+    aliases.sort();
+    assert_eq!(aliases, ["Get-ChildItem", "Get-ChildItem", "Select-Object", "Where-Object"]);
+    errors.assert_contains(Lint::AvoidAliases, "`gci` is an alias of `Get-ChildItem`");
+}
+
+#[test]
+fn functions_named_like_aliases_are_resolved_to_definitions() {
+    let dir = setup_dir(&[
+        ("C.ps1", "function Fc { 1 }\n"),
+        ("B.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot\\C.ps1\nFc\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(errors.is_empty(), "{:#?}", errors.emitted_items);
+}
+
+#[test]
+fn skips_configured_paths() {
+    let files = [
+        ("Archive/Old.ps1", "Get-Old\n"),
+        ("_Old_Tests/Old.ps1", "Get-Old\n"),
+    ];
+
+    let dir = setup_dir(&files).unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(!errors.in_file("Archive/Old.ps1").is_empty());
+    assert!(errors.in_file("_Old_Tests/Old.ps1").is_empty());
+
+    let mut files = files.to_vec();
+    files.push(("shelly.toml", "[traversal]\nskip = [\"Archive\"]\n"));
+    let dir = setup_dir(&files).unwrap();
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(errors.in_file("Archive/Old.ps1").is_empty());
+    assert!(!errors.in_file("_Old_Tests/Old.ps1").is_empty());
+}
+
+#[test]
+fn resolves_import_module_of_sibling_files() {
+    let dir = setup_dir(&[
+        ("Helpers.psm1", "function Get-Helper {}\n"),
+        ("Lib/Other.psm1", "function Get-Other {}\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "Import-Module $PSScriptRoot\\Helpers.psm1\n",
+            "Import-Module \"$PSScriptRoot/Lib/Other.psm1\" -Force\n",
+            ". $PSScriptRoot/Zz.ps1\n",
+            ". $PSScriptRoot/Aa.ps1\n",
+            "Get-Helper\n",
+            "Get-Zz\n",
+            "Get-Aa\n",
+        )),
+        ("Zz.ps1", "function Get-Zz {}\n"),
+        ("Aa.ps1", "function Get-Aa {}\n"),
+        ("Missing.ps1", "Set-StrictMode -Version Latest\nImport-Module $PSScriptRoot/Nope.psm1\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let lints: Vec<_> = errors.in_file("Main.ps1").iter().map(|item| item.lint).collect();
+    assert_eq!(lints, [Lint::UnusedImports]);
+    assert_eq!(errors.in_file("Main.ps1")[0].location.span.unwrap().start.line, 3);
+    assert_eq!(errors.in_file("Missing.ps1")[0].lint, Lint::NonexistingImports);
+}
+
+#[test]
+fn analyzes_only_configured_extensions() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[files]\nextensions = [\"ps1\"]\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\nImport-Module Utils\nGet-Util\n"),
+        ("Utils.psm1", "function Get-Util { Get-Missing }\n"),
+    ]).unwrap();
+
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), Default::default(), &mut errors).unwrap();
+
+    // Utils.psm1 is still parsed to resolve Get-Util
+    assert!(errors.is_empty(), "{:?}", errors.lints());
+
+    let dir = setup_dir(&[("shelly.toml", "[files]\nextensions = [\"ps2\"]\n")]).unwrap();
+
+    let mut errors = shelly::VecEmitter::new();
+    assert!(shelly::run(dir.path(), Default::default(), &mut errors).is_err());
+}
+
+#[test]
+fn unused_imports_and_letter_casing_can_be_fixed() {
+    let dir = setup_dir(&[
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Bar.ps1", "function Get-Bar {}\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Foo.ps1\n",
+            ". $PSScriptRoot/Bar.ps1 # bar\n",
+            "get-foo\n",
+        )),
+    ]).unwrap();
+
+    let mut emitter = shelly::json::JsonEmitter::new(true);
+    shelly::run(dir.path(), Default::default(), &mut emitter).unwrap();
+
+    let applied = shelly::json::apply_fixes(&emitter.messages, &[]).unwrap();
+    assert_eq!(applied.applied, 2);
+    assert_eq!(applied.to_string(), "2 fixes in 1 file");
+
+    let fixed = std::fs::read_to_string(dir.path().join("Main.ps1")).unwrap();
+    assert_eq!(fixed, "Set-StrictMode -Version Latest\n. $PSScriptRoot/Foo.ps1\nGet-Foo\n");
+}
+
+#[test]
+fn fixes_keep_the_utf16_encoding() {
+    use std::fs;
+
+    let dir = setup_dir(&[
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Foo.ps1\nget-foo\n"),
+    ]).unwrap();
+
+    let utf16le = |text: &str| -> Vec<u8> {
+        "\u{feff}".encode_utf16().chain(text.encode_utf16()).flat_map(u16::to_le_bytes).collect()
+    };
+    let source = "Set-StrictMode -Version Latest\n. $PSScriptRoot/Foo.ps1\nGET-FOO\n";
+    fs::write(dir.path().join("Utf16.ps1"), utf16le(source)).unwrap();
+
+    let mut emitter = shelly::json::JsonEmitter::new(true);
+    shelly::run(dir.path(), Default::default(), &mut emitter).unwrap();
+
+    let applied = shelly::json::apply_fixes(&emitter.messages, &[]).unwrap();
+    assert_eq!(applied.to_string(), "2 fixes in 2 files");
+
+    let fixed = fs::read(dir.path().join("Utf16.ps1")).unwrap();
+    assert_eq!(fixed, utf16le(&source.replace("GET-FOO", "Get-Foo")));
+    let fixed = fs::read_to_string(dir.path().join("Main.ps1")).unwrap();
+    assert!(fixed.ends_with("\nGet-Foo\n"));
+}
+
+#[test]
+fn fixes_can_be_previewed_as_diffs() {
+    let dir = setup_dir(&[
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Foo.ps1\nget-foo\n"),
+    ]).unwrap();
+
+    let mut emitter = shelly::json::JsonEmitter::new(true);
+    shelly::run(dir.path(), Default::default(), &mut emitter).unwrap();
+
+    let preview = shelly::json::preview_fixes(&emitter.messages, &[]).unwrap();
+    assert_eq!(preview.applied, 1);
+    assert_eq!(preview.files.len(), 1);
+    assert_eq!(preview.to_string(), "1 fix in 1 file");
+
+    let diff = preview.files[0].unified_diff();
+    assert!(diff.contains("@@ -1,3 +1,3 @@\n"));
+    assert!(diff.contains("\n-get-foo\n+Get-Foo\n"));
+
+    let source = std::fs::read_to_string(dir.path().join("Main.ps1")).unwrap();
+    assert!(source.ends_with("get-foo\n"));
+}
+
+#[test]
+fn loads_config_from_given_path() {
+    let dir = setup_dir(&[
+        ("ci/shelly.toml", "[levels]\nunknown-functions = \"allow\"\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\nGet-Foo\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+
+    let run_opt = shelly::RunOpt {
+        config: Some(dir.path().join("ci/shelly.toml")),
+        ..Default::default()
+    };
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn fails_on_too_many_warnings() {
+    use std::process::Command;
+
+    let dir = setup_dir(&[
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Bar.ps1", "function Get-Bar {}\n"),
+        ("Main.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Bar.ps1\n. $PSScriptRoot/Foo.ps1\n"),
+    ]).unwrap();
+
+    let summary = shelly::run(dir.path(), Default::default(), &mut shelly::VecEmitter::new()).unwrap();
+    assert_eq!((summary.errors, summary.warnings, summary.files), (0, 2, 3));
+    assert_eq!(summary.to_string(), "0 errors, 2 warnings across 3 files (unused-imports: 2)");
+
+    let status = |args: &[&str]| Command::new(shelly_binary())
+        .arg("analyze")
+        .args(args)
+        .current_dir(dir.path())
+        .output()
+        .unwrap()
+        .status;
+
+    assert!(status(&[]).success());
+    assert!(status(&["--max-warnings", "2"]).success());
+    assert_eq!(status(&["--max-warnings", "1"]).code(), Some(1));
+    assert_eq!(status(&["--deny-warnings"]).code(), Some(1));
+}
+
+#[test]
+fn messages_can_be_triaged_interactively() {
+    use std::fs;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let dir = setup_dir(&[
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Bar.ps1", "function Get-Bar {}\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Foo.ps1\n",
+            ". $PSScriptRoot/Bar.ps1\n",
+            "get-foo\n",
+            "Get-Missing\n",
+        )),
+    ]).unwrap();
+
+    let mut child = Command::new(shelly_binary())
+        .args(["analyze", "--interactive"])
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Fix the letter casing, baseline the unknown function (after
+    // an invalid answer) and allow the unused import with a reason
+    child.stdin.take().unwrap().write_all(b"f\nx\nb\na\nlegacy\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("[1/3]") && stdout.contains("[3/3]"), "{}", stdout);
+    assert!(stdout.contains("Applied 1 fix, added 1 allow comment and 1 baseline entry"), "{}", stdout);
+
+    let main = fs::read_to_string(dir.path().join("Main.ps1")).unwrap();
+    assert!(main.contains(". $PSScriptRoot/Bar.ps1 # allow unused-imports -- legacy\nGet-Foo\n"), "{}", main);
+
+    let errors = test_dir(dir.path());
+    assert_eq!(errors.len(), 0);
+}
+
+#[test]
+fn using_module_brings_classes_into_scope() {
+    let dir = setup_dir(&[
+        ("Classes.psm1", concat!(
+            "enum Color {\n",
+            "    Red\n",
+            "    Green\n",
+            "}\n",
+            "class Boat {\n",
+            "    [Color] $Color\n",
+            "}\n",
+        )),
+        ("Main.ps1", concat!(
+            "using module ./Classes.psm1\n",
+            "Set-StrictMode -Version Latest\n",
+            "function Get-Boat([Color] $Color) {\n",
+            "    $boat = [Boat]::new()\n",
+            "    $boat.Color = $Color\n",
+            "    $boat\n",
+            "}\n",
+        )),
+        ("Missing.ps1", "using module ./Nope.psm1\nSet-StrictMode -Version Latest\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(errors.in_file("Main.ps1").is_empty(), "{:?}", errors.lints());
+    assert!(errors.in_file("Classes.psm1").is_empty(), "{:?}", errors.lints());
+    assert_eq!(errors.in_file("Missing.ps1")[0].lint, Lint::NonexistingImports);
+}
+
+#[test]
+fn respects_export_module_member() {
+    let dir = setup_dir(&[
+        ("Utils.psm1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "function Get-Public { Get-Helper }\n",
+            "function Get-Helper {}\n",
+            "function Get-Unused {}\n",
+            "function Set-Public {}\n",
+            "Export-ModuleMember -Function Get-Public, Set-*\n",
+        )),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "Import-Module Utils\n",
+            "Get-Public\n",
+            "Set-Public\n",
+            "Get-Helper\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+
+    let main = errors.in_file("Main.ps1");
+    assert_eq!(main.len(), 1, "{:?}", errors.lints());
+    assert_eq!(main[0].lint, Lint::UnknownFunctions);
+    assert_eq!(main[0].location.span.unwrap().start.line, 5);
+
+    let utils = errors.in_file("Utils.psm1");
+    assert_eq!(utils.len(), 1, "{:?}", errors.lints());
+    assert_eq!(utils[0].lint, Lint::UnusedPrivateFunctions);
+    assert_eq!(utils[0].location.span.unwrap().start.line, 4);
+}
+
+#[test]
+fn checks_functions_and_aliases_exported_by_manifest() {
+    let dir = setup_dir(&[
+        ("Utils/Utils.psd1", concat!(
+            "@{\n",
+            "    RootModule = 'Utils.psm1'\n",
+            "    FunctionsToExport = @('Get-Util', 'Get-Gone')\n",
+            "    AliasesToExport = 'gu'\n",
+            "}\n",
+        )),
+        ("Utils/Utils.psm1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "function Get-Util {}\n",
+            "function Get-Private {}\n",
+            "Set-Alias gu Get-Util\n",
+        )),
+        ("Binary/Binary.psd1", "@{ RootModule = 'Binary.dll'; FunctionsToExport = @('Get-Binary') }\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            "Import-Module Utils\n",
+            "Get-Util\n",
+            "gu\n",
+            "Get-Private\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+
+    let main = errors.in_file("Main.ps1");
+    assert_eq!(main.len(), 1, "{:?}", errors.lints());
+    assert_eq!(main[0].lint, Lint::UnknownFunctions);
+    assert_eq!(main[0].location.span.unwrap().start.line, 5);
+
+    let manifest = errors.in_file("Utils/Utils.psd1");
+    assert_eq!(manifest.len(), 1, "{:?}", errors.lints());
+    assert_eq!(manifest[0].lint, Lint::NonexistingExports);
+    assert_eq!(manifest[0].location.span.unwrap().start.line, 3);
+
+    assert!(errors.in_file("Binary/Binary.psd1").is_empty());
+}
+
+#[test]
+fn resolves_computed_dot_imports() {
+    let dir = setup_dir(&[
+        ("lib/Utils.ps1", "function Get-Util {}\n"),
+        ("Other.ps1", "function Get-Other {}\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". (Join-Path $PSScriptRoot \"lib\\Utils.ps1\")\n",
+            ". \"$(Split-Path -Parent $PSCommandPath)\\Other.ps1\"\n",
+            "Get-Util\n",
+            "Get-Other\n",
+        )),
+        ("Main.Tests.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSCommandPath.Replace('.Tests.ps1', '.ps1')\n",
+            "Get-Other\n",
+        )),
+        ("Missing.ps1", "Set-StrictMode -Version Latest\n. (Join-Path $PSScriptRoot Nope.ps1)\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(errors.in_file("Main.ps1").is_empty(), "{:?}", errors.lints());
+    assert_eq!(errors.in_file("Missing.ps1")[0].lint, Lint::NonexistingImports);
+
+    // Get-Other comes indirectly through Main.ps1
+    let tests: Vec<_> = errors.in_file("Main.Tests.ps1").iter().map(|item| item.lint).collect();
+    assert_eq!(tests, [Lint::IndirectImports]);
+}
+
+#[test]
+fn resolves_imports_relative_to_env_roots() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "required_env = []\n[imports]\nenv_roots = { BUILD_ROOT = \"build\" }\n"),
+        ("build/common.ps1", "function Get-Common {}\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". \"$env:BUILD_ROOT\\common.ps1\"\n",
+            "Get-Common\n",
+        )),
+        ("Other.ps1", "Set-StrictMode -Version Latest\n. $env:OTHER_ROOT/common.ps1\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    assert!(errors.in_file("Main.ps1").is_empty(), "{:?}", errors.lints());
+
+    // Only the mapped variables are assumed to be set
+    let mut other: Vec<_> = errors.in_file("Other.ps1").iter().map(|item| item.lint).collect();
+    other.sort();
+    assert_eq!(other, [Lint::UnrecognizedImports, Lint::UncheckedEnvVariables]);
+}
+
+#[test]
+fn reports_imports_after_the_first_statement() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\nimports-not-first = \"warn\"\n"),
+        ("Foo.ps1", "function Get-Foo {}\n"),
+        ("Bar.ps1", "function Get-Bar {}\n"),
+        ("Main.ps1", concat!(
+            "# Main script\n",
+            "Set-StrictMode -Version Latest\n",
+            ". (Join-Path $PSScriptRoot Foo.ps1)\n",
+            "Write-Output 'Starting'\n",
+            ". $PSScriptRoot/Bar.ps1\n",
+            "Get-Foo\n",
+            "Get-Bar\n",
+        )),
+        ("Main.Tests.ps1", concat!(
+            "BeforeAll {\n",
+            "    . $PSScriptRoot/Foo.ps1\n",
+            "}\n",
+            "Describe 'Get-Foo' { It 'works' { Get-Foo } }\n",
+        )),
+        ("Bar.Tests.ps1", concat!(
+            "Describe 'Get-Bar' {\n",
+            "    BeforeAll {\n",
+            "        Write-Output 'Loading'\n",
+            "        . $PSScriptRoot/Bar.ps1\n",
+            "    }\n",
+            "    It 'works' { Get-Bar }\n",
+            "}\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let late = errors.only(Lint::ImportsNotFirst);
+    assert_eq!(late.len(), 1, "{:?}", errors.lints());
+    assert!(late[0].location.file.ends_with("Main.ps1"));
+    assert_eq!(late[0].location.span.unwrap().start.line, 5);
+}
+
+#[test]
+fn reports_recursive_imports_and_continues() {
+    let dir = setup_dir(&[
+        ("A.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/B.ps1\nfunction Get-A { Get-B }\n"),
+        ("B.ps1", ". $PSScriptRoot/C.ps1\nfunction Get-B { Get-C }\n"),
+        ("C.ps1", ". $PSScriptRoot/A.ps1\nfunction Get-C { Get-A }\n"),
+        ("Other.ps1", "Set-StrictMode -Version Latest\nGet-Missing\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+
+    let recursive = errors.only(Lint::RecursiveImports);
+    assert_eq!(recursive.len(), 1, "{:?}", errors.lints());
+    assert!(recursive[0].location.file.ends_with("C.ps1"));
+    assert!(recursive[0].notes[0].message.starts_with("3 files form the cycle"));
+    assert!(recursive[0].notes[1].message.ends_with("B.ps1"));
+    assert!(recursive[0].notes[2].message.ends_with("C.ps1"));
+    assert!(recursive[0].notes[1].location.as_ref().unwrap().file.ends_with("A.ps1"));
+
+    // The cycle is broken at the reported import
+    let lints: Vec<_> = errors.in_file("C.ps1").iter().map(|item| item.lint).collect();
+    assert_eq!(lints, [Lint::RecursiveImports, Lint::UnknownFunctions]);
+    assert!(errors.in_file("A.ps1").is_empty());
+
+    // The rest of the files is still analyzed
+    assert_eq!(errors.in_file("Other.ps1")[0].lint, Lint::UnknownFunctions);
+}
+
+#[test]
+fn analyzes_files_with_nonexisting_imports() {
+    let dir = setup_dir(&[
+        ("Lib.ps1", ". $PSScriptRoot/Nope.ps1\nfunction Get-Lib { Get-Nope }\n"),
+        ("Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/Lib.ps1\n",
+            "Get-Lib\n",
+            "Get-Nope\n",
+        )),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+
+    let lib: Vec<_> = errors.in_file("Lib.ps1").iter().map(|item| item.lint).collect();
+    assert_eq!(lib, [Lint::NonexistingImports, Lint::UnknownFunctions]);
+
+    // Get-Lib is still found, and Get-Nope points to the root cause
+    let main = errors.in_file("Main.ps1");
+    assert_eq!(main.len(), 1, "{:?}", errors.lints());
+    assert_eq!(main[0].lint, Lint::UnknownFunctions);
+    assert_eq!(main[0].location.span.unwrap().start.line, 4);
+    let root_cause = main[0].notes[0].location.as_ref().unwrap();
+    assert!(root_cause.file.ends_with("Lib.ps1"));
+}
+
+#[test]
+fn parses_imports_from_external_roots() {
+    let dir = setup_dir(&[
+        ("Shared/Utils.ps1", "function Get-Shared { Get-Missing }\n"),
+        ("Shared/Tools/Tools.psm1", "function Get-Tool {}\n"),
+        ("Project/shelly.toml", "[paths]\nexternal_roots = [\"../Shared\"]\n"),
+        ("Project/Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $PSScriptRoot/../Shared/Utils.ps1\n",
+            "Import-Module Tools\n",
+            "Get-Shared\n",
+            "Get-Tool\n",
+        )),
+    ]).unwrap();
+
+    // Nothing is reported for the external files
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path().join("Project"), Default::default(), &mut errors).unwrap();
+    assert!(errors.is_empty(), "{:?}", errors.lints());
+
+    let dir = setup_dir(&[("shelly.toml", "[paths]\nexternal_roots = [\"../Nope\"]\n")]).unwrap();
+    assert!(shelly::run(dir.path(), Default::default(), &mut shelly::VecEmitter::new()).is_err());
+}
+
+#[test]
+fn paths_in_given_config_are_relative_to_it() {
+    let dir = setup_dir(&[
+        ("Shared/Tools/Tools.psm1", "function Get-Tool {}\n"),
+        ("Project/build/common.ps1", "function Get-Common {}\n"),
+        ("Config/ci/cmdlets.txt", "New-Frobnicator\n"),
+        ("Config/ci/shelly.toml", concat!(
+            "[paths]\nexternal_roots = [\"../../Shared\"]\n\n",
+            "[extras]\nbuiltins_files = [\"cmdlets.txt\"]\n\n",
+            "[imports]\nenv_roots = { BUILD_ROOT = \"../../Project/build\" }\n",
+        )),
+        ("Project/Main.ps1", concat!(
+            "Set-StrictMode -Version Latest\n",
+            ". $env:BUILD_ROOT/common.ps1\n",
+            "Import-Module Tools\n",
+            "Get-Common\n",
+            "Get-Tool\n",
+            "New-Frobnicator\n",
+        )),
+    ]).unwrap();
+
+    let run_opt = shelly::RunOpt {
+        config: Some(dir.path().join("Config/ci/shelly.toml")),
+        ..Default::default()
+    };
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path().join("Project"), run_opt, &mut errors).unwrap();
+    assert!(errors.is_empty(), "{:?}", errors.emitted_items);
+}
+
+#[test]
+fn explains_why_a_function_is_in_scope() {
+    let dir = setup_dir(&[
+        ("A.Tests.ps1", ". $PSScriptRoot/B.ps1\nNew-Widget\n"),
+        ("B.ps1", "\n. $PSScriptRoot/C.ps1\n"),
+        ("C.ps1", "function New-Widget {}\n"),
+    ]).unwrap();
+    let why = |function| {
+        shelly::why(dir.path(), Default::default(), &dir.path().join("A.Tests.ps1"), function).unwrap()
+    };
+
+    match why("new-widget") {
+        shelly::Origin::Defined { chain, imports, definition } => {
+            let names: Vec<_> = chain.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+            assert_eq!(names, ["A.Tests.ps1", "B.ps1", "C.ps1"]);
+            let lines: Vec<_> = imports.iter().map(|import| import.as_ref().unwrap().span.unwrap().start.line).collect();
+            assert_eq!(lines, [1, 2]);
+            assert!(definition.file.ends_with("C.ps1"));
+        }
+        origin => panic!("unexpected {:?}", origin),
+    }
+
+    assert!(matches!(why("Get-ChildItem"), shelly::Origin::Assumed(_)));
+    assert!(matches!(why("Get-Nothing"), shelly::Origin::NotInScope));
+}
+
+#[test]
+fn explains_functions_coming_from_preludes() {
+    use std::process::Command;
+
+    let dir = setup_dir(&[
+        ("shelly.toml", "[extras]\npreludes = [\"Profile.ps1\"]\n"),
+        ("Profile.ps1", "\nfunction Get-Profile {}\n"),
+        ("Main.ps1", "Get-Profile\n"),
+    ]).unwrap();
+
+    match shelly::why(dir.path(), Default::default(), &dir.path().join("Main.ps1"), "Get-Profile").unwrap() {
+        shelly::Origin::Defined { chain, imports, definition } => {
+            assert_eq!(chain.len(), 2);
+            assert!(imports.len() == 1 && imports[0].is_none());
+            assert_eq!(definition.span.unwrap().start.line, 2);
+        }
+        origin => panic!("unexpected {:?}", origin),
+    }
+
+    let output = Command::new(shelly_binary())
+        .args(["why", "--file", "Main.ps1", "--function", "Get-Profile"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let output = ::std::str::from_utf8(&output.stdout).unwrap();
+    assert!(output.contains("Main.ps1: prelude from `extras.preludes`\n"), "{}", output);
+    assert!(output.contains("Profile.ps1:2: function Get-Profile {}\n"), "{}", output);
+}
+
+#[test]
+fn reports_scripts_not_imported_by_any_file() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\nunused-files = \"warn\"\n\n[entrypoints]\npaths = [\"Build.ps1\", \"tools/*.ps1\"]\n"),
+        ("Build.ps1", ". $PSScriptRoot/Utils.ps1\nGet-Util\n"),
+        ("Utils.ps1", "function Get-Util {}\n"),
+        ("Utils.Tests.ps1", ". $PSScriptRoot/Utils.ps1\n"),
+        ("tools/Deploy.ps1", "Get-ChildItem\n"),
+        ("Old.ps1", "function Get-Old {}\n"),
+        ("Module.psm1", "function Get-Module {}\n"),
+    ]).unwrap();
+
+    let errors = test_dir(dir.path());
+    let unused = errors.only(Lint::UnusedFiles);
+    assert_eq!(unused.len(), 1, "{:?}", unused);
+    assert!(unused[0].location.file.ends_with("Old.ps1"));
+}
+
+#[test]
+fn only_entrypoints_are_required_to_enable_strict_mode() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[entrypoints]\npaths = [\"Build.ps1\", \"Run.ps1\"]\n\n[strictness]\nscope = \"all\"\n"),
+        ("Build.ps1", ". $PSScriptRoot/Utils.ps1\nGet-Util\n"),
+        ("Run.ps1", "Set-StrictMode -Version Latest\n. $PSScriptRoot/Other.ps1\nGet-Other\n"),
+        ("Utils.ps1", "function Get-Util {}\n"),
+        ("Other.ps1", "function Get-Other {}\n"),
+        ("Orphan.ps1", ". $PSScriptRoot/Dead.ps1\nGet-Dead\n"),
+        ("Dead.ps1", "function Get-Dead {}\n"),
+    ]).unwrap();
+
+    let errors = test_dir(dir.path());
+    let files: Vec<_> = errors.only(Lint::NoStrictMode)
+        .iter()
+        .map(|item| item.location.file.file_name().unwrap().to_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(files, ["Build.ps1", "Dead.ps1", "Orphan.ps1", "Utils.ps1"]);
+
+    // Files not imported by entry points are reported by `unused-files` when it's enabled
+    let run_opt = shelly::RunOpt {
+        lint_overrides: Some((Lint::UnusedFiles, shelly::lint::Level::Warn)).into_iter().collect(),
+        ..Default::default()
+    };
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+    let files: Vec<_> = errors.emitted_items
+        .iter()
+        .map(|item| (item.lint, item.location.file.file_name().unwrap().to_str().unwrap().to_owned()))
+        .collect();
+    assert_eq!(files, [
+        (Lint::NoStrictMode, "Build.ps1".to_owned()),
+        (Lint::NoStrictMode, "Utils.ps1".to_owned()),
+        (Lint::UnusedFiles, "Orphan.ps1".to_owned()),
+    ]);
+}
+
+#[test]
+fn analyzes_given_directories_together() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\nno-strict-mode = \"allow\"\n"),
+        ("src/Main.ps1", ". $PSScriptRoot/../tools/Tools.ps1\nGet-Tool\nGet-Missing\n"),
+        ("tools/Tools.ps1", "function Get-Tool {}\n"),
+        ("tools/nested/shelly.toml", "[levels]\nunknown-functions = \"allow\"\n"),
+        ("tools/nested/Other.ps1", "Get-Nothing\n"),
+        ("huge/Unrelated.ps1", "Get-Unrelated\n"),
+    ]).unwrap();
+
+    let run_opt = shelly::RunOpt {
+        dirs: vec![dir.path().join("src"), dir.path().join("tools"), dir.path().join("tools/nested")],
+        ..Default::default()
+    };
+    let mut errors = shelly::VecEmitter::new();
+    let summary = shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+
+    assert_eq!(summary.files, 3);
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+    assert!(errors.emitted_items[0].location.file.ends_with("Main.ps1"));
+
+    let run_opt = shelly::RunOpt { dirs: vec![std::env::temp_dir()], ..Default::default() };
+    assert!(shelly::run(dir.path(), run_opt, &mut shelly::VecEmitter::new()).is_err());
+}
+
+#[test]
+fn parses_imports_from_outside_of_given_directories() {
+    let dir = setup_dir(&[
+        ("shelly.toml", "[levels]\nno-strict-mode = \"allow\"\nunused-files = \"warn\"\n"),
+        ("src/Main.ps1", ". $PSScriptRoot/../lib/Lib.ps1\n. $PSScriptRoot/Helper.ps1\nGet-Lib\nGet-Helper\n"),
+        ("src/Helper.ps1", "function Get-Helper {}\n"),
+        ("lib/Lib.ps1", "function Get-Lib {}\nGet-Missing\n"),
+        ("tests/Uses.ps1", ". $PSScriptRoot/../src/Helper.ps1\nGet-Helper\n"),
+    ]).unwrap();
+
+    // Lib.ps1 is parsed as a dependency, but not reported on
+    let run_opt = shelly::RunOpt { dirs: vec![dir.path().join("src")], ..Default::default() };
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+    assert!(errors.is_empty(), "{:?}", errors.emitted_items);
+
+    // Importers outside of the given directories are not known,
+    // so no file is reported as unused
+    let run_opt = shelly::RunOpt {
+        dirs: vec![dir.path().join("src"), dir.path().join("lib")],
+        ..Default::default()
+    };
+    let mut errors = shelly::VecEmitter::new();
+    shelly::run(dir.path(), run_opt, &mut errors).unwrap();
+    assert_eq!(errors.lints(), [Lint::UnknownFunctions]);
+}
+
+#[test]
+fn reports_every_import_cycle_once() {
+    let dir = setup_dir(&[
+        ("A.ps1", ". $PSScriptRoot/B.ps1\n"),
+        ("B.ps1", ". $PSScriptRoot/A.ps1\n. $PSScriptRoot/C.ps1\n"),
+        ("C.ps1", ". $PSScriptRoot/B.ps1\n"),
+        ("D.ps1", ". $PSScriptRoot/D.ps1\n"),
+        ("E.ps1", ". $PSScriptRoot/F.ps1\n"),
+        ("F.ps1", ". $PSScriptRoot/E.ps1\n"),
+    ]).unwrap();
+
+    let errors = shelly::testing::test_dir(dir.path());
+    let recursive = errors.only(Lint::RecursiveImports);
+    assert_eq!(recursive.len(), 3, "{:?}", recursive);
+
+    // A, B and C form a single cycle, broken at two of its imports
+    assert!(recursive[0].notes[0].message.starts_with("3 files form the cycle"));
+    let notes: Vec<_> = recursive[0].notes.iter()
+        .map(|note| &*note.message)
+        .filter(|note| note.starts_with("...which imports"))
+        .collect();
+    assert_eq!(notes.len(), 3, "{:?}", notes);
+    assert_eq!(notes.iter().filter(|note| note.ends_with("(also ignored)")).count(), 1);
+
+    assert!(recursive[1].location.file.ends_with("D.ps1"));
+    assert_eq!(recursive[1].notes[0].message, "The file imports itself");
+    assert!(recursive[2].location.file.ends_with("F.ps1"));
+}
+
+#[test]
+fn mocks_are_usages_and_replace_missing_functions() {
+    let dir = setup_dir(&[
+        ("Tools.ps1", "function Send-Mail {}\n"),
+        ("Get-Report.ps1", "function Get-Report { Get-Data }\n"),
+        ("Get-Report.Tests.ps1", concat!(
+            ". $PSScriptRoot/Get-Report.ps1\n",
+            ". $PSScriptRoot/Tools.ps1\n",
+            "Describe 'Get-Report' {\n",
+            "    BeforeAll {\n",
+            "        Mock Send-Mail {}\n",
+            "        Mock Get-Data { 42 }\n",
+            "    }\n",
+            "    It 'works' { Get-Report; Get-Data }\n",
+            "}\n",
+            "Describe 'Other' {\n",
+            "    It 'fails' { Get-Data }\n",
+            "}\n",
+        )),
+    ]).unwrap();
+
+    let errors = test_dir(dir.path());
+    let lints: Vec<_> = errors.in_file("Get-Report.Tests.ps1").iter().map(|item| item.lint).collect();
+
+    // Tools.ps1 is used by the mock of `Send-Mail` and
+    // `Get-Data` is mocked only in the first `Describe`
+    assert_eq!(lints, [Lint::UnknownFunctions, Lint::NoStrictMode]);
+    assert_eq!(errors.only(Lint::UnknownFunctions)[0].location.span.unwrap().start.line, 11);
+}
@@ -0,0 +1,3 @@
+Set-StrictMode -Version Latest
+
+Invoke-Build
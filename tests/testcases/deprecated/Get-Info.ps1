@@ -0,0 +1,7 @@
+Set-StrictMode -Version Latest
+
+function Write-Logg {}
+function Write-Log {}
+
+Get-WmiObject Win32_OperatingSystem
+Write-Logg "done"
@@ -0,0 +1 @@
+Set-StrictMode -Version Latest
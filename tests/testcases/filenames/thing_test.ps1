@@ -0,0 +1,5 @@
+Set-StrictMode -Version Latest
+
+Describe "thing" {
+    It "works" {}
+}
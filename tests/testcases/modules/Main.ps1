@@ -0,0 +1,7 @@
+Set-StrictMode -Version Latest
+
+Import-Module Utils
+Import-Module Pester
+
+Get-Util
+Get-Private
@@ -0,0 +1,13 @@
+Set-StrictMode -Version Latest
+
+task Compile {
+    Write-Host "compiling"
+}
+
+Register-Step -Name Publish-Artifacts {
+    Write-Host "publishing"
+}
+
+Compile
+Publish-Artifacts
+Deploy-Everything